@@ -18,12 +18,16 @@
 struct MaterialSettings {
     flip: u32,
     angle: f32,
+    // lid lighting level, 0 is fully lit
+    lighting: u32,
 }
 
 @group(2) @binding(100)
 var<uniform> material: MaterialSettings ;
 
 const TAU:f32 =  6.28318530718;
+// brightness lost per lighting level
+const LIGHTING_STEP: f32 = 0.2;
 
 /// Clockwise by `theta`
 fn rotate2D(theta: f32) -> mat2x2<f32> {
@@ -51,6 +55,8 @@ fn fragment(
     modified_input.uv = uvs;
 
     var pbr_input = pbr_input_from_standard_material(modified_input, is_front);
+    let brightness = 1.0 - LIGHTING_STEP * f32(material.lighting);
+    pbr_input.material.base_color = vec4(pbr_input.material.base_color.rgb * brightness, pbr_input.material.base_color.a);
 
 #ifdef PREPASS_PIPELINE
     // in deferred mode we can't modify anything after that, as lighting is run in a separate fullscreen shader.
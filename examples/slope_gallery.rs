@@ -0,0 +1,7 @@
+use bevy::prelude::*;
+
+fn main() {
+    App::new()
+        .add_plugins((DefaultPlugins, arrie::map::gallery::plugin))
+        .run();
+}
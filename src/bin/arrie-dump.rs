@@ -0,0 +1,572 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, Cursor, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use arrie::{
+    chunk::{self, ChunkHeader},
+    map::{
+        export::{self, ExportFormat},
+        file::{Map, SlopeType},
+        minimap::{Area, TopDownRenderer},
+        tile_usage::{self, FaceCounts, TileUsage},
+        validate::{self, Severity},
+    },
+    tile_sheet::{self, PaletteMode},
+    writer, StyleFile,
+};
+use clap::{Parser, Subcommand};
+use serde::Serialize;
+
+const MAP_FILE_TYPE: &str = "GBMP";
+const STYLE_FILE_TYPE: &str = "GBST";
+
+/// Inspect GTA2 map (GMP) and style (STY) files without starting the viewer.
+#[derive(Parser)]
+#[command(name = "arrie-dump")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print header, chunk layout and content statistics of a GMP or STY file
+    Info {
+        file: PathBuf,
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Write every tile of a STY file as PNG into a directory
+    ExportTiles {
+        style: PathBuf,
+        out_dir: PathBuf,
+        /// Also write every 256x256 tile page as PNG
+        #[arg(long)]
+        pages: bool,
+    },
+    /// Import edited tile PNGs (named like the exported ones) into a new STY
+    /// file
+    ImportTiles {
+        style: PathBuf,
+        tiles_dir: PathBuf,
+        out: PathBuf,
+        /// Generate a new physical palette for each tile instead of
+        /// quantising to the palette the tile currently uses
+        #[arg(long)]
+        new_palettes: bool,
+    },
+    /// Render the lids of a map from straight above into a PNG
+    RenderMap {
+        map: PathBuf,
+        style: PathBuf,
+        out: PathBuf,
+        /// Pixels per map cell, 64 draws the full tiles
+        #[arg(long, default_value_t = 8, value_parser = clap::value_parser!(u32).range(1..=64))]
+        zoom: u32,
+        /// Only render the cells "x,y,width,height"
+        #[arg(long, value_parser = parse_area)]
+        area: Option<Area>,
+    },
+    /// Export the blocks of a map with their tiles as glTF binary (.glb) or
+    /// OBJ+MTL (.obj), by the extension of `out`
+    ExportMap {
+        map: PathBuf,
+        style: PathBuf,
+        out: PathBuf,
+    },
+    /// Count the uses of every tile of a STY by the lids and sides of a map,
+    /// and list unused tiles and ids missing in the style
+    TileUsage {
+        map: PathBuf,
+        style: PathBuf,
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+        /// Also list the unused tiles in the table
+        #[arg(long)]
+        all: bool,
+    },
+    /// Check a map for broken indices, tiles missing in a STY, unmatched
+    /// slopes, missing lids and arrows into walls. Fails if errors are found
+    Validate {
+        map: PathBuf,
+        style: PathBuf,
+        /// Print the issues as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let result: Result<(), Box<dyn std::error::Error>> = match cli.command {
+        Command::Info { file, json } => info(&file, json).map_err(Into::into),
+        Command::ExportTiles {
+            style,
+            out_dir,
+            pages,
+        } => export_tiles(&style, &out_dir, pages),
+        Command::ImportTiles {
+            style,
+            tiles_dir,
+            out,
+            new_palettes,
+        } => import_tiles(&style, &tiles_dir, &out, new_palettes),
+        Command::RenderMap {
+            map,
+            style,
+            out,
+            zoom,
+            area,
+        } => render_map(&map, &style, &out, zoom, area.unwrap_or(Area::WHOLE_MAP)),
+        Command::ExportMap { map, style, out } => export_map(&map, &style, &out),
+        Command::TileUsage {
+            map,
+            style,
+            json,
+            all,
+        } => tile_usage(&map, &style, json, all),
+        Command::Validate { map, style, json } => validate(&map, &style, json),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Report {
+    file: String,
+    file_type: String,
+    version: u16,
+    chunks: Vec<ChunkReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    map: Option<MapReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    style: Option<StyleReport>,
+}
+
+#[derive(Serialize)]
+struct ChunkReport {
+    tag: String,
+    offset: u64,
+    size: u32,
+    /// The size runs past the end of the file.
+    truncated: bool,
+}
+
+impl From<ChunkHeader> for ChunkReport {
+    fn from(chunk: ChunkHeader) -> Self {
+        Self {
+            tag: chunk.tag,
+            offset: chunk.offset,
+            size: chunk.size,
+            truncated: chunk.truncated,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct MapReport {
+    columns: usize,
+    column_words: usize,
+    block_infos: usize,
+    /// Blocks by slope byte, without the ground type bits.
+    slope_types: BTreeMap<u8, usize>,
+}
+
+#[derive(Serialize)]
+struct StyleReport {
+    tiles: usize,
+    tile_pages: usize,
+    physical_palettes: usize,
+    palette_index_entries: usize,
+    palette_base: BTreeMap<&'static str, u16>,
+}
+
+fn info(path: &Path, json: bool) -> io::Result<()> {
+    let bytes = std::fs::read(path)?;
+    let (header, chunks) = chunk::read_layout(&mut Cursor::new(&bytes))?;
+
+    let mut report = Report {
+        file: path.display().to_string(),
+        file_type: header.file_type,
+        version: header.version,
+        chunks: chunks.into_iter().map(ChunkReport::from).collect(),
+        map: None,
+        style: None,
+    };
+
+    match report.file_type.as_str() {
+        MAP_FILE_TYPE => report.map = Some(map_report(Map::from_bytes(bytes)?)),
+        STYLE_FILE_TYPE => report.style = Some(style_report(&StyleFile::from_bytes(bytes)?)),
+        _ => {}
+    }
+
+    if json {
+        let out = serde_json::to_string_pretty(&report).map_err(io::Error::other)?;
+        println!("{out}");
+    } else {
+        print_report(&report);
+    }
+
+    Ok(())
+}
+
+fn export_tiles(
+    path: &Path,
+    out_dir: &Path,
+    pages: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let style = StyleFile::from_bytes(std::fs::read(path)?)?;
+
+    let tiles = tile_sheet::export_tiles(&style, out_dir)?;
+    println!("wrote {} tiles to {}", tiles.len(), out_dir.display());
+
+    if pages {
+        let pages = tile_sheet::export_pages(&style, out_dir)?;
+        println!("wrote {} pages to {}", pages.len(), out_dir.display());
+    }
+
+    Ok(())
+}
+
+fn import_tiles(
+    path: &Path,
+    tiles_dir: &Path,
+    out: &Path,
+    new_palettes: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let original = std::fs::read(path)?;
+    let mut style = StyleFile::from_bytes(original.clone())?;
+
+    let mode = if new_palettes {
+        PaletteMode::New
+    } else {
+        PaletteMode::Existing
+    };
+    let imported = tile_sheet::import_tiles(&mut style, tiles_dir, mode)?;
+
+    let mut file = io::BufWriter::new(File::create(out)?);
+    writer::write_style(&style, &original, &mut file)?;
+
+    println!("imported {} tiles into {}", imported.len(), out.display());
+
+    Ok(())
+}
+
+fn render_map(
+    map_path: &Path,
+    style_path: &Path,
+    out: &Path,
+    zoom: u32,
+    area: Area,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let map = Map::from_bytes(std::fs::read(map_path)?)?;
+    let style = StyleFile::from_bytes(std::fs::read(style_path)?)?;
+    let uncompressed = map.uncompressed_map.ok_or("map has no blocks")?;
+
+    let renderer = TopDownRenderer::new(&style, zoom as usize);
+    let image = renderer.render(&uncompressed, area);
+
+    let file = io::BufWriter::new(File::create(out)?);
+    tile_sheet::encode_png(file, image.width, image.height, &image.rgba)?;
+
+    println!(
+        "wrote {}x{} map image to {}",
+        image.width,
+        image.height,
+        out.display()
+    );
+
+    Ok(())
+}
+
+fn export_map(
+    map_path: &Path,
+    style_path: &Path,
+    out: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let format = match out.extension().and_then(|extension| extension.to_str()) {
+        Some("glb") => ExportFormat::Glb,
+        Some("obj") => ExportFormat::Obj,
+        _ => return Err("output must end in .glb or .obj".into()),
+    };
+
+    let map = Map::from_bytes(std::fs::read(map_path)?)?;
+    let style = StyleFile::from_bytes(std::fs::read(style_path)?)?;
+    let uncompressed = map.uncompressed_map.ok_or("map has no blocks")?;
+
+    let faces = export::map_faces(&uncompressed, None);
+    let textures = |tile_id| {
+        let mut pixels = style.tile_bgra(tile_id)?;
+        tile_sheet::bgra_to_rgba(&mut pixels);
+        Some(pixels)
+    };
+
+    match format {
+        ExportFormat::Glb => {
+            let mut file = io::BufWriter::new(File::create(out)?);
+            export::write_glb(&faces, textures, &mut file)?;
+            file.flush()?;
+        }
+        ExportFormat::Obj => {
+            let dir = out.parent().unwrap_or(Path::new("."));
+            let name = out
+                .file_stem()
+                .and_then(|name| name.to_str())
+                .ok_or("output has no file name")?;
+            export::write_obj(&faces, textures, dir, name)?;
+        }
+    }
+
+    println!("wrote {} faces to {}", faces.len(), out.display());
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct TileUsageReport {
+    tiles: Vec<TileCountReport>,
+    unused: Vec<usize>,
+    missing: Vec<TileCountReport>,
+}
+
+#[derive(Serialize)]
+struct TileCountReport {
+    id: usize,
+    lid: usize,
+    side: usize,
+}
+
+impl From<(&usize, &FaceCounts)> for TileCountReport {
+    fn from((id, counts): (&usize, &FaceCounts)) -> Self {
+        Self {
+            id: *id,
+            lid: counts.lid,
+            side: counts.side,
+        }
+    }
+}
+
+fn tile_usage(
+    map_path: &Path,
+    style_path: &Path,
+    json: bool,
+    all: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let map = Map::from_bytes(std::fs::read(map_path)?)?;
+    let style = StyleFile::from_bytes(std::fs::read(style_path)?)?;
+    let uncompressed = map.uncompressed_map.ok_or("map has no blocks")?;
+
+    let usage = TileUsage::new(&uncompressed, &style);
+
+    if json {
+        let report = TileUsageReport {
+            tiles: usage.counts.iter().map(TileCountReport::from).collect(),
+            unused: usage.unused.clone(),
+            missing: usage.missing.iter().map(TileCountReport::from).collect(),
+        };
+        let out = serde_json::to_string_pretty(&report)?;
+        println!("{out}");
+        return Ok(());
+    }
+
+    println!("{:>6}  {:>8}  {:>8}", "tile", "lids", "sides");
+    for (id, counts) in &usage.counts {
+        if all || counts.total() > 0 {
+            println!("{id:>6}  {:>8}  {:>8}", counts.lid, counts.side);
+        }
+    }
+
+    println!(
+        "used tiles:   {} of {}",
+        usage.counts.len() - usage.unused.len(),
+        usage.counts.len()
+    );
+    println!(
+        "unused tiles: {}",
+        tile_usage::id_ranges(usage.unused.iter().copied())
+    );
+
+    if !usage.missing.is_empty() {
+        println!("ids missing in the style:");
+        for (id, counts) in &usage.missing {
+            println!("{id:>6}  {:>8}  {:>8}", counts.lid, counts.side);
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct IssueReport {
+    severity: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    location: Option<[i32; 3]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    column: Option<[i32; 2]>,
+    message: String,
+}
+
+impl From<&validate::Issue> for IssueReport {
+    fn from(issue: &validate::Issue) -> Self {
+        let location = issue.location;
+        Self {
+            severity: issue.severity.to_string(),
+            location: location.and_then(|l| Some([l.x, l.y, l.z?])),
+            column: location.filter(|l| l.z.is_none()).map(|l| [l.x, l.y]),
+            message: issue.kind.to_string(),
+        }
+    }
+}
+
+fn validate(
+    map_path: &Path,
+    style_path: &Path,
+    json: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let map = Map::from_bytes(std::fs::read(map_path)?)?;
+    let style = StyleFile::from_bytes(std::fs::read(style_path)?)?;
+
+    let issues = validate::validate(&map, &style);
+    let errors = issues
+        .iter()
+        .filter(|issue| issue.severity == Severity::Error)
+        .count();
+
+    if json {
+        let report: Vec<_> = issues.iter().map(IssueReport::from).collect();
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        for issue in &issues {
+            println!("{issue}");
+        }
+        println!("{errors} errors, {} warnings", issues.len() - errors);
+    }
+
+    if errors > 0 {
+        return Err(format!("{} has {errors} errors", map_path.display()).into());
+    }
+
+    Ok(())
+}
+
+fn parse_area(value: &str) -> Result<Area, String> {
+    let parts = value
+        .split(',')
+        .map(|part| part.trim().parse::<i32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| err.to_string())?;
+
+    let [x, y, width, height] = parts[..] else {
+        return Err("expected x,y,width,height".into());
+    };
+    if width <= 0 || height <= 0 {
+        return Err("width and height must be positive".into());
+    }
+
+    Ok(Area {
+        x,
+        y,
+        width: width as u32,
+        height: height as u32,
+    })
+}
+
+fn map_report(map: Map) -> MapReport {
+    let compressed = &map.compressed_map_32bit;
+
+    let mut columns: Vec<u32> = compressed.base().to_vec();
+    columns.sort_unstable();
+    columns.dedup();
+
+    let mut slope_types = BTreeMap::new();
+    if let Some(uncompressed) = &map.uncompressed_map {
+        for block in &uncompressed.0 {
+            *slope_types.entry(u8::from(&block.slope_type)).or_insert(0) += 1;
+        }
+    }
+
+    MapReport {
+        columns: columns.len(),
+        column_words: compressed.column_infos().len(),
+        block_infos: compressed.block_infos().len(),
+        slope_types,
+    }
+}
+
+fn style_report(style: &StyleFile) -> StyleReport {
+    const TILES_PER_PAGE: usize = 16;
+
+    let base = &style.palette_base;
+    let palette_base = BTreeMap::from([
+        ("tile", base.tile),
+        ("sprite", base.sprite),
+        ("car_remap", base.car_remap),
+        ("ped_remap", base.ped_remap),
+        ("code_obj_remap", base.code_obj_remap),
+        ("map_obj_remap", base.map_opj_remap),
+        ("user_remap", base.user_remap),
+        ("font_remap", base.font_remap),
+    ]);
+
+    StyleReport {
+        tiles: style.tiles.len(),
+        tile_pages: style.tiles.len().div_ceil(TILES_PER_PAGE),
+        physical_palettes: style.physical_palette.len(),
+        palette_index_entries: style.palette_index.physical_index.len(),
+        palette_base,
+    }
+}
+
+fn print_report(report: &Report) {
+    println!("file:    {}", report.file);
+    println!("type:    {}", report.file_type);
+    println!("version: {}", report.version);
+
+    println!("chunks:");
+    for chunk in &report.chunks {
+        println!(
+            "  {:<4}  offset {:>10}  size {:>10}{}",
+            chunk.tag,
+            chunk.offset,
+            chunk.size,
+            if chunk.truncated { "  (truncated)" } else { "" }
+        );
+    }
+
+    if let Some(map) = &report.map {
+        println!("columns:      {}", map.columns);
+        println!("column words: {}", map.column_words);
+        println!("block infos:  {}", map.block_infos);
+        println!("slope types:");
+        for (slope_type, count) in &map.slope_types {
+            println!(
+                "  {count:>8}  {slope_type:>2} {:?}",
+                SlopeType::from(slope_type << 2)
+            );
+        }
+    }
+
+    if let Some(style) = &report.style {
+        println!("tiles:             {}", style.tiles);
+        println!("tile pages:        {}", style.tile_pages);
+        println!("physical palettes: {}", style.physical_palettes);
+        println!("palette index:     {}", style.palette_index_entries);
+        println!("palette base:");
+        for (name, count) in &style.palette_base {
+            println!("  {name:<15} {count}");
+        }
+    }
+}
@@ -1,9 +1,67 @@
-use bevy::prelude::*;
+mod fly;
+
+use bevy::{input::common_conditions::input_just_pressed, prelude::*};
 use bevy_panorbit_camera::{PanOrbitCamera, PanOrbitCameraPlugin};
 
 pub fn plugin(app: &mut App) {
-    app.add_plugins(PanOrbitCameraPlugin)
-        .add_systems(Startup, setup_camera);
+    app.add_plugins((PanOrbitCameraPlugin, fly::plugin))
+        .insert_state(CameraMode::Orbit)
+        .add_systems(Startup, (setup_camera, spawn_preview_target))
+        .add_systems(
+            Update,
+            toggle_camera_mode.run_if(input_just_pressed(TOGGLE_KEY)),
+        )
+        .add_systems(OnEnter(CameraMode::Orbit), enter_orbit_mode)
+        .add_systems(OnEnter(CameraMode::Game), enter_game_mode)
+        .add_systems(
+            Update,
+            (drive_preview_target, follow_target)
+                .chain()
+                .run_if(in_state(CameraMode::Game)),
+        );
+}
+
+const TOGGLE_KEY: KeyCode = KeyCode::KeyC;
+
+/// Camera height above the target while standing still.
+const GAME_CAMERA_HEIGHT: f32 = 12.0;
+const GAME_CAMERA_MAX_HEIGHT: f32 = 30.0;
+/// Additional height per block per second of target speed.
+const SPEED_ZOOM: f32 = 0.6;
+const FOLLOW_SMOOTHNESS: f32 = 8.0;
+const ZOOM_SMOOTHNESS: f32 = 2.0;
+
+const PREVIEW_ACCELERATION: f32 = 20.0;
+const PREVIEW_MAX_SPEED: f32 = 25.0;
+const PREVIEW_DRAG: f32 = 2.0;
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Default, States)]
+pub enum CameraMode {
+    /// Free [`PanOrbitCamera`].
+    #[default]
+    Orbit,
+    /// Top-down camera following the [`CameraTarget`] like in the game.
+    Game,
+    /// WASD and mouse look camera, optionally walking on the map.
+    Fly,
+}
+
+/// Entity followed by the game camera. Entities other than the preview
+/// target take precedence.
+#[derive(Component)]
+pub struct CameraTarget;
+
+/// Stand-in target moved with the arrow keys while nothing else is followed.
+#[derive(Component, Default)]
+struct PreviewTarget {
+    velocity: Vec2,
+}
+
+#[derive(Component, Default)]
+struct GameCamera {
+    focus: Vec3,
+    height: f32,
+    last_target: Option<Vec3>,
 }
 
 fn setup_camera(mut commands: Commands) {
@@ -16,6 +74,140 @@ fn setup_camera(mut commands: Commands) {
             },
             ..Default::default()
         },
+        GameCamera::default(),
         Transform::from_xyz(128.0, 128.0, 18.0),
     ));
 }
+
+fn spawn_preview_target(mut commands: Commands) {
+    commands.spawn((CameraTarget, PreviewTarget::default(), Transform::default()));
+}
+
+fn toggle_camera_mode(mode: Res<State<CameraMode>>, mut next_mode: ResMut<NextState<CameraMode>>) {
+    next_mode.set(match mode.get() {
+        CameraMode::Orbit => CameraMode::Game,
+        CameraMode::Game | CameraMode::Fly => CameraMode::Orbit,
+    });
+}
+
+fn enter_orbit_mode(mut cameras: Query<&mut PanOrbitCamera>) {
+    for mut camera in &mut cameras {
+        // the focus was kept in sync while following, so the orbit resumes
+        // around the last game camera focus
+        camera.enabled = true;
+        camera.force_update = true;
+    }
+}
+
+fn enter_game_mode(
+    mut cameras: Query<(&mut PanOrbitCamera, &mut GameCamera)>,
+    mut previews: Query<(&mut Transform, &mut PreviewTarget)>,
+) {
+    for (mut camera, mut game_camera) in &mut cameras {
+        hold_orbit_camera(&mut camera);
+
+        *game_camera = GameCamera {
+            focus: camera.focus,
+            height: GAME_CAMERA_HEIGHT,
+            last_target: None,
+        };
+
+        for (mut transform, mut preview) in &mut previews {
+            transform.translation = camera.focus;
+            preview.velocity = Vec2::ZERO;
+        }
+    }
+}
+
+/// Disables the orbit camera controls and stops any smoothing still in
+/// progress, which would move the camera.
+fn hold_orbit_camera(camera: &mut PanOrbitCamera) {
+    camera.enabled = false;
+    camera.target_focus = camera.focus;
+    camera.target_yaw = camera.yaw.unwrap_or(camera.target_yaw);
+    camera.target_pitch = camera.pitch.unwrap_or(camera.target_pitch);
+    camera.target_radius = camera.radius.unwrap_or(camera.target_radius);
+}
+
+fn drive_preview_target(
+    time: Res<Time>,
+    keys: Res<ButtonInput<KeyCode>>,
+    targets: Query<&GlobalTransform, (With<CameraTarget>, Without<PreviewTarget>)>,
+    mut previews: Query<(&mut Transform, &mut PreviewTarget)>,
+) {
+    // the arrow keys belong to the followed target, the preview waits where
+    // it is to take over when the target is gone
+    if let Some(target) = targets.iter().next() {
+        for (mut transform, mut preview) in &mut previews {
+            transform.translation = target.translation();
+            preview.velocity = Vec2::ZERO;
+        }
+        return;
+    }
+
+    let mut direction = Vec2::ZERO;
+    for (key, step) in [
+        (KeyCode::ArrowUp, Vec2::Y),
+        (KeyCode::ArrowDown, Vec2::NEG_Y),
+        (KeyCode::ArrowLeft, Vec2::NEG_X),
+        (KeyCode::ArrowRight, Vec2::X),
+    ] {
+        if keys.pressed(key) {
+            direction += step;
+        }
+    }
+
+    let dt = time.delta_secs();
+    for (mut transform, mut preview) in &mut previews {
+        let velocity = preview.velocity + direction.normalize_or_zero() * PREVIEW_ACCELERATION * dt;
+        preview.velocity =
+            (velocity * (1.0 - PREVIEW_DRAG * dt).max(0.0)).clamp_length_max(PREVIEW_MAX_SPEED);
+        transform.translation += preview.velocity.extend(0.0) * dt;
+    }
+}
+
+fn follow_target(
+    time: Res<Time>,
+    targets: Query<(&GlobalTransform, Has<PreviewTarget>), With<CameraTarget>>,
+    mut cameras: Query<(&mut Transform, &mut GameCamera, &mut PanOrbitCamera)>,
+) {
+    let Some((target, _)) = targets
+        .iter()
+        .find(|(_, preview)| !preview)
+        .or_else(|| targets.iter().next())
+    else {
+        return;
+    };
+    let target = target.translation();
+
+    let dt = time.delta_secs();
+    if dt <= 0.0 {
+        return;
+    }
+
+    for (mut transform, mut game_camera, mut orbit_camera) in &mut cameras {
+        let speed = game_camera
+            .last_target
+            .map_or(0.0, |last| (target - last).truncate().length() / dt);
+        game_camera.last_target = Some(target);
+
+        let focus = game_camera
+            .focus
+            .lerp(target, 1.0 - (-FOLLOW_SMOOTHNESS * dt).exp());
+        let height = (GAME_CAMERA_HEIGHT + speed * SPEED_ZOOM).min(GAME_CAMERA_MAX_HEIGHT);
+        let height = game_camera
+            .height
+            .lerp(height, 1.0 - (-ZOOM_SMOOTHNESS * dt).exp());
+
+        game_camera.focus = focus;
+        game_camera.height = height;
+
+        *transform =
+            Transform::from_translation(focus + Vec3::Z * height).looking_at(focus, Vec3::Y);
+
+        // keep the orbit camera on the same focus, setting both values
+        // leaves it at rest
+        orbit_camera.focus = focus;
+        orbit_camera.target_focus = focus;
+    }
+}
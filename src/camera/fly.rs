@@ -0,0 +1,201 @@
+use std::f32::consts::FRAC_PI_2;
+
+use bevy::{
+    input::{common_conditions::input_just_pressed, mouse::AccumulatedMouseMotion},
+    prelude::*,
+    window::{CursorGrabMode, PrimaryWindow},
+};
+use bevy_panorbit_camera::PanOrbitCamera;
+
+use super::{hold_orbit_camera, CameraMode};
+use crate::{
+    car::MAX_DELTA,
+    map::collision::{Blocking, CollisionMap},
+};
+
+pub(super) fn plugin(app: &mut App) {
+    app.add_systems(Update, toggle_fly_mode.run_if(input_just_pressed(FLY_KEY)))
+        .add_systems(OnEnter(CameraMode::Fly), enter_fly_mode)
+        .add_systems(OnExit(CameraMode::Fly), exit_fly_mode)
+        .add_systems(
+            Update,
+            (toggle_walk.run_if(input_just_pressed(WALK_KEY)), fly)
+                .chain()
+                .run_if(in_state(CameraMode::Fly)),
+        );
+}
+
+const FLY_KEY: KeyCode = KeyCode::KeyF;
+const WALK_KEY: KeyCode = KeyCode::KeyV;
+const FAST_KEY: KeyCode = KeyCode::ShiftLeft;
+const UP_KEY: KeyCode = KeyCode::Space;
+const DOWN_KEY: KeyCode = KeyCode::ControlLeft;
+
+const FLY_SPEED: f32 = 6.0;
+const WALK_SPEED: f32 = 1.5;
+const FAST_FACTOR: f32 = 4.0;
+/// Radians per pixel of mouse motion.
+const MOUSE_SENSITIVITY: f32 = 0.003;
+const MAX_PITCH: f32 = FRAC_PI_2 - 0.01;
+
+// walker dimensions in blocks, a ped is a bit less than half a block tall
+const EYE_HEIGHT: f32 = 0.35;
+const BODY_HEIGHT: f32 = 0.4;
+const BODY_RADIUS: f32 = 0.1;
+const STEP_HEIGHT: f32 = 0.2;
+
+#[derive(Component, Default)]
+struct FlyCamera {
+    yaw: f32,
+    pitch: f32,
+    walk: bool,
+    vertical_speed: f32,
+}
+
+fn toggle_fly_mode(mode: Res<State<CameraMode>>, mut next_mode: ResMut<NextState<CameraMode>>) {
+    next_mode.set(match mode.get() {
+        CameraMode::Fly => CameraMode::Orbit,
+        _ => CameraMode::Fly,
+    });
+}
+
+fn enter_fly_mode(
+    mut commands: Commands,
+    mut cameras: Query<(Entity, &Transform, &mut PanOrbitCamera)>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    for (entity, transform, mut orbit_camera) in &mut cameras {
+        hold_orbit_camera(&mut orbit_camera);
+
+        // continue looking into the same direction
+        let forward = transform.forward();
+        let yaw = if forward.truncate().length() > f32::EPSILON {
+            (-forward.x).atan2(forward.y)
+        } else {
+            0.0
+        };
+
+        commands.entity(entity).insert(FlyCamera {
+            yaw,
+            pitch: forward.z.asin().clamp(-MAX_PITCH, MAX_PITCH),
+            ..default()
+        });
+    }
+
+    for mut window in &mut windows {
+        window.cursor_options.grab_mode = CursorGrabMode::Locked;
+        window.cursor_options.visible = false;
+    }
+}
+
+fn exit_fly_mode(
+    mut commands: Commands,
+    cameras: Query<Entity, With<FlyCamera>>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    for entity in &cameras {
+        commands.entity(entity).remove::<FlyCamera>();
+    }
+
+    for mut window in &mut windows {
+        window.cursor_options.grab_mode = CursorGrabMode::None;
+        window.cursor_options.visible = true;
+    }
+}
+
+fn toggle_walk(mut cameras: Query<&mut FlyCamera>) {
+    for mut camera in &mut cameras {
+        camera.walk = !camera.walk;
+        camera.vertical_speed = 0.0;
+        info!("Walk mode: {}", camera.walk);
+    }
+}
+
+fn fly(
+    time: Res<Time>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mouse_motion: Res<AccumulatedMouseMotion>,
+    collision: Option<Res<CollisionMap>>,
+    mut cameras: Query<(&mut Transform, &mut FlyCamera, &mut PanOrbitCamera)>,
+) {
+    let dt = time.delta_secs();
+
+    let mut input = Vec3::ZERO;
+    for (key, direction) in [
+        (KeyCode::KeyW, Vec3::Y),
+        (KeyCode::KeyS, Vec3::NEG_Y),
+        (KeyCode::KeyA, Vec3::NEG_X),
+        (KeyCode::KeyD, Vec3::X),
+        (UP_KEY, Vec3::Z),
+        (DOWN_KEY, Vec3::NEG_Z),
+    ] {
+        if keys.pressed(key) {
+            input += direction;
+        }
+    }
+    let fast = if keys.pressed(FAST_KEY) {
+        FAST_FACTOR
+    } else {
+        1.0
+    };
+
+    for (mut transform, mut camera, mut orbit_camera) in &mut cameras {
+        camera.yaw -= mouse_motion.delta.x * MOUSE_SENSITIVITY;
+        camera.pitch =
+            (camera.pitch - mouse_motion.delta.y * MOUSE_SENSITIVITY).clamp(-MAX_PITCH, MAX_PITCH);
+
+        // the camera looks along -Z, tilt it to look along +Y first
+        let yaw = Quat::from_rotation_z(camera.yaw);
+        transform.rotation = yaw * Quat::from_rotation_x(FRAC_PI_2 + camera.pitch);
+
+        match (camera.walk, collision.as_deref()) {
+            (true, Some(collision)) => {
+                let heading = (yaw * input.with_z(0.0)).truncate().normalize_or_zero();
+                let feet = transform.translation.z - EYE_HEIGHT;
+                let mut position = transform.translation.truncate();
+
+                let dt = dt.min(MAX_DELTA);
+
+                // move along each axis on its own to slide along walls
+                for step in [Vec2::new(heading.x, 0.0), Vec2::new(0.0, heading.y)] {
+                    let step = step * WALK_SPEED * fast * dt;
+                    if step == Vec2::ZERO {
+                        continue;
+                    }
+
+                    // floors up to the step height are climbed, the body
+                    // above them must be free
+                    let body = position.extend(feet + (STEP_HEIGHT + BODY_HEIGHT) / 2.0);
+                    let half_size =
+                        Vec2::splat(BODY_RADIUS).extend((BODY_HEIGHT - STEP_HEIGHT) / 2.0);
+                    position = match collision.sweep_box(
+                        body,
+                        half_size,
+                        step.extend(0.0),
+                        Blocking::Movement,
+                    ) {
+                        Some(hit) => hit.center.truncate(),
+                        None => position + step,
+                    };
+                }
+
+                let feet = collision.fall(
+                    position.extend(feet),
+                    &mut camera.vertical_speed,
+                    STEP_HEIGHT,
+                    dt,
+                );
+                transform.translation = position.extend(feet + EYE_HEIGHT);
+            }
+            _ => {
+                let movement =
+                    transform.forward() * input.y + transform.right() * input.x + Vec3::Z * input.z;
+                transform.translation += movement.normalize_or_zero() * FLY_SPEED * fast * dt;
+            }
+        }
+
+        // keep the orbit camera, and with it the mini-map, on the position
+        orbit_camera.focus = transform.translation;
+        orbit_camera.target_focus = transform.translation;
+    }
+}
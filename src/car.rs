@@ -0,0 +1,572 @@
+//! Drivable car to test the drivability of maps. The car drives on the
+//! floors of the [`CollisionMap`] with simple top-down physics, climbs
+//! slopes, stops at walls and is drawn with its sprite from the style.
+
+use bevy::{
+    asset::RenderAssetUsages, ecs::system::EntityCommands,
+    input::common_conditions::input_just_pressed, prelude::*,
+};
+use wgpu::{Extent3d, TextureDimension, TextureFormat};
+
+use crate::{
+    camera::{CameraMode, CameraTarget},
+    loader::StyleFileAsset,
+    map::{
+        collision::{Blocking, CollisionMap, SweepHit},
+        file::GroundType,
+        ground, Map, MapFileAsset, Style,
+    },
+    style::{CarInfo, Remap},
+    StyleFile,
+};
+
+pub fn plugin(app: &mut App) {
+    app.add_systems(
+        Update,
+        (toggle_car.run_if(input_just_pressed(CAR_KEY)), drive_cars)
+            .chain()
+            .run_if(in_state(CameraMode::Game)),
+    );
+}
+
+const CAR_KEY: KeyCode = KeyCode::Enter;
+const ACCELERATE_KEY: KeyCode = KeyCode::ArrowUp;
+const BRAKE_KEY: KeyCode = KeyCode::ArrowDown;
+const LEFT_KEY: KeyCode = KeyCode::ArrowLeft;
+const RIGHT_KEY: KeyCode = KeyCode::ArrowRight;
+const HANDBRAKE_KEY: KeyCode = KeyCode::Space;
+
+/// Sprites and car infos have 64 pixels per block.
+pub(crate) const PIXELS_PER_BLOCK: f32 = 64.0;
+/// Width and length of cars without car info or sprite, in blocks.
+const DEFAULT_SIZE: Vec2 = Vec2::new(0.5, 1.0);
+const DEFAULT_COLOR: Color = Color::srgb(0.8, 0.1, 0.1);
+/// Draws sprites above the floor they stand on.
+pub(crate) const SPRITE_LIFT: f32 = 0.02;
+
+// body used for collisions, in blocks
+const BODY_HEIGHT: f32 = 0.2;
+/// Floors up to this above the wheels are driven onto.
+const STEP_HEIGHT: f32 = 0.2;
+/// Steepest slope the whole length of the car climbs, the 26° slopes.
+const MAX_GRADIENT: f32 = 0.5;
+
+// speeds in blocks per second
+const ACCELERATION: f32 = 6.0;
+const REVERSE_ACCELERATION: f32 = 3.0;
+const BRAKE_DECELERATION: f32 = 12.0;
+const ROLLING_DECELERATION: f32 = 1.5;
+const HANDBRAKE_DECELERATION: f32 = 6.0;
+const MAX_SPEED: f32 = 12.0;
+const MAX_REVERSE_SPEED: f32 = 4.0;
+/// Fraction of the speed lost per second to the air.
+const DRAG: f32 = 0.3;
+/// Largest angle of the front wheels, in radians.
+const MAX_STEERING: f32 = 0.6;
+/// Speed at which the steering angle is halved, fast cars turn less.
+const STEERING_FALLOFF_SPEED: f32 = 10.0;
+/// Distance of the axles relative to the length of the car.
+const WHEELBASE: f32 = 0.6;
+/// How much more the car turns with locked rear wheels.
+const HANDBRAKE_TURN: f32 = 1.8;
+/// Fraction of the speed kept when hitting a wall.
+const WALL_SPEED: f32 = 0.3;
+/// Longest step of the physics, for frames taking longer.
+pub(crate) const MAX_DELTA: f32 = 0.1;
+
+/// Car with simple top-down physics, its transform is on the floor below
+/// its centre.
+#[derive(Component, Debug, Clone)]
+pub struct Car {
+    /// Angle around Z, 0 facing along +Y.
+    heading: f32,
+    /// Speed along the heading, negative while reversing.
+    speed: f32,
+    vertical_speed: f32,
+    /// Half of the width and the length of the body.
+    half_size: Vec2,
+}
+
+/// The car driven with the arrow keys.
+#[derive(Component, Debug)]
+pub struct PlayerCar;
+
+/// Driver input, `throttle` and `steering` from -1 to 1, left steering is
+/// positive.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) struct Controls {
+    pub throttle: f32,
+    pub steering: f32,
+    pub handbrake: bool,
+}
+
+/// Size of a car model in blocks with the mesh and material drawing it, a
+/// plain rectangle if the style has no sprite for it.
+#[derive(Debug, Clone)]
+pub(crate) struct CarModel {
+    size: Vec2,
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+impl CarModel {
+    pub(crate) fn new(
+        style: Option<&StyleFile>,
+        info: Option<&CarInfo>,
+        meshes: &mut Assets<Mesh>,
+        materials: &mut Assets<StandardMaterial>,
+        images: &mut Assets<Image>,
+    ) -> Self {
+        let size = info.map_or(DEFAULT_SIZE, |info| {
+            Vec2::new(f32::from(info.width), f32::from(info.height)) / PIXELS_PER_BLOCK
+        });
+
+        // NOTE: car sprites are the first sprites of the style
+        let sprite = style.zip(info).and_then(|(style, info)| {
+            sprite_image(style, usize::from(info.sprite), Remap::None, images)
+        });
+        match sprite {
+            Some((image, sprite_size)) => Self {
+                size,
+                mesh: meshes.add(Rectangle::from_size(sprite_size)),
+                material: materials.add(StandardMaterial {
+                    base_color_texture: Some(image),
+                    alpha_mode: AlphaMode::Mask(0.5),
+                    ..default()
+                }),
+            },
+            None => Self {
+                size,
+                mesh: meshes.add(Rectangle::from_size(size)),
+                material: materials.add(DEFAULT_COLOR),
+            },
+        }
+    }
+
+    /// Spawns a car of this model facing `heading`.
+    pub(crate) fn spawn<'a>(
+        &self,
+        commands: &'a mut Commands,
+        position: Vec3,
+        heading: f32,
+    ) -> EntityCommands<'a> {
+        let mut car = commands.spawn((
+            Car::new(self.size, heading),
+            Transform::from_translation(position).with_rotation(Quat::from_rotation_z(heading)),
+            Visibility::default(),
+        ));
+        car.with_children(|car| {
+            car.spawn((
+                Mesh3d(self.mesh.clone()),
+                MeshMaterial3d(self.material.clone()),
+                Transform::from_xyz(0.0, 0.0, SPRITE_LIFT),
+            ));
+        });
+
+        car
+    }
+}
+
+impl Controls {
+    fn from_keys(keys: &ButtonInput<KeyCode>) -> Self {
+        let axis = |positive: KeyCode, negative: KeyCode| {
+            f32::from(u8::from(keys.pressed(positive)))
+                - f32::from(u8::from(keys.pressed(negative)))
+        };
+
+        Self {
+            throttle: axis(ACCELERATE_KEY, BRAKE_KEY),
+            steering: axis(LEFT_KEY, RIGHT_KEY),
+            handbrake: keys.pressed(HANDBRAKE_KEY),
+        }
+    }
+}
+
+impl Car {
+    fn new(size: Vec2, heading: f32) -> Self {
+        Self {
+            heading,
+            speed: 0.0,
+            vertical_speed: 0.0,
+            half_size: size / 2.0,
+        }
+    }
+
+    pub(crate) fn heading(&self) -> f32 {
+        self.heading
+    }
+
+    pub(crate) fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Half of the width and the length of the body.
+    pub(crate) fn half_size(&self) -> Vec2 {
+        self.half_size
+    }
+
+    pub(crate) fn forward(heading: f32) -> Vec2 {
+        Vec2::from_angle(heading).rotate(Vec2::Y)
+    }
+
+    /// Advances the car by `dt` seconds, `transform` is its position on the
+    /// floor.
+    pub(crate) fn step(
+        &mut self,
+        transform: &mut Transform,
+        collision: &CollisionMap,
+        controls: Controls,
+        dt: f32,
+    ) {
+        self.update_speed(controls, dt);
+
+        // turn unless the body turns into a wall
+        let mut position = transform.translation;
+        let heading = self.heading + self.turn_rate(controls) * dt;
+        if !self.overlaps(collision, position, heading) {
+            self.heading = heading;
+        }
+
+        // move, sliding along walls
+        let mut motion = (Self::forward(self.heading) * self.speed * dt).extend(0.0);
+        let mut hit_wall = false;
+        for _ in 0..2 {
+            let Some(hit) = self.sweep(collision, position, motion) else {
+                position += motion;
+                break;
+            };
+
+            position += motion * hit.time;
+            motion = Vec3::select(hit.blocked, Vec3::ZERO, motion * (1.0 - hit.time));
+            hit_wall = true;
+        }
+        if hit_wall {
+            self.speed *= WALL_SPEED;
+        }
+
+        position.z = collision.fall(position, &mut self.vertical_speed, STEP_HEIGHT, dt);
+        transform.translation = position;
+        transform.rotation = self.tilt(collision, position);
+    }
+
+    fn update_speed(&mut self, controls: Controls, dt: f32) {
+        let throttle = controls.throttle;
+        // pressing against the direction of travel brakes first
+        let braking =
+            throttle != 0.0 && self.speed != 0.0 && throttle.signum() != self.speed.signum();
+
+        let mut speed = if braking {
+            approach(self.speed, 0.0, BRAKE_DECELERATION * throttle.abs() * dt)
+        } else if throttle > 0.0 {
+            self.speed + ACCELERATION * throttle * dt
+        } else if throttle < 0.0 {
+            self.speed + REVERSE_ACCELERATION * throttle * dt
+        } else {
+            approach(self.speed, 0.0, ROLLING_DECELERATION * dt)
+        };
+        if controls.handbrake {
+            speed = approach(speed, 0.0, HANDBRAKE_DECELERATION * dt);
+        }
+        speed *= (1.0 - DRAG * dt).max(0.0);
+
+        self.speed = speed.clamp(-MAX_REVERSE_SPEED, MAX_SPEED);
+    }
+
+    /// Radians per second around Z, like a bicycle with the length of the
+    /// car between the wheels.
+    fn turn_rate(&self, controls: Controls) -> f32 {
+        let steering =
+            controls.steering * MAX_STEERING / (1.0 + self.speed.abs() / STEERING_FALLOFF_SPEED);
+        let wheelbase = self.half_size.y * 2.0 * WHEELBASE;
+        let rate = self.speed * steering.tan() / wheelbase;
+
+        if controls.handbrake {
+            rate * HANDBRAKE_TURN
+        } else {
+            rate
+        }
+    }
+
+    fn box_half_size(&self) -> Vec3 {
+        Vec2::splat(self.half_size.x).extend(BODY_HEIGHT / 2.0)
+    }
+
+    /// Centres of two squares as wide as the car at its front and rear,
+    /// covering the body. They are above the floors the car climbs.
+    fn body_boxes(&self, position: Vec3, heading: f32) -> [Vec3; 2] {
+        let offset = Self::forward(heading) * (self.half_size.y - self.half_size.x).max(0.0);
+        let lift = STEP_HEIGHT + self.half_size.y * MAX_GRADIENT + BODY_HEIGHT / 2.0;
+
+        [offset, -offset].map(|offset| position + offset.extend(lift))
+    }
+
+    fn overlaps(&self, collision: &CollisionMap, position: Vec3, heading: f32) -> bool {
+        self.body_boxes(position, heading)
+            .into_iter()
+            .any(|center| collision.overlaps_box(center, self.box_half_size(), Blocking::Movement))
+    }
+
+    /// Earliest collision of the body moving by `motion`.
+    fn sweep(&self, collision: &CollisionMap, position: Vec3, motion: Vec3) -> Option<SweepHit> {
+        self.body_boxes(position, self.heading)
+            .into_iter()
+            .filter_map(|center| {
+                collision.sweep_box(center, self.box_half_size(), motion, Blocking::Movement)
+            })
+            .min_by(|a, b| a.time.total_cmp(&b.time))
+    }
+
+    /// Rotation of the car following the floor below its front, rear and
+    /// sides.
+    fn tilt(&self, collision: &CollisionMap, position: Vec3) -> Quat {
+        let forward = Self::forward(self.heading);
+        let right = Vec2::new(forward.y, -forward.x);
+        let height = |offset: Vec2| {
+            let max_z = position.z + STEP_HEIGHT + offset.length() * MAX_GRADIENT;
+            collision
+                .height_at(position.truncate() + offset, max_z)
+                .unwrap_or(position.z)
+        };
+
+        let (length, width) = (self.half_size.y, self.half_size.x);
+        let pitch =
+            ((height(forward * length) - height(-forward * length)) / (2.0 * length)).atan();
+        let roll = ((height(right * width) - height(-right * width)) / (2.0 * width)).atan();
+
+        Quat::from_rotation_z(self.heading)
+            * Quat::from_rotation_x(pitch)
+            * Quat::from_rotation_y(-roll)
+    }
+}
+
+/// Moves `value` by at most `step` towards `target`.
+fn approach(value: f32, target: f32, step: f32) -> f32 {
+    if value > target {
+        (value - step).max(target)
+    } else {
+        (value + step).min(target)
+    }
+}
+
+/// Removes the car, or spawns the next car model at the followed target,
+/// on the road if there is one.
+#[allow(clippy::too_many_arguments)]
+fn toggle_car(
+    mut commands: Commands,
+    mut spawned: Local<usize>,
+    cars: Query<Entity, With<PlayerCar>>,
+    targets: Query<&GlobalTransform, With<CameraTarget>>,
+    map: Option<Res<Map>>,
+    map_assets: Res<Assets<MapFileAsset>>,
+    style: Option<Res<Style>>,
+    style_assets: Res<Assets<StyleFileAsset>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    if !cars.is_empty() {
+        for car in &cars {
+            commands.entity(car).despawn_recursive();
+        }
+        return;
+    }
+
+    let Some(uncompressed) = map
+        .and_then(|map| map_assets.get(&map.asset))
+        .and_then(|map| map.0.uncompressed_map.as_ref())
+    else {
+        warn!("No map loaded to drive on");
+        return;
+    };
+    let Some(target) = targets.iter().next() else {
+        return;
+    };
+
+    let position = target.translation().truncate();
+    let Some(ground) = ground::grounds(uncompressed, position)
+        .find(|ground| ground.ground_type == GroundType::Road)
+        .or_else(|| ground::grounds(uncompressed, position).next())
+    else {
+        warn!("No ground to place a car at {position}");
+        return;
+    };
+
+    let style = style
+        .and_then(|style| style_assets.get(&style.asset))
+        .map(|style| &style.0);
+    let car_info = style.and_then(|style| {
+        let model = *spawned % style.car_infos.len().max(1);
+        style.car_infos.get(model)
+    });
+    *spawned += 1;
+
+    if let Some(info) = car_info {
+        info!(
+            "Car model {} with {} passengers",
+            info.model, info.passengers
+        );
+    }
+
+    CarModel::new(style, car_info, &mut meshes, &mut materials, &mut images)
+        .spawn(&mut commands, position.extend(ground.height), 0.0)
+        .insert((PlayerCar, CameraTarget));
+}
+
+/// Image of the sprite `id` in `remap` and its size in blocks. Car sprites
+/// face up, along +Y.
+pub(crate) fn sprite_image(
+    style: &StyleFile,
+    id: usize,
+    remap: Remap,
+    images: &mut Assets<Image>,
+) -> Option<(Handle<Image>, Vec2)> {
+    let sprite = style.sprite_bgra(id, remap)?;
+    let size = Extent3d {
+        width: sprite.width as u32,
+        height: sprite.height as u32,
+        depth_or_array_layers: 1,
+    };
+
+    let image = images.add(Image::new(
+        size,
+        TextureDimension::D2,
+        sprite.bgra,
+        TextureFormat::Bgra8UnormSrgb,
+        RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+    ));
+
+    Some((
+        image,
+        Vec2::new(sprite.width as f32, sprite.height as f32) / PIXELS_PER_BLOCK,
+    ))
+}
+
+fn drive_cars(
+    time: Res<Time>,
+    keys: Res<ButtonInput<KeyCode>>,
+    collision: Option<Res<CollisionMap>>,
+    mut cars: Query<(&mut Car, &mut Transform), With<PlayerCar>>,
+) {
+    let Some(collision) = collision else {
+        return;
+    };
+    let dt = time.delta_secs().min(MAX_DELTA);
+    if dt <= 0.0 {
+        return;
+    }
+
+    let controls = Controls::from_keys(&keys);
+    for (mut car, mut transform) in &mut cars {
+        car.step(&mut transform, &collision, controls, dt);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::map::file::{
+        tests::test_map_with_blocks, BlockInfo, SlopeDirection, SlopeLevel, SlopeType,
+    };
+
+    const DT: f32 = 1.0 / 60.0;
+
+    /// Ground at level 0 around the world cells x 90..110, y 90..110, with
+    /// `blocks` on top.
+    fn map(blocks: &[(IVec3, BlockInfo)]) -> CollisionMap {
+        let ground = (90..110).flat_map(|x| (90..110).map(move |y| IVec3::new(x, y, 0)));
+        let road = BlockInfo {
+            ground_type: GroundType::Road,
+            ..default()
+        };
+
+        let map = test_map_with_blocks(
+            ground
+                .map(|cell| (cell, road.clone()))
+                .chain(blocks.iter().cloned()),
+        );
+        CollisionMap::from(&map)
+    }
+
+    fn drive(car: &mut Car, transform: &mut Transform, map: &CollisionMap, seconds: f32) {
+        let controls = Controls {
+            throttle: 1.0,
+            ..default()
+        };
+        for _ in 0..(seconds / DT) as u32 {
+            car.step(transform, map, controls, DT);
+        }
+    }
+
+    #[test]
+    fn cars_accelerate_and_steer() {
+        let map = map(&[]);
+        let mut car = Car::new(DEFAULT_SIZE, 0.0);
+        let mut transform = Transform::from_xyz(100.0, 92.0, 0.5);
+
+        drive(&mut car, &mut transform, &map, 1.0);
+        assert!(car.speed > 4.0);
+        assert!(transform.translation.y > 94.0);
+        assert!((transform.translation.x - 100.0).abs() < 1e-4);
+        assert!((transform.translation.z - 0.5).abs() < 1e-4);
+
+        let left = Controls {
+            throttle: 1.0,
+            steering: 1.0,
+            ..default()
+        };
+        car.step(&mut transform, &map, left, DT);
+        assert!(car.heading > 0.0);
+    }
+
+    #[test]
+    fn cars_stop_at_walls() {
+        let wall: Vec<_> = (90..110)
+            .map(|x| {
+                let block = BlockInfo {
+                    ground_type: GroundType::Pavement,
+                    ..default()
+                };
+                (IVec3::new(x, 100, 1), block)
+            })
+            .collect();
+        let map = map(&wall);
+        let mut car = Car::new(DEFAULT_SIZE, 0.0);
+        let mut transform = Transform::from_xyz(95.0, 92.0, 0.5);
+
+        drive(&mut car, &mut transform, &map, 4.0);
+        let front = transform.translation.y + car.half_size.y;
+        assert!(front <= 99.5 + 1e-3, "front at {front}");
+        assert!(front > 99.0);
+        assert!((transform.translation.z - 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn cars_climb_ramps() {
+        let ramp = |level| BlockInfo {
+            ground_type: GroundType::Road,
+            slope_type: SlopeType::Degree26 {
+                direction: SlopeDirection::Up,
+                level,
+            },
+            ..default()
+        };
+        let road = BlockInfo {
+            ground_type: GroundType::Road,
+            ..default()
+        };
+        let mut blocks = vec![
+            (IVec3::new(100, 96, 1), ramp(SlopeLevel::Low)),
+            (IVec3::new(100, 97, 1), ramp(SlopeLevel::High)),
+        ];
+        blocks.extend((98..110).map(|y| (IVec3::new(100, y, 1), road.clone())));
+        let map = map(&blocks);
+
+        let mut car = Car::new(DEFAULT_SIZE, 0.0);
+        let mut transform = Transform::from_xyz(100.0, 92.0, 0.5);
+
+        drive(&mut car, &mut transform, &map, 2.0);
+        assert!(transform.translation.y > 99.0);
+        assert!((transform.translation.z - 1.5).abs() < 1e-4);
+    }
+}
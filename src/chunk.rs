@@ -0,0 +1,125 @@
+//! Chunk layout shared by GTA2 map (GMP) and style (STY) files.
+//!
+//! Both formats start with a 4 byte file type and a `u16` version, followed
+//! by a list of chunks, each made of a 4 byte tag, a `u32` size and the data.
+
+use std::io::{self, Read, Seek, SeekFrom};
+
+use byteorder::{NativeEndian, ReadBytesExt};
+
+#[derive(Debug, Clone)]
+pub struct FileHeader {
+    pub file_type: String,
+    pub version: u16,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChunkHeader {
+    pub tag: String,
+    /// Offset of the chunk data (not the chunk header) from the file start.
+    pub offset: u64,
+    pub size: u32,
+    /// The size runs past the end of the file, only the data up to the end
+    /// is there.
+    pub truncated: bool,
+}
+
+pub const HEADER_SIZE: u64 = 6;
+pub const CHUNK_HEADER_SIZE: u64 = 8;
+
+/// Reads the file header and the headers of all chunks without loading any
+/// chunk data.
+pub fn read_layout<T: Read + Seek>(reader: &mut T) -> io::Result<(FileHeader, Vec<ChunkHeader>)> {
+    reader.seek(SeekFrom::Start(0))?;
+
+    let mut buffer = [0; 4];
+    reader.read_exact(&mut buffer)?;
+    let file_type = String::from_utf8_lossy(&buffer).into_owned();
+    let version = reader.read_u16::<NativeEndian>()?;
+
+    let end = reader.seek(SeekFrom::End(0))?;
+    let mut position = reader.seek(SeekFrom::Start(HEADER_SIZE))?;
+
+    let mut chunks = Vec::new();
+    while position + CHUNK_HEADER_SIZE <= end {
+        reader.read_exact(&mut buffer)?;
+        let size = reader.read_u32::<NativeEndian>()?;
+
+        let offset = position + CHUNK_HEADER_SIZE;
+        chunks.push(ChunkHeader {
+            tag: String::from_utf8_lossy(&buffer).into_owned(),
+            offset,
+            size,
+            truncated: offset + u64::from(size) > end,
+        });
+
+        position = reader.seek(SeekFrom::Start(offset + u64::from(size)))?;
+    }
+
+    Ok((FileHeader { file_type, version }, chunks))
+}
+
+/// Reads the `size` bytes of data of a chunk, failing if the file ends
+/// before them.
+pub fn read_data<T: Read>(reader: &mut T, size: u32) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    reader.take(u64::from(size)).read_to_end(&mut data)?;
+
+    if data.len() < size as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!("chunk of {size} bytes overruns the file end"),
+        ));
+    }
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn file(chunks: &[(&[u8; 4], u32, &[u8])]) -> Vec<u8> {
+        let mut bytes = b"GBMP".to_vec();
+        bytes.extend(500u16.to_ne_bytes());
+        for (tag, size, data) in chunks {
+            bytes.extend(*tag);
+            bytes.extend(size.to_ne_bytes());
+            bytes.extend(*data);
+        }
+        bytes
+    }
+
+    #[test]
+    fn chunks_are_listed_with_their_data_offsets() {
+        let bytes = file(&[(b"ZONE", 2, &[1, 2]), (b"LGHT", 0, &[])]);
+        let (header, chunks) = read_layout(&mut Cursor::new(bytes)).unwrap();
+
+        assert_eq!(header.file_type, "GBMP");
+        assert_eq!(header.version, 500);
+        let layout: Vec<_> = chunks
+            .iter()
+            .map(|chunk| {
+                (
+                    chunk.tag.as_str(),
+                    chunk.offset,
+                    chunk.size,
+                    chunk.truncated,
+                )
+            })
+            .collect();
+        assert_eq!(layout, [("ZONE", 14, 2, false), ("LGHT", 24, 0, false)]);
+    }
+
+    #[test]
+    fn chunks_running_past_the_file_end_are_truncated() {
+        let bytes = file(&[(b"ZONE", 2, &[1, 2]), (b"DMAP", 100, &[0; 10])]);
+        let (_, chunks) = read_layout(&mut Cursor::new(bytes)).unwrap();
+
+        assert!(!chunks[0].truncated);
+        assert!(chunks[1].truncated);
+        assert_eq!(chunks.len(), 2);
+    }
+}
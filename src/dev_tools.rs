@@ -1,19 +1,170 @@
 use bevy::{
     dev_tools::ui_debug_overlay::{DebugUiPlugin, UiDebugOptions},
+    diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin},
     input::common_conditions::input_just_pressed,
     prelude::*,
 };
 
+use crate::{camera::CameraMode, light::ShadowSettings};
+
 pub fn plugin(app: &mut App) {
-    let toggle_system = toggle_debug_ui.run_if(input_just_pressed(TOGGLE_KEY));
+    // the key is also used to move the fly camera
+    let toggle_system = toggle_debug_ui
+        .run_if(input_just_pressed(TOGGLE_KEY))
+        .run_if(not(in_state(CameraMode::Fly)));
 
     // Toggle the debug overlay for UI.
-    app.add_plugins(DebugUiPlugin);
-    app.add_systems(Update, toggle_system);
+    app.add_plugins((DebugUiPlugin, FrameTimeDiagnosticsPlugin));
+    app.init_resource::<FrameTimes>()
+        .add_systems(Startup, spawn_frame_time_text)
+        .add_systems(Update, (toggle_system, update_frame_time));
 }
 
 const TOGGLE_KEY: KeyCode = KeyCode::KeyD;
+/// Frames skipped after toggling the shadows, which are slow while the
+/// pipelines and shadow maps are prepared.
+const SETTLE_FRAMES: u32 = 60;
+
+/// Average frame time in milliseconds with and without shadows to show
+/// their cost, each measured since the shadows were last toggled.
+#[derive(Resource, Debug, Default)]
+struct FrameTimes {
+    with_shadows: Option<f64>,
+    without_shadows: Option<f64>,
+    /// Shadow setting of the current measurement, `None` before the first.
+    measuring: Option<bool>,
+    /// Frames left to skip before measuring.
+    settle: u32,
+    sum: f64,
+    frames: u32,
+}
+
+impl FrameTimes {
+    /// Adds a frame to the average of the current shadow setting, starting a
+    /// new average when the setting changed.
+    fn record(&mut self, shadows: bool, frame_time: f64) {
+        if self.measuring != Some(shadows) {
+            self.measuring = Some(shadows);
+            self.settle = SETTLE_FRAMES;
+            self.sum = 0.0;
+            self.frames = 0;
+        }
+        if self.settle > 0 {
+            self.settle -= 1;
+            return;
+        }
+
+        self.sum += frame_time;
+        self.frames += 1;
+        let average = Some(self.sum / f64::from(self.frames));
+        if shadows {
+            self.with_shadows = average;
+        } else {
+            self.without_shadows = average;
+        }
+    }
+}
+
+#[derive(Component)]
+struct FrameTimeText;
 
 fn toggle_debug_ui(mut options: ResMut<UiDebugOptions>) {
     options.toggle();
 }
+
+fn spawn_frame_time_text(mut commands: Commands) {
+    commands.spawn((
+        FrameTimeText,
+        Text::default(),
+        TextFont {
+            font_size: 14.0,
+            ..default()
+        },
+        Node {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(50.0),
+            left: Val::Percent(35.0),
+            ..default()
+        },
+        BackgroundColor(Color::BLACK.with_alpha(0.6)),
+        Visibility::Hidden,
+    ));
+}
+
+fn update_frame_time(
+    options: Res<UiDebugOptions>,
+    diagnostics: Res<DiagnosticsStore>,
+    shadows: Res<ShadowSettings>,
+    mut frame_times: ResMut<FrameTimes>,
+    mut texts: Query<(&mut Text, &mut Visibility), With<FrameTimeText>>,
+) {
+    let Some(diagnostic) = diagnostics.get(&FrameTimeDiagnosticsPlugin::FRAME_TIME) else {
+        return;
+    };
+    let (Some(frame_time), Some(latest)) = (diagnostic.smoothed(), diagnostic.value()) else {
+        return;
+    };
+
+    frame_times.record(shadows.enabled, latest);
+
+    let format = |time: Option<f64>| time.map_or("-".to_string(), |time| format!("{time:.2} ms"));
+    let cost = frame_times
+        .with_shadows
+        .zip(frame_times.without_shadows)
+        .map_or(String::new(), |(with, without)| {
+            format!(" ({:+.2} ms)", with - without)
+        });
+
+    for (mut text, mut visibility) in &mut texts {
+        *visibility = if options.enabled {
+            Visibility::Visible
+        } else {
+            Visibility::Hidden
+        };
+
+        **text = format!(
+            "Frame time: {frame_time:.2} ms ({:.0} fps)\n\
+             Shadows on: {}, off: {}{cost}",
+            1000.0 / frame_time,
+            format(frame_times.with_shadows),
+            format(frame_times.without_shadows),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(frame_times: &mut FrameTimes, shadows: bool, frame_time: f64, frames: u32) {
+        for _ in 0..frames {
+            frame_times.record(shadows, frame_time);
+        }
+    }
+
+    #[test]
+    fn frames_after_toggling_are_skipped() {
+        let mut frame_times = FrameTimes::default();
+        record(&mut frame_times, true, 50.0, SETTLE_FRAMES);
+        assert_eq!(frame_times.with_shadows, None);
+
+        record(&mut frame_times, true, 10.0, 1);
+        record(&mut frame_times, true, 20.0, 1);
+        assert_eq!(frame_times.with_shadows, Some(15.0));
+    }
+
+    #[test]
+    fn toggling_starts_a_new_average() {
+        let mut frame_times = FrameTimes::default();
+        record(&mut frame_times, true, 10.0, SETTLE_FRAMES + 5);
+        record(&mut frame_times, false, 50.0, SETTLE_FRAMES);
+        record(&mut frame_times, false, 4.0, 5);
+        assert_eq!(frame_times.without_shadows, Some(4.0));
+
+        record(&mut frame_times, true, 50.0, SETTLE_FRAMES);
+        record(&mut frame_times, true, 12.0, 2);
+        // the earlier measurement is kept until the new one settled
+        assert_eq!(frame_times.with_shadows, Some(12.0));
+        assert_eq!(frame_times.without_shadows, Some(4.0));
+    }
+}
@@ -1,13 +1,18 @@
 extern crate byteorder;
 
 mod camera;
+mod car;
+pub mod chunk;
 mod dev_tools;
 mod light;
 pub mod map;
+mod ped;
+mod rng;
 mod style;
+mod traffic;
 mod window;
 
-pub use style::{loader, StyleFile, Tile};
+pub use style::{loader, tile_sheet, writer, StyleFile, Tile};
 
 use bevy::{prelude::*, utils::HashMap};
 
@@ -25,7 +30,15 @@ pub struct Arrie;
 
 impl Plugin for Arrie {
     fn build(&self, app: &mut App) {
-        app.add_plugins((window::plugin, camera::plugin, map::plugin, light::plugin));
+        app.add_plugins((
+            window::plugin,
+            camera::plugin,
+            map::plugin,
+            light::plugin,
+            car::plugin,
+            ped::plugin,
+            traffic::plugin,
+        ));
 
         #[cfg(feature = "dev")]
         app.add_plugins(dev_tools::plugin);
@@ -1,31 +1,534 @@
+//! Sun, ambient light and the lights of the map, following the time of day.
+
 use std::f32::consts::PI;
 
-use bevy::{pbr::CascadeShadowConfigBuilder, prelude::*};
+use bevy::{
+    color::palettes::css::GOLD,
+    input::common_conditions::input_just_pressed,
+    pbr::{CascadeShadowConfig, CascadeShadowConfigBuilder, NotShadowCaster},
+    prelude::*,
+    ui::RelativeCursorPosition,
+};
+use bevy_panorbit_camera::PanOrbitCamera;
+
+use crate::map::{file::UncompressedMap, FaceInfo, Map, MapFileAsset};
 
 pub fn plugin(app: &mut App) {
-    app.add_systems(Startup, setup_main_light);
+    app.init_resource::<TimeOfDay>()
+        .init_resource::<ShadowSettings>()
+        .add_systems(Startup, (setup_main_light, spawn_time_controls))
+        .add_systems(
+            Update,
+            (
+                apply_shadow_settings.run_if(resource_changed::<ShadowSettings>),
+                fit_shadow_cascades,
+                skip_transparent_shadows,
+            ),
+        )
+        .add_systems(
+            Update,
+            (
+                advance_time.run_if(|time: Res<TimeOfDay>| time.running),
+                step_time,
+                toggle_night.run_if(input_just_pressed(NIGHT_KEY)),
+                spawn_map_lights,
+                (update_sun, update_map_lights, update_time_controls)
+                    .run_if(resource_changed::<TimeOfDay>),
+                select_map_lights,
+            )
+                .chain(),
+        );
+}
+
+const EARLIER_KEY: KeyCode = KeyCode::Minus;
+const LATER_KEY: KeyCode = KeyCode::Equal;
+const NIGHT_KEY: KeyCode = KeyCode::KeyN;
+
+/// Real seconds per day while the time is running.
+const DAY_LENGTH: f32 = 120.0;
+/// Hours per key press, a quarter with `Shift`.
+const HOUR_STEP: f32 = 1.0;
+
+/// How far south the sun stands, tilting the shadows north.
+const SUN_TILT: f32 = 0.4;
+const NOON_ILLUMINANCE: f32 = 1_000.0;
+const SUNRISE_ILLUMINANCE: f32 = light_consts::lux::CLEAR_SUNRISE;
+/// Brighter than real moonlight, to still make out the map.
+const MOON_ILLUMINANCE: f32 = 20.0;
+const NOON_COLOR: Color = Color::srgb(1.0, 0.98, 0.94);
+const SUNRISE_COLOR: Color = Color::srgb(1.0, 0.62, 0.38);
+const MOON_COLOR: Color = Color::srgb(0.55, 0.65, 1.0);
+const DAY_AMBIENT: f32 = 150.0;
+const NIGHT_AMBIENT: f32 = 15.0;
+
+/// Lumen of a map light at full intensity.
+const MAP_LIGHT_LUMENS: f32 = 100_000.0;
+/// Map lights lit at once, the ones nearest to the camera focus. Every
+/// visible point light is shaded per pixel, maps have hundreds of them.
+const MAX_MAP_LIGHTS: usize = 64;
+/// Distance the camera focus moves before the lit map lights are chosen
+/// again.
+const MAP_LIGHTS_RESELECT_DISTANCE: f32 = 4.0;
+
+/// Shadows reach this many times the camera height above the ground.
+const SHADOW_REACH_PER_HEIGHT: f32 = 4.0;
+const MIN_SHADOW_REACH: f32 = 30.0;
+/// Diagonal of the map, nothing is further away.
+const MAX_SHADOW_REACH: f32 = 363.0;
+/// Relative change of the reach before the cascades are rebuilt.
+const SHADOW_REACH_TOLERANCE: f32 = 0.1;
+
+const TRACK_WIDTH: f32 = 240.0;
+
+/// Drives the sun, ambient and map lights.
+#[derive(Resource, Debug)]
+pub(crate) struct TimeOfDay {
+    /// Hours since midnight, in `0.0..24.0`.
+    pub hour: f32,
+    pub running: bool,
+    /// Hour to go back to when leaving the night.
+    day_hour: f32,
+}
+
+impl Default for TimeOfDay {
+    fn default() -> Self {
+        Self {
+            hour: 9.0,
+            running: false,
+            day_hour: 9.0,
+        }
+    }
 }
 
-fn setup_main_light(mut commands: Commands) {
+impl TimeOfDay {
+    fn set_hour(&mut self, hour: f32) {
+        self.hour = hour.rem_euclid(24.0);
+    }
+
+    /// Sun angle, 0 at sunrise at 6:00 and π at sunset at 18:00.
+    fn sun_angle(&self) -> f32 {
+        (self.hour - 6.0) / 12.0 * PI
+    }
+
+    /// From 0 in daylight to 1 at night, blending around sunrise and sunset.
+    pub fn darkness(&self) -> f32 {
+        let elevation = self.sun_angle().sin();
+        1.0 - ((elevation + 0.1) / 0.3).clamp(0.0, 1.0)
+    }
+}
+
+/// Whether the sun casts shadows, toggled in the time controls.
+#[derive(Resource, Debug, Default)]
+pub(crate) struct ShadowSettings {
+    pub enabled: bool,
+}
+
+#[derive(Component)]
+struct Sun;
+
+/// Light of the map, shining at night.
+#[derive(Component)]
+struct MapLight {
+    lumens: f32,
+}
+
+#[derive(Component)]
+struct TimeText;
+
+#[derive(Component)]
+struct PlayText;
+
+#[derive(Component)]
+struct ShadowText;
+
+#[derive(Component, Default)]
+struct TimeTrack {
+    /// Whether dragging disabled the orbit camera.
+    paused_camera: bool,
+}
+
+#[derive(Component)]
+struct TimeHandle;
+
+fn setup_main_light(mut commands: Commands, shadows: Res<ShadowSettings>) {
     commands.spawn((
+        Sun,
         DirectionalLight {
             illuminance: light_consts::lux::CLEAR_SUNRISE,
-            shadows_enabled: false,
+            shadows_enabled: shadows.enabled,
             ..default()
         },
-        Transform {
-            translation: Vec3::new(0.0, 12.0, 0.0),
-            rotation: Quat::from_rotation_x(-PI / 4.),
-            ..default()
-        },
-        // The default cascade config is designed to handle large scenes.
-        // As this example has a much smaller world, we can tighten the shadow
-        // bounds for better visual quality.
-        CascadeShadowConfigBuilder {
-            first_cascade_far_bound: 10.0,
-            maximum_distance: 28.0,
-            ..default()
-        }
-        .build(),
+        Transform::from_rotation(Quat::from_rotation_x(-PI / 4.)),
+        cascades(MIN_SHADOW_REACH),
     ));
 }
+
+fn spawn_time_controls(mut commands: Commands) {
+    let font = TextFont {
+        font_size: 14.0,
+        ..default()
+    };
+
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(10.0),
+                left: Val::Percent(35.0),
+                align_items: AlignItems::Center,
+                column_gap: Val::Px(10.0),
+                padding: UiRect::all(Val::Px(6.0)),
+                ..default()
+            },
+            BackgroundColor(Color::BLACK.with_alpha(0.6)),
+        ))
+        .with_children(|panel| {
+            panel
+                .spawn((Node::default(), BackgroundColor(Color::NONE)))
+                .with_child((PlayText, Text::default(), font.clone()))
+                .observe(toggle_running);
+
+            panel
+                .spawn((
+                    TimeTrack::default(),
+                    Node {
+                        width: Val::Px(TRACK_WIDTH),
+                        height: Val::Px(12.0),
+                        ..default()
+                    },
+                    BackgroundColor(Color::srgb(0.25, 0.25, 0.3)),
+                    RelativeCursorPosition::default(),
+                ))
+                .with_child((
+                    TimeHandle,
+                    Node {
+                        position_type: PositionType::Absolute,
+                        width: Val::Px(4.0),
+                        height: Val::Percent(100.0),
+                        ..default()
+                    },
+                    BackgroundColor(GOLD.into()),
+                    PickingBehavior::IGNORE,
+                ))
+                .observe(scrub_time::<Down>)
+                .observe(scrub_time::<Drag>)
+                .observe(start_scrub)
+                .observe(end_scrub);
+
+            panel.spawn((TimeText, Text::default(), font.clone()));
+
+            panel
+                .spawn((Node::default(), BackgroundColor(Color::NONE)))
+                .with_child((ShadowText, Text::default(), font))
+                .observe(toggle_shadows);
+        });
+}
+
+fn advance_time(time: Res<Time>, mut time_of_day: ResMut<TimeOfDay>) {
+    let hour = time_of_day.hour + time.delta_secs() * 24.0 / DAY_LENGTH;
+    time_of_day.set_hour(hour);
+}
+
+fn step_time(keys: Res<ButtonInput<KeyCode>>, mut time_of_day: ResMut<TimeOfDay>) {
+    let direction = match (keys.just_pressed(EARLIER_KEY), keys.just_pressed(LATER_KEY)) {
+        (true, false) => -1.0,
+        (false, true) => 1.0,
+        _ => return,
+    };
+
+    let step = if keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        HOUR_STEP / 4.0
+    } else {
+        HOUR_STEP
+    };
+
+    let hour = time_of_day.hour + direction * step;
+    time_of_day.set_hour(hour);
+}
+
+/// Switches between midnight and the hour of day left.
+fn toggle_night(mut time_of_day: ResMut<TimeOfDay>) {
+    if time_of_day.darkness() < 0.5 {
+        time_of_day.day_hour = time_of_day.hour;
+        time_of_day.hour = 0.0;
+    } else {
+        time_of_day.hour = time_of_day.day_hour;
+    }
+}
+
+fn toggle_running(_click: Trigger<Pointer<Click>>, mut time_of_day: ResMut<TimeOfDay>) {
+    time_of_day.running = !time_of_day.running;
+}
+
+fn scrub_time<E: std::fmt::Debug + Clone + Reflect>(
+    trigger: Trigger<Pointer<E>>,
+    tracks: Query<&RelativeCursorPosition, With<TimeTrack>>,
+    mut time_of_day: ResMut<TimeOfDay>,
+) {
+    let Some(cursor) = tracks
+        .get(trigger.entity())
+        .ok()
+        .and_then(|track| track.normalized)
+    else {
+        return;
+    };
+
+    // the end of the track is the next midnight
+    let hour = cursor.x.clamp(0.0, 0.999) * 24.0;
+    time_of_day.set_hour(hour);
+}
+
+/// Keeps the orbit camera from turning while dragging on the track.
+fn start_scrub(
+    trigger: Trigger<Pointer<DragStart>>,
+    mut tracks: Query<&mut TimeTrack>,
+    mut cameras: Query<&mut PanOrbitCamera>,
+) {
+    let Ok(mut track) = tracks.get_mut(trigger.entity()) else {
+        return;
+    };
+
+    for mut camera in &mut cameras {
+        if camera.enabled {
+            camera.enabled = false;
+            track.paused_camera = true;
+        }
+    }
+}
+
+fn end_scrub(
+    trigger: Trigger<Pointer<DragEnd>>,
+    mut tracks: Query<&mut TimeTrack>,
+    mut cameras: Query<&mut PanOrbitCamera>,
+) {
+    let Ok(mut track) = tracks.get_mut(trigger.entity()) else {
+        return;
+    };
+
+    if std::mem::take(&mut track.paused_camera) {
+        for mut camera in &mut cameras {
+            camera.enabled = true;
+        }
+    }
+}
+
+fn toggle_shadows(_click: Trigger<Pointer<Click>>, mut shadows: ResMut<ShadowSettings>) {
+    shadows.enabled = !shadows.enabled;
+}
+
+fn apply_shadow_settings(
+    shadows: Res<ShadowSettings>,
+    mut suns: Query<&mut DirectionalLight, With<Sun>>,
+    mut texts: Query<&mut Text, With<ShadowText>>,
+) {
+    for mut light in &mut suns {
+        light.shadows_enabled = shadows.enabled;
+    }
+
+    for mut text in &mut texts {
+        **text = format!("Shadows {}", if shadows.enabled { "on" } else { "off" });
+    }
+}
+
+/// Covers what the camera sees with the shadow cascades, a few blocks when
+/// close to the ground up to the whole map from high above.
+fn fit_shadow_cascades(
+    cameras: Query<&GlobalTransform, With<PanOrbitCamera>>,
+    mut suns: Query<&mut CascadeShadowConfig, With<Sun>>,
+    mut reach: Local<f32>,
+) {
+    let Some(camera) = cameras.iter().next() else {
+        return;
+    };
+
+    let new_reach = (camera.translation().z * SHADOW_REACH_PER_HEIGHT)
+        .clamp(MIN_SHADOW_REACH, MAX_SHADOW_REACH);
+    if (new_reach - *reach).abs() <= *reach * SHADOW_REACH_TOLERANCE {
+        return;
+    }
+    *reach = new_reach;
+
+    for mut config in &mut suns {
+        *config = cascades(new_reach);
+    }
+}
+
+fn cascades(reach: f32) -> CascadeShadowConfig {
+    CascadeShadowConfigBuilder {
+        maximum_distance: reach,
+        first_cascade_far_bound: reach / 10.0,
+        ..default()
+    }
+    .build()
+}
+
+/// Flat faces may be see-through, their whole quad would cast a shadow.
+fn skip_transparent_shadows(
+    mut commands: Commands,
+    faces: Query<(Entity, &FaceInfo), Added<FaceInfo>>,
+) {
+    for (entity, face) in &faces {
+        if face.flat {
+            commands.entity(entity).insert(NotShadowCaster);
+        }
+    }
+}
+
+/// Moves the sun from east to west, and the moon at night.
+fn update_sun(
+    time_of_day: Res<TimeOfDay>,
+    mut suns: Query<(&mut DirectionalLight, &mut Transform), With<Sun>>,
+    mut ambient: ResMut<AmbientLight>,
+) {
+    let angle = time_of_day.sun_angle();
+    let elevation = angle.sin();
+    let daylight = 1.0 - time_of_day.darkness();
+
+    let sun = Vec3::new(angle.cos(), -SUN_TILT, elevation).normalize();
+    let towards_light = if elevation >= 0.0 { sun } else { -sun };
+
+    let height = elevation.clamp(0.0, 1.0);
+    let sun_color = SUNRISE_COLOR.mix(&NOON_COLOR, height);
+    let sun_illuminance = SUNRISE_ILLUMINANCE.lerp(NOON_ILLUMINANCE, height);
+
+    for (mut light, mut transform) in &mut suns {
+        light.color = MOON_COLOR.mix(&sun_color, daylight);
+        light.illuminance = MOON_ILLUMINANCE.lerp(sun_illuminance, daylight);
+        transform.look_to(-towards_light, Vec3::Z);
+    }
+
+    ambient.color = MOON_COLOR.mix(&Color::WHITE, daylight);
+    ambient.brightness = NIGHT_AMBIENT.lerp(DAY_AMBIENT, daylight);
+}
+
+fn spawn_map_lights(
+    mut commands: Commands,
+    mut events: EventReader<AssetEvent<MapFileAsset>>,
+    map: Option<Res<Map>>,
+    map_assets: Res<Assets<MapFileAsset>>,
+    map_lights: Query<Entity, With<MapLight>>,
+    time_of_day: Res<TimeOfDay>,
+) {
+    let Some(map) = map else {
+        return;
+    };
+    if !events
+        .read()
+        .any(|event| event.is_loaded_with_dependencies(&map.asset))
+    {
+        return;
+    }
+    let Some(map_file) = map_assets.get(&map.asset) else {
+        return;
+    };
+
+    for entity in &map_lights {
+        commands.entity(entity).despawn();
+    }
+
+    let lights = &map_file.0.lights;
+    if lights.len() > MAX_MAP_LIGHTS {
+        info!(
+            "Map has {} lights, only the {MAX_MAP_LIGHTS} nearest to the camera are lit",
+            lights.len()
+        );
+    }
+
+    let darkness = time_of_day.darkness();
+    for light in lights {
+        let [r, g, b] = light.rgb();
+        let lumens = f32::from(light.intensity) / 255.0 * MAP_LIGHT_LUMENS;
+
+        // file positions are measured from the corner of the first block,
+        // rows from the top
+        let translation = Vec3::new(
+            light.x - 0.5,
+            UncompressedMap::world_y(0) as f32 - (light.y - 0.5),
+            light.z - 0.5,
+        );
+
+        commands.spawn((
+            MapLight { lumens },
+            PointLight {
+                color: Color::srgb_u8(r, g, b),
+                intensity: lumens * darkness,
+                range: light.radius,
+                ..default()
+            },
+            Transform::from_translation(translation),
+            // lit by select_map_lights
+            Visibility::Hidden,
+        ));
+    }
+}
+
+fn update_map_lights(
+    time_of_day: Res<TimeOfDay>,
+    mut map_lights: Query<(&MapLight, &mut PointLight)>,
+) {
+    let darkness = time_of_day.darkness();
+
+    for (map_light, mut light) in &mut map_lights {
+        light.intensity = map_light.lumens * darkness;
+    }
+}
+
+/// Lights the [`MAX_MAP_LIGHTS`] map lights nearest to the camera focus at
+/// night, the others are hidden.
+fn select_map_lights(
+    time_of_day: Res<TimeOfDay>,
+    cameras: Query<&PanOrbitCamera>,
+    added: Query<(), Added<MapLight>>,
+    mut map_lights: Query<(&Transform, &mut Visibility), With<MapLight>>,
+    mut last_focus: Local<Option<Vec3>>,
+) {
+    let Some(focus) = cameras.iter().next().map(|camera| camera.focus) else {
+        return;
+    };
+    let moved = last_focus.is_none_or(|last| last.distance(focus) > MAP_LIGHTS_RESELECT_DISTANCE);
+    if !moved && added.is_empty() && !time_of_day.is_changed() {
+        return;
+    }
+    *last_focus = Some(focus);
+
+    let mut lights: Vec<_> = map_lights.iter_mut().collect();
+    lights.sort_by(|(a, _), (b, _)| {
+        let distance = |transform: &Transform| transform.translation.distance_squared(focus);
+        distance(a).total_cmp(&distance(b))
+    });
+
+    let night = time_of_day.darkness() > 0.0;
+    for (i, (_, mut visibility)) in lights.into_iter().enumerate() {
+        visibility.set_if_neq(if night && i < MAX_MAP_LIGHTS {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        });
+    }
+}
+
+fn update_time_controls(
+    time_of_day: Res<TimeOfDay>,
+    mut time_texts: Query<&mut Text, With<TimeText>>,
+    mut play_texts: Query<&mut Text, (With<PlayText>, Without<TimeText>)>,
+    mut handles: Query<&mut Node, With<TimeHandle>>,
+) {
+    let minutes = (time_of_day.hour * 60.0) as u32;
+    let period = if time_of_day.darkness() < 0.5 {
+        "day"
+    } else {
+        "night"
+    };
+
+    for mut text in &mut time_texts {
+        **text = format!("{:02}:{:02} {period}", minutes / 60, minutes % 60);
+    }
+
+    for mut text in &mut play_texts {
+        **text = if time_of_day.running { "Pause" } else { "Play" }.to_string();
+    }
+
+    for mut node in &mut handles {
+        node.left = Val::Percent(time_of_day.hour / 24.0 * 100.0);
+    }
+}
@@ -5,23 +5,20 @@ use std::f32::consts::TAU;
 pub use face::Face;
 
 use crate::map::{
-    file::{self, BlockInfo, DiagonalType, SlopeDirection},
-    Flatness, MyExtension, TextureIndex,
+    file::{self, BlockInfo, DiagonalType, PartialPosition, SlopeDirection, SlopeLevel, SlopeType},
+    geometry::{self, FaceMesh, Model, Part, PARTIAL_OFFSET},
+    FaceInfo, MapMaterial, MyExtension, TextureIndex,
 };
 
 use bevy::{
-    asset::Assets,
-    ecs::{
-        component::Component,
-        system::{Commands, Res, ResMut},
-    },
-    gltf::{Gltf, GltfMesh},
+    asset::{Assets, Handle},
+    ecs::{component::Component, system::Commands, system::Resource},
     hierarchy::{BuildChildren, ChildBuild},
     math::{Quat, Vec3},
     pbr::{ExtendedMaterial, MeshMaterial3d, StandardMaterial},
-    render::{alpha::AlphaMode, mesh::Mesh3d, view::Visibility},
+    render::{alpha::AlphaMode, mesh::Mesh, mesh::Mesh3d, view::Visibility},
     transform::components::Transform,
-    utils::default,
+    utils::{default, HashMap},
 };
 
 #[derive(Component, Debug)]
@@ -29,802 +26,375 @@ pub(crate) struct Block {
     pub pos: Position,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Position {
     pub x: u8,
-    pub y: u8,
+    /// World Y, from 1 for the bottom row of the map to 256 for the top row.
+    pub y: u16,
     pub z: u8,
 }
 
-#[derive(Component)]
-struct Normal;
-
-pub fn spawn_normal(
-    pos: Position,
-    voxel: &BlockInfo,
-    block_gltf: &Gltf,
-    assets_gltfmesh: &Res<Assets<GltfMesh>>,
-    textures: &Res<TextureIndex>,
-    ext_materials: &mut ResMut<Assets<ExtendedMaterial<StandardMaterial, MyExtension>>>,
-    commands: &mut Commands,
-) {
-    let mut get_face = |face: &file::Face, name| {
-        if face.tile_id == 0 {
-            return None;
+impl Position {
+    /// Position of the block at column `x`, `row` and level `z` of the map
+    /// file.
+    pub fn from_file(x: usize, row: usize, z: usize) -> Self {
+        Self {
+            x: x as u8,
+            y: file::UncompressedMap::world_y(row as i32) as u16,
+            z: z as u8,
         }
+    }
 
-        let handle = block_gltf
-            .named_meshes
-            .get(name)
-            .unwrap_or_else(|| panic!("named mesh [{name}] to be found"))
-            .clone();
-
-        let mesh = &assets_gltfmesh
-            .get(&handle)
-            .unwrap_or_else(|| panic!("mesh [{name}] to exist"))
-            .primitives[0]
-            .mesh;
-
-        let base_color_texture = textures
-            .index
-            .get(&face.tile_id)
-            .unwrap_or_else(|| panic!("texture for tile_id: {} to be found", face.tile_id))
-            .clone();
-
-        // TODO could be optimized by re-using ext material with same properties
-        let ext_material = ext_materials.add(ExtendedMaterial {
-            base: StandardMaterial {
-                base_color_texture: Some(base_color_texture),
-                // NOTE: transperency is only allowed in flat faces
-                alpha_mode: if face.flat {
-                    AlphaMode::AlphaToCoverage
-                } else {
-                    AlphaMode::Opaque
-                },
-                ..default()
-            },
-            extension: MyExtension::new(face.flip, face.rotate.clockwise_rad()),
-        });
-
-        Some(Face {
-            mesh: Mesh3d(mesh.clone()),
-            material: MeshMaterial3d(ext_material),
-        })
-    };
-
-    let lid = get_face(&voxel.lid, "block.lid");
-    let (left, right, lr_flat) = match (voxel.left.flat, voxel.right.flat) {
-        (true, true) => (voxel.left.clone(), voxel.right.clone(), Flatness::Both),
-        (true, false) => {
-            let mut right = voxel.right.clone();
-            right.tile_id = voxel.left.tile_id;
-            right.flat = voxel.left.flat;
-            (voxel.left.clone(), right, Flatness::Left)
-        }
-        (false, true) => {
-            let mut left = voxel.left.clone();
-            left.tile_id = voxel.right.tile_id;
-            left.flat = voxel.right.flat;
-            (left, voxel.right.clone(), Flatness::Right)
-        }
-        (false, false) => (voxel.left.clone(), voxel.right.clone(), Flatness::None),
-    };
+    /// Column, row and level in the map file, rows count down from the top,
+    /// `None` outside of the map.
+    pub fn file_coordinates(&self) -> Option<(usize, usize, usize)> {
+        let row = file::UncompressedMap::row(i32::from(self.y));
+        let in_map = (0..file::UncompressedMap::Y as i32).contains(&row)
+            && usize::from(self.z) < file::UncompressedMap::Z;
 
-    let left = get_face(&left, "block.left");
-    let right = get_face(&right, "block.right");
+        in_map.then(|| (usize::from(self.x), row as usize, usize::from(self.z)))
+    }
+}
 
-    let (top, bottom, tb_flat) = match (voxel.top.flat, voxel.bottom.flat) {
-        (true, true) => (voxel.top.clone(), voxel.bottom.clone(), Flatness::Both),
-        (true, false) => {
-            let mut bottom = voxel.bottom.clone();
-            bottom.tile_id = voxel.top.tile_id;
-            bottom.flat = voxel.top.flat;
-            (voxel.top.clone(), bottom, Flatness::Left)
-        }
-        (false, true) => {
-            let mut top = voxel.top.clone();
-            top.tile_id = voxel.bottom.tile_id;
-            top.flat = voxel.bottom.flat;
-            (top, voxel.bottom.clone(), Flatness::Right)
+impl From<Position> for Vec3 {
+    fn from(pos: Position) -> Self {
+        Vec3 {
+            x: f32::from(pos.x),
+            y: f32::from(pos.y),
+            z: f32::from(pos.z),
         }
-        (false, false) => (voxel.top.clone(), voxel.bottom.clone(), Flatness::None),
-    };
-
-    let top = get_face(&top, "block.top");
-    let bottom = get_face(&bottom, "block.bottom");
-
-    let transform = Transform::from_translation(Vec3::from(pos));
-    commands
-        .spawn((Block { pos }, Normal, transform, Visibility::Visible))
-        .with_children(|parent| {
-            lid.map(|face| parent.spawn((face::Lid, face)));
-
-            match lr_flat {
-                Flatness::None => {
-                    left.map(|face| parent.spawn((face::Left, face)));
-                    right.map(|face| parent.spawn((face::Right, face)));
-                }
-                Flatness::Left => {
-                    left.map(|face| parent.spawn((face::Left, face)));
-                    right.map(|face| {
-                        parent.spawn((face::Right, face, Transform::from_xyz(-1.0, 0.0, 0.0)))
-                    });
-                }
-                Flatness::Right => {
-                    right.map(|face| parent.spawn((face::Right, face)));
-                    left.map(|face| {
-                        parent.spawn((face::Left, face, Transform::from_xyz(1.0, 0.0, 0.0)))
-                    });
-                }
-                Flatness::Both => {
-                    left.clone().map(|face| parent.spawn((face::Left, face)));
-                    right.clone().map(|face| {
-                        parent.spawn((face::Right, face, Transform::from_xyz(-1.0, 0.0, 0.0)))
-                    });
-                    right.map(|face| parent.spawn((face::Right, face)));
-                    left.map(|face| {
-                        parent.spawn((face::Left, face, Transform::from_xyz(1.0, 0.0, 0.0)))
-                    });
-                }
-            }
-
-            match tb_flat {
-                Flatness::None => {
-                    top.map(|face| parent.spawn((face::Top, face)));
-                    bottom.map(|face| parent.spawn((face::Bottom, face)));
-                }
-                Flatness::Left => {
-                    top.map(|face| parent.spawn((face::Top, face)));
-                    bottom.map(|face| {
-                        parent.spawn((face::Bottom, face, Transform::from_xyz(0.0, 1.0, 0.0)))
-                    });
-                }
-                Flatness::Right => {
-                    top.map(|face| {
-                        parent.spawn((face::Top, face, Transform::from_xyz(0.0, -1.0, 0.0)))
-                    });
-                    bottom.map(|face| parent.spawn((face::Bottom, face)));
-                }
-                Flatness::Both => {
-                    top.clone().map(|face| parent.spawn((face::Top, face)));
-                    bottom.clone().map(|face| {
-                        parent.spawn((face::Bottom, face, Transform::from_xyz(0.0, 1.0, 0.0)))
-                    });
-                    top.map(|face| {
-                        parent.spawn((face::Top, face, Transform::from_xyz(0.0, -1.0, 0.0)))
-                    });
-                    bottom.map(|face| parent.spawn((face::Bottom, face)));
-                }
-            }
-        });
+    }
 }
 
 #[derive(Component)]
-struct Diagonal;
-
-pub fn spawn_diagonal(
-    pos: Position,
-    voxel: &BlockInfo,
-    diagonal_type: &DiagonalType,
-    block_gltf: &Gltf,
-    assets_gltfmesh: &Res<Assets<GltfMesh>>,
-    textures: &Res<TextureIndex>,
-    ext_materials: &mut ResMut<Assets<ExtendedMaterial<StandardMaterial, MyExtension>>>,
-    commands: &mut Commands,
-) {
-    let mut get_face = |face: &file::Face, name, angle| {
-        if face.tile_id == 0 {
-            return None;
-        }
-
-        let handle = block_gltf
-            .named_meshes
-            .get(name)
-            .unwrap_or_else(|| panic!("named mesh [{name}] to be found"))
-            .clone();
-
-        let mesh = &assets_gltfmesh
-            .get(&handle)
-            .unwrap_or_else(|| panic!("mesh [{name}] to exist"))
-            .primitives[0]
-            .mesh;
+struct Normal;
 
-        let base_color_texture = textures.index.get(&face.tile_id).cloned();
+#[derive(Component)]
+struct Diagonal;
 
-        if base_color_texture.is_none() {
-            dbg!(format!("texture for tile_id: {} to be found", face.tile_id));
-        }
+#[derive(Component)]
+struct ThreeSided;
 
-        let rotation = if face.flip {
-            face.rotate.clockwise_rad() - angle
-        } else {
-            face.rotate.clockwise_rad() + angle
-        };
+#[derive(Component)]
+struct FourSided;
 
-        // TODO could be optimized by re-using ext material with same properties
-        let ext_material = ext_materials.add(ExtendedMaterial {
-            base: StandardMaterial {
-                base_color_texture,
-                // NOTE: transperency is only allowed in flat faces
-                alpha_mode: if face.flat {
-                    AlphaMode::AlphaToCoverage
-                } else {
-                    AlphaMode::Opaque
-                },
-                ..default()
-            },
-            extension: MyExtension::new(face.flip, rotation),
-        });
+#[derive(Component)]
+struct Partial;
 
-        Some(Face {
-            mesh: Mesh3d(mesh.clone()),
-            material: MeshMaterial3d(ext_material),
-        })
-    };
+#[derive(Component)]
+struct PartialCorner;
 
-    let (angle, diagonal, right, top) = match diagonal_type {
-        DiagonalType::DownLeft => (0.0, &voxel.left, &voxel.right, &voxel.top),
-        DiagonalType::DownRight => (0.25 * TAU, &voxel.right, &voxel.top, &voxel.left),
-        DiagonalType::UpRight => (0.5 * TAU, &voxel.right, &voxel.left, &voxel.bottom),
-        DiagonalType::UpLeft => (0.75 * TAU, &voxel.left, &voxel.bottom, &voxel.right),
-    };
+#[derive(Component)]
+struct PartialCentre;
 
-    // NOTE: angle is used to compensate the rotation of the lid's UV map which
-    // occurs when rotating the whole blog
-    let lid = get_face(&voxel.lid, "diagonal.lid", angle);
-    let diagonal = get_face(diagonal, "diagonal.front", 0.0);
-    let right = get_face(right, "block.right", 0.0);
-    let top = get_face(top, "block.top", 0.0);
-
-    let transform =
-        Transform::from_translation(Vec3::from(pos)).with_rotation(Quat::from_rotation_z(angle));
-    commands
-        .spawn((Block { pos }, Diagonal, transform, Visibility::Visible))
-        .with_children(|parent| {
-            // NOTE: diagonals can not be flat
-            lid.map(|face| parent.spawn((face::Lid, face)));
-            diagonal.map(|face| parent.spawn(face));
-            right.map(|face| parent.spawn(face));
-            top.map(|face| parent.spawn(face));
-        });
-}
+#[derive(Component)]
+struct Degree7;
 
 #[derive(Component)]
-struct Partial;
+struct Degree26;
 
-pub fn spawn_partial(
-    pos: Position,
-    voxel: &BlockInfo,
-    partial_pos: &file::PartialPosition,
-    block_gltf: &Gltf,
-    assets_gltfmesh: &Res<Assets<GltfMesh>>,
-    textures: &Res<TextureIndex>,
-    ext_materials: &mut ResMut<Assets<ExtendedMaterial<StandardMaterial, MyExtension>>>,
-    commands: &mut Commands,
-) {
-    let mut get_face = |face: &file::Face, name| {
-        if face.tile_id == 0 {
-            return None;
-        }
+#[derive(Component)]
+struct Degree45;
 
-        let handle = block_gltf
-            .named_meshes
-            .get(name)
-            .unwrap_or_else(|| panic!("named mesh [{name}] to be found"))
-            .clone();
-
-        let mesh = &assets_gltfmesh
-            .get(&handle)
-            .unwrap_or_else(|| panic!("mesh [{name}] to exist"))
-            .primitives[0]
-            .mesh;
-
-        let base_color_texture = textures
-            .index
-            .get(&face.tile_id)
-            .unwrap_or_else(|| panic!("texture for tile_id: {} to be found", face.tile_id))
-            .clone();
-
-        let mut rotation = face.rotate.clockwise_rad();
-        // NOTE: we need to compensate the UV map rotation of the lid that
-        // occurs while rotating the base 3D model
-        if let file::FaceKind::Lid = face.kind {
-            let compensation = match partial_pos {
-                file::PartialPosition::Bottom => 0.0,
-                file::PartialPosition::Right => 0.75 * TAU,
-                file::PartialPosition::Top => 0.5 * TAU,
-                file::PartialPosition::Left => 0.25 * TAU,
-            };
+/// A face of a block model with the tile it shows.
+#[derive(Debug, Clone)]
+pub(crate) struct ModelFace {
+    pub face: file::Face,
+    pub mesh: FaceMesh,
+    /// Rotation of the model around Z, which also turns the UV map of lids.
+    model_angle: f32,
+}
 
-            if face.flip {
-                rotation += compensation;
+impl ModelFace {
+    /// Flip, rotation and lighting of the tile.
+    pub fn extension(&self) -> MyExtension {
+        // NOTE: the tile is flipped before it is rotated, so the compensation
+        // turns the other way for flipped lids
+        let mut rotation = self.face.rotate.clockwise_rad();
+        if let file::FaceKind::Lid = self.face.kind {
+            if self.face.flip {
+                rotation -= self.model_angle;
             } else {
-                rotation -= compensation;
-            };
-        };
-
-        // TODO could be optimized by re-using ext material with same properties
-        let ext_material = ext_materials.add(ExtendedMaterial {
-            base: StandardMaterial {
-                base_color_texture: Some(base_color_texture),
-                // NOTE: transperency is only allowed in flat faces
-                alpha_mode: if face.flat {
-                    AlphaMode::AlphaToCoverage
-                } else {
-                    AlphaMode::Opaque
-                },
-                ..default()
-            },
-            extension: MyExtension::new(face.flip, rotation),
-        });
-
-        Some(Face {
-            mesh: Mesh3d(mesh.clone()),
-            material: MeshMaterial3d(ext_material),
-        })
-    };
-
-    const PARTIAL_POS_OFFSET: f32 = (64.0 - 24.0) / 64.0 / 2.0;
-    let transform = Transform::from_translation(Vec3::from(pos));
-    let transform = match partial_pos {
-        file::PartialPosition::Left => transform
-            .mul_transform(Transform::from_xyz(-PARTIAL_POS_OFFSET, 0.0, 0.0))
-            .with_rotation(Quat::from_rotation_z(0.25 * TAU)),
-        file::PartialPosition::Right => transform
-            .mul_transform(Transform::from_xyz(PARTIAL_POS_OFFSET, 0.0, 0.0))
-            .with_rotation(Quat::from_rotation_z(0.75 * TAU)),
-        file::PartialPosition::Top => transform
-            .mul_transform(Transform::from_xyz(0.0, PARTIAL_POS_OFFSET, 0.0))
-            .with_rotation(Quat::from_rotation_z(0.5 * TAU)),
-        file::PartialPosition::Bottom => {
-            transform.mul_transform(Transform::from_xyz(0.0, -PARTIAL_POS_OFFSET, 0.0))
+                rotation += self.model_angle;
+            }
         }
-    };
-
-    let left = &voxel.left;
-    let right = &voxel.right;
-    let top = &voxel.top;
-    let bottom = &voxel.bottom;
-
-    let (left, top, right, bottom) = match partial_pos {
-        file::PartialPosition::Bottom => (left, top, right, bottom),
-        file::PartialPosition::Right => (top, right, bottom, left),
-        file::PartialPosition::Top => (right, bottom, left, top),
-        file::PartialPosition::Left => (bottom, left, top, right),
-    };
-
-    let left_flat = left.flat.then(|| {
-        let mut right = right.clone();
-        right.flat = true;
-        get_face(&right, "partial.right")
-    });
-
-    let right_flat = right.flat.then(|| {
-        let mut left = left.clone();
-        left.flat = true;
-        get_face(&left, "partial.left")
-    });
-
-    let top_flat = top.flat.then(|| {
-        let mut bottom = bottom.clone();
-        bottom.flat = true;
-        get_face(&bottom, "partial.bottom")
-    });
-
-    let bottom_flat = bottom.flat.then(|| {
-        let mut top = top.clone();
-        top.flat = true;
-        get_face(&top, "partial.top")
-    });
 
-    let lid = get_face(&voxel.lid, "partial.lid");
-    let left = get_face(&left, "partial.left");
-    let right = get_face(&right, "partial.right");
-    let top = get_face(&top, "partial.top");
-    let bottom = get_face(&bottom, "partial.bottom");
-
-    commands
-        .spawn((Block { pos }, Partial, transform, Visibility::Visible))
-        .with_children(|parent| {
-            lid.map(|face| parent.spawn((face::Lid, face)));
-
-            match (left_flat, right_flat) {
-                (None, Some(right_flat)) => {
-                    right.map(|face| parent.spawn((face::Right, face)));
-                    right_flat.map(|face| {
-                        parent.spawn((face::Right, face, Transform::from_xyz(1.0, 0.0, 0.0)))
-                    });
-                }
-                (Some(left_flat), None) => {
-                    left.map(|face| parent.spawn((face::Left, face)));
-                    left_flat.map(|face| {
-                        parent.spawn((face::Left, face, Transform::from_xyz(-1.0, 0.0, 0.0)))
-                    });
-                }
-                (left_flat, right_flat) => {
-                    left.map(|face| parent.spawn((face::Left, face)));
-                    left_flat.flatten().map(|face| {
-                        parent.spawn((face::Left, face, Transform::from_xyz(-1.0, 0.0, 0.0)))
-                    });
-                    right.map(|face| parent.spawn((face::Right, face)));
-                    right_flat.flatten().map(|face| {
-                        parent.spawn((face::Right, face, Transform::from_xyz(1.0, 0.0, 0.0)))
-                    });
-                }
-            };
+        MyExtension::new(self.face.flip, rotation).with_lighting(self.face.lighting)
+    }
+}
 
-            const FLAT_OFFSET: f32 = 24.0 / 64.0;
-            match (top_flat, bottom_flat) {
-                (None, Some(bottom_flat)) => {
-                    bottom.map(|face| parent.spawn((face::Bottom, face)));
-                    bottom_flat.map(|face| {
-                        parent.spawn((
-                            face::Bottom,
-                            face,
-                            Transform::from_xyz(0.0, -FLAT_OFFSET, 0.0),
-                        ))
-                    });
-                }
-                (Some(top_flat), None) => {
-                    top.map(|face| parent.spawn((face::Top, face)));
-                    top_flat.map(|face| {
-                        parent.spawn((face::Top, face, Transform::from_xyz(0.0, FLAT_OFFSET, 0.0)))
-                    });
-                }
-                (top_flat, bottom_flat) => {
-                    top.map(|face| parent.spawn((face::Top, face)));
-                    top_flat.flatten().map(|face| {
-                        parent.spawn((face::Top, face, Transform::from_xyz(0.0, FLAT_OFFSET, 0.0)))
-                    });
-
-                    bottom.map(|face| parent.spawn((face::Bottom, face)));
-                    bottom_flat.flatten().map(|face| {
-                        parent.spawn((
-                            face::Bottom,
-                            face,
-                            Transform::from_xyz(0.0, -FLAT_OFFSET, 0.0),
-                        ))
-                    });
-                }
-            }
-        });
+/// Faces of a block, placed relative to the block centre by `transform`.
+#[derive(Debug, Clone)]
+pub(crate) struct BlockModel {
+    pub transform: Transform,
+    pub faces: Vec<ModelFace>,
 }
 
-#[derive(Component)]
-struct ThreeSided;
+/// Collects the faces of `model` turned by `angle`.
+struct ModelBuilder {
+    model: Model,
+    angle: f32,
+    faces: Vec<ModelFace>,
+}
 
-pub fn three_sided_diagonal(
-    pos: Position,
-    diagonal_type: &file::DiagonalType,
-    voxel: &BlockInfo,
-    block_gltf: &Gltf,
-    assets_gltfmesh: &Assets<GltfMesh>,
-    textures: &TextureIndex,
-    ext_materials: &mut Assets<ExtendedMaterial<StandardMaterial, MyExtension>>,
-    commands: &mut Commands<'_, '_>,
-) {
-    let mut get_face = |face: &file::Face, name| {
-        if face.tile_id == 0 {
-            return None;
+impl ModelBuilder {
+    fn new(model: Model, angle: f32) -> Self {
+        Self {
+            model,
+            angle,
+            faces: Vec::new(),
         }
+    }
 
-        let handle = block_gltf
-            .named_meshes
-            .get(name)
-            .unwrap_or_else(|| panic!("named mesh [{name}] to be found"))
-            .clone();
+    fn face(&mut self, part: Part, face: &file::Face) -> &mut Self {
+        self.mesh(FaceMesh::new(self.model, part), face)
+    }
 
-        let mesh = &assets_gltfmesh
-            .get(&handle)
-            .unwrap_or_else(|| panic!("mesh [{name}] to exist"))
-            .primitives[0]
-            .mesh;
+    /// Faces without tile are left out.
+    fn mesh(&mut self, mesh: FaceMesh, face: &file::Face) -> &mut Self {
+        if face.tile_id != 0 {
+            self.faces.push(ModelFace {
+                face: face.clone(),
+                mesh,
+                model_angle: self.angle,
+            });
+        }
+        self
+    }
 
-        let base_color_texture = textures
-            .index
-            .get(&face.tile_id)
-            .unwrap_or_else(|| panic!("texture for tile_id: {} to be found", face.tile_id))
-            .clone();
+    /// Flat faces are see-through and also drawn from behind. A flat face
+    /// alone shows its tile on both sides and hides the opposite face, two
+    /// flat faces show each other on their backs. `walls` tells whether the
+    /// model has each of the faces.
+    fn opposite(
+        &mut self,
+        parts: [Part; 2],
+        faces: [&file::Face; 2],
+        walls: [bool; 2],
+    ) -> &mut Self {
+        for (i, j) in [(0, 1), (1, 0)] {
+            let (face, other) = (faces[i], faces[j]);
+            if !walls[i] {
+                continue;
+            }
 
-        // NOTE: no compensation for rotation needed, because we have no lid
-        let rotation = face.rotate.clockwise_rad();
+            let hidden = !face.flat && other.flat && walls[j];
+            if !hidden {
+                self.face(parts[i], face);
+            }
 
-        // TODO could be optimized by re-using ext material with same properties
-        let ext_material = ext_materials.add(ExtendedMaterial {
-            base: StandardMaterial {
-                base_color_texture: Some(base_color_texture),
-                // NOTE: transperency is only allowed in flat faces
-                alpha_mode: if face.flat {
-                    AlphaMode::AlphaToCoverage
+            if face.flat {
+                let back = if other.flat {
+                    other.clone()
                 } else {
-                    AlphaMode::Opaque
-                },
-                ..default()
-            },
-            extension: MyExtension::new(face.flip, rotation),
-        });
-
-        Some(Face {
-            mesh: Mesh3d(mesh.clone()),
-            material: MeshMaterial3d(ext_material),
-        })
-    };
+                    file::Face {
+                        tile_id: face.tile_id,
+                        flat: true,
+                        ..other.clone()
+                    }
+                };
+                self.mesh(FaceMesh::new(self.model, parts[i]).back(), &back);
+            }
+        }
+        self
+    }
 
-    let left = &voxel.left;
-    let top = &voxel.top;
-    let right = &voxel.right;
-    let bottom = &voxel.bottom;
+    /// The side faces of the block as seen by the turned model, `walls` tells
+    /// whether the model has a wall on its left, top, right and bottom.
+    fn sides(&mut self, voxel: &BlockInfo, walls: [bool; 4]) -> &mut Self {
+        let [left, top, right, bottom] = model_sides(voxel, quarter_turns(self.angle));
+
+        self.opposite(
+            [Part::Left, Part::Right],
+            [left, right],
+            [walls[0], walls[2]],
+        )
+        .opposite(
+            [Part::Top, Part::Bottom],
+            [top, bottom],
+            [walls[1], walls[3]],
+        )
+    }
 
-    let (left, top, right, bottom) = match diagonal_type {
-        DiagonalType::DownLeft => (left, top, right, bottom),
-        DiagonalType::DownRight => (right, left, top, bottom),
-        DiagonalType::UpRight => (right, bottom, left, top),
-        DiagonalType::UpLeft => (left, right, bottom, top),
-    };
+    /// `transform` moves the model from the block centre, the builder turns
+    /// it.
+    fn build(&mut self, transform: Transform) -> BlockModel {
+        BlockModel {
+            transform: transform.with_rotation(Quat::from_rotation_z(self.angle)),
+            faces: std::mem::take(&mut self.faces),
+        }
+    }
+}
 
-    let top_flat = top.flat.then(|| {
-        let mut bottom = bottom.clone();
-        bottom.flat = true;
-        get_face(&bottom, "3_sided.right")
-    });
+/// Side faces of a block as seen by its model turned counterclockwise by
+/// `quarter_turns`, in the order left, top, right, bottom.
+fn model_sides(voxel: &BlockInfo, quarter_turns: usize) -> [&file::Face; 4] {
+    // counterclockwise, starting at the right
+    let sides = [&voxel.right, &voxel.top, &voxel.left, &voxel.bottom];
+    let side = |model_side: usize| sides[(model_side + quarter_turns) % 4];
 
-    let right_flat = right.flat.then(|| {
-        let mut left = left.clone();
-        left.flat = true;
-        get_face(&left, "3_sided.top")
-    });
+    [side(2), side(1), side(0), side(3)]
+}
 
-    let left_flat = left.flat.then(|| {
-        let mut left_rev = right.clone();
-        left_rev.flat = true;
-        get_face(&left_rev, "3_sided.lid.flat")
-    });
+fn quarter_turns(angle: f32) -> usize {
+    (angle / (0.25 * TAU)).round() as usize % 4
+}
 
-    let left = get_face(&left, "3_sided.lid");
-    let top = get_face(&top, "3_sided.top");
-    let right = get_face(&right, "3_sided.right");
+fn slope_angle(direction: &SlopeDirection) -> f32 {
+    match direction {
+        SlopeDirection::Up => 0.0,
+        SlopeDirection::Left => 0.25 * TAU,
+        SlopeDirection::Down => 0.5 * TAU,
+        SlopeDirection::Right => 0.75 * TAU,
+    }
+}
 
-    let rad = match diagonal_type {
+fn diagonal_angle(diagonal_type: &DiagonalType) -> f32 {
+    match diagonal_type {
         DiagonalType::DownLeft => 0.0,
         DiagonalType::DownRight => 0.25 * TAU,
         DiagonalType::UpRight => 0.5 * TAU,
         DiagonalType::UpLeft => 0.75 * TAU,
-    };
-
-    let transform =
-        Transform::from_translation(Vec3::from(pos)).with_rotation(Quat::from_rotation_z(rad));
-
-    commands
-        .spawn((Block { pos }, ThreeSided, transform, Visibility::Visible))
-        .with_children(|parent| {
-            left.map(|face| parent.spawn((face::Left, face)));
-            left_flat
-                .flatten()
-                .map(|face| parent.spawn((face::Left, face)));
-
-            top.map(|face| parent.spawn((face::Top, face)));
-            top_flat.flatten().map(|face| {
-                parent.spawn((
-                    face::Top,
-                    face,
-                    Transform::from_xyz(0.0, 1.0, 0.0)
-                        .with_rotation(Quat::from_rotation_z(-0.25 * TAU)),
-                ))
-            });
-
-            right.map(|face| parent.spawn((face::Right, face)));
-            right_flat.flatten().map(|face| {
-                parent.spawn((
-                    face::Right,
-                    face,
-                    Transform::from_xyz(1.0, 0.0, 0.0)
-                        .with_rotation(Quat::from_rotation_z(0.25 * TAU)),
-                ))
-            });
-        });
+    }
 }
 
-#[derive(Component)]
-struct Degree45;
+/// The diagonal face of a block shows the left face for diagonals on the
+/// left and the right face for those on the right.
+fn diagonal_face<'a>(voxel: &'a BlockInfo, diagonal_type: &DiagonalType) -> &'a file::Face {
+    match diagonal_type {
+        DiagonalType::UpLeft | DiagonalType::DownLeft => &voxel.left,
+        DiagonalType::UpRight | DiagonalType::DownRight => &voxel.right,
+    }
+}
 
-pub(crate) fn spawn_45_degree(
-    pos: Position,
-    direction: &file::SlopeDirection,
-    voxel: &BlockInfo,
-    block_gltf: &Gltf,
-    assets_gltfmesh: &Assets<GltfMesh>,
-    textures: &TextureIndex,
-    ext_materials: &mut Assets<ExtendedMaterial<StandardMaterial, MyExtension>>,
-    commands: &mut Commands<'_, '_>,
-) {
-    let mut get_face = |face: &file::Face, name| {
-        if face.tile_id == 0 {
-            return None;
+/// Model of `voxel` with the faces it shows, `None` for blocks without
+/// geometry. Used by the spawner and by the exporters, which run without
+/// a renderer.
+pub(crate) fn model(voxel: &BlockInfo) -> Option<BlockModel> {
+    let centre = Transform::IDENTITY;
+    // diagonals have walls on the right and top of the model only
+    let diagonal_walls = [false, true, true, false];
+
+    let model = match &voxel.slope_type {
+        SlopeType::None | SlopeType::SlopeAbove => ModelBuilder::new(Model::Cube, 0.0)
+            .face(Part::Lid, &voxel.lid)
+            .sides(voxel, [true; 4])
+            .build(centre),
+        // NOTE: diagonals can not be flat
+        SlopeType::Diagonal(diagonal_type) => {
+            ModelBuilder::new(Model::Diagonal, diagonal_angle(diagonal_type))
+                .face(Part::Lid, &voxel.lid)
+                .face(Part::Diagonal, diagonal_face(voxel, diagonal_type))
+                .sides(voxel, diagonal_walls)
+                .build(centre)
         }
+        SlopeType::FourSidedDiagonal(diagonal_type) => {
+            ModelBuilder::new(Model::FourSided, diagonal_angle(diagonal_type))
+                .face(Part::Lid, &voxel.lid)
+                .face(Part::Diagonal, diagonal_face(voxel, diagonal_type))
+                .sides(voxel, diagonal_walls)
+                .build(centre)
+        }
+        // the slope has no lid and shows the diagonal face, also from below
+        // if it is flat
+        SlopeType::ThreeSidedDiagonal(diagonal_type) => {
+            let slope = diagonal_face(voxel, diagonal_type);
+            let mut builder = ModelBuilder::new(Model::ThreeSided, diagonal_angle(diagonal_type));
+            builder.face(Part::Diagonal, slope);
+            if slope.flat {
+                builder.mesh(
+                    FaceMesh::new(Model::ThreeSided, Part::Diagonal).back(),
+                    slope,
+                );
+            }
 
-        let handle = block_gltf
-            .named_meshes
-            .get(name)
-            .unwrap_or_else(|| panic!("named mesh [{name}] to be found"))
-            .clone();
-
-        let mesh = &assets_gltfmesh
-            .get(&handle)
-            .unwrap_or_else(|| panic!("mesh [{name}] to exist"))
-            .primitives[0]
-            .mesh;
-
-        let base_color_texture = textures
-            .index
-            .get(&face.tile_id)
-            .unwrap_or_else(|| panic!("texture for tile_id: {} to be found", face.tile_id))
-            .clone();
-
-        // NOTE: we need to compensate the UV map rotation of the lid that
-        // occurs while rotating the base 3D model
-        let mut rotation = face.rotate.clockwise_rad();
-        if let file::FaceKind::Lid = face.kind {
-            let compensation = match direction {
-                SlopeDirection::Up => 0.0,
-                SlopeDirection::Left => 0.25 * TAU,
-                SlopeDirection::Down => 0.5 * TAU,
-                SlopeDirection::Right => 0.75 * TAU,
-            };
-
-            if face.flip {
-                rotation -= compensation;
-            } else {
-                rotation += compensation;
+            builder.sides(voxel, diagonal_walls).build(centre)
+        }
+        SlopeType::Degree7 { direction, index } => {
+            slope_model(voxel, Model::degree_7(*index), direction)
+        }
+        SlopeType::Degree26 { direction, level } => slope_model(
+            voxel,
+            Model::degree_26(*level == SlopeLevel::High),
+            direction,
+        ),
+        SlopeType::Degree45(direction) => slope_model(voxel, Model::degree_45(), direction),
+        // NOTE: partial blocks are modelled at the bottom of the block and
+        // turned towards the other sides
+        SlopeType::PartialBlock(position) => {
+            let (x, y, angle) = match position {
+                PartialPosition::Left => (-PARTIAL_OFFSET, 0.0, 0.25 * TAU),
+                PartialPosition::Right => (PARTIAL_OFFSET, 0.0, 0.75 * TAU),
+                PartialPosition::Top => (0.0, PARTIAL_OFFSET, 0.5 * TAU),
+                PartialPosition::Bottom => (0.0, -PARTIAL_OFFSET, 0.0),
             };
-        };
 
-        // TODO could be optimized by re-using ext material with same properties
-        let ext_material = ext_materials.add(ExtendedMaterial {
-            base: StandardMaterial {
-                base_color_texture: Some(base_color_texture),
-                // NOTE: transperency is only allowed in flat faces
-                alpha_mode: if face.flat {
-                    AlphaMode::AlphaToCoverage
-                } else {
-                    AlphaMode::Opaque
-                },
-                ..default()
-            },
-            extension: MyExtension::new(face.flip, rotation),
-        });
+            ModelBuilder::new(Model::Partial, angle)
+                .face(Part::Lid, &voxel.lid)
+                .sides(voxel, [true; 4])
+                .build(Transform::from_xyz(x, y, 0.0))
+        }
+        SlopeType::PartialCornerBlock(position) => {
+            let offset = geometry::corner_offset(*position).extend(0.0);
 
-        Some(Face {
-            mesh: Mesh3d(mesh.clone()),
-            material: MeshMaterial3d(ext_material),
-        })
+            ModelBuilder::new(Model::Corner(*position), 0.0)
+                .face(Part::Lid, &voxel.lid)
+                .sides(voxel, [true; 4])
+                .build(Transform::from_translation(offset))
+        }
+        SlopeType::PartialCentre => ModelBuilder::new(Model::Centre, 0.0)
+            .face(Part::Lid, &voxel.lid)
+            .sides(voxel, [true; 4])
+            .build(centre),
+        SlopeType::Ignore(_) => return None,
     };
 
-    let lid = &voxel.lid;
-    let left = &voxel.left;
-    let top = &voxel.top;
-    let right = &voxel.right;
-    let bottom = &voxel.bottom;
-
-    let (rotation, left, top, right, bottom) = match direction {
-        SlopeDirection::Up => (0.0, left, top, right, bottom),
-        SlopeDirection::Left => (0.25 * TAU, bottom, left, top, right),
-        SlopeDirection::Down => (0.5 * TAU, right, bottom, left, top),
-        SlopeDirection::Right => (0.75 * TAU, top, right, bottom, left),
-    };
+    Some(model)
+}
 
-    let top_flat = top.flat.then(|| {
-        let mut bottom = bottom.clone();
-        bottom.flat = true;
-        get_face(&bottom, "block.bottom")
-    });
+/// Shared by the 7°, 26° and 45° slopes, which only have a wall on their low
+/// side if its edge is above the bottom of the block.
+fn slope_model(voxel: &BlockInfo, model: Model, direction: &SlopeDirection) -> BlockModel {
+    let low_wall = matches!(model, Model::Slope { low, .. } if low > 0);
 
-    let left_flat = left.flat.then(|| {
-        let mut right = right.clone();
-        right.flat = true;
-        get_face(&right, "degree_45.right")
-    });
+    ModelBuilder::new(model, slope_angle(direction))
+        .face(Part::Lid, &voxel.lid)
+        .sides(voxel, [true, true, true, low_wall])
+        .build(Transform::IDENTITY)
+}
 
-    let right_flat = right.flat.then(|| {
-        let mut left = left.clone();
-        left.flat = true;
-        get_face(&left, "degree_45.left")
-    });
+/// Meshes of the model faces, created when first used.
+#[derive(Resource, Debug, Default)]
+pub(crate) struct BlockMeshes(HashMap<FaceMesh, Handle<Mesh>>);
 
-    let lid = get_face(lid, "degree_45.lid");
-    let left = get_face(left, "degree_45.left");
-    let right = get_face(right, "degree_45.right");
-    let top = get_face(top, "block.top");
-
-    let transform =
-        Transform::from_translation(Vec3::from(pos)).with_rotation(Quat::from_rotation_z(rotation));
-
-    commands
-        .spawn((Block { pos }, Degree45, transform, Visibility::Visible))
-        .with_children(|parent| {
-            lid.map(|face| parent.spawn((face::Lid, face)));
-
-            top.map(|face| parent.spawn((face::Top, face)));
-            top_flat
-                .flatten()
-                .map(|face| parent.spawn((face::Top, face, Transform::from_xyz(0.0, 1.0, 0.0))));
-
-            left.map(|face| parent.spawn((face::Left, face)));
-            left_flat
-                .flatten()
-                .map(|face| parent.spawn((face::Left, face, Transform::from_xyz(-1.0, 0.0, 0.0))));
-
-            right.map(|face| parent.spawn((face::Right, face)));
-            right_flat
-                .flatten()
-                .map(|face| parent.spawn((face::Right, face, Transform::from_xyz(1.0, 0.0, 0.0))));
-        });
+/// Creates the entities of faces.
+pub(crate) struct FaceBuilder<'a> {
+    pub block_meshes: &'a mut BlockMeshes,
+    pub meshes: &'a mut Assets<Mesh>,
+    pub textures: &'a TextureIndex,
+    pub materials: &'a mut Assets<MapMaterial>,
 }
 
-impl From<Position> for Vec3 {
-    fn from(pos: Position) -> Self {
-        Vec3 {
-            x: f32::from(pos.x),
-            y: f32::from(pos.y),
-            z: f32::from(pos.z),
+impl FaceBuilder<'_> {
+    fn mesh(&mut self, face_mesh: FaceMesh) -> Option<Handle<Mesh>> {
+        if let Some(mesh) = self.block_meshes.0.get(&face_mesh) {
+            return Some(mesh.clone());
         }
-    }
-}
 
-pub fn partial_corner(
-    pos: Position,
-    voxel: &BlockInfo,
-    partial_pos: &file::CornerPosition,
-    block_gltf: &Gltf,
-    assets_gltfmesh: &Assets<GltfMesh>,
-    textures: &TextureIndex,
-    ext_materials: &mut Assets<ExtendedMaterial<StandardMaterial, MyExtension>>,
-    commands: &mut Commands<'_, '_>,
-) {
-    let mut get_face = |face: &file::Face, name| {
-        if face.tile_id == 0 {
-            return None;
-        }
+        let mesh = self.meshes.add(Mesh::from(face_mesh.geometry()?));
+        self.block_meshes.0.insert(face_mesh, mesh.clone());
+        Some(mesh)
+    }
 
-        let handle = block_gltf
-            .named_meshes
-            .get(name)
-            .unwrap_or_else(|| panic!("named mesh [{name}] to be found"))
-            .clone();
-
-        let mesh = &assets_gltfmesh
-            .get(&handle)
-            .unwrap_or_else(|| panic!("mesh [{name}] to exist"))
-            .primitives[0]
-            .mesh;
-
-        let base_color_texture = textures
-            .index
-            .get(&face.tile_id)
-            .unwrap_or_else(|| panic!("texture for tile_id: {} to be found", face.tile_id))
-            .clone();
-
-        // NOTE: we need to compensate the UV map rotation of the lid that
-        // occurs while rotating the base 3D model
-        let rotation = face.rotate.clockwise_rad();
-        // if let file::FaceKind::Lid = face.kind {
-        //     let compensation = match partial_pos {
-        //         file::CornerPosition::TopLeft => 0.0,
-        //         file::CornerPosition::BottomLeft => 0.25 * TAU,
-        //         file::CornerPosition::BottomRight => 0.5 * TAU,
-        //         file::CornerPosition::TopRight => 0.75 * TAU,
-        //     };
-
-        //     if face.flip {
-        //         rotation -= compensation;
-        //     } else {
-        //         rotation += compensation;
-        //     };
-        // };
+    /// `None` if the model has no mesh for the face.
+    fn face(&mut self, model_face: &ModelFace) -> Option<Face> {
+        let mesh = self.mesh(model_face.mesh)?;
+        let face = &model_face.face;
 
         // TODO could be optimized by re-using ext material with same properties
-        let ext_material = ext_materials.add(ExtendedMaterial {
+        let material = self.materials.add(ExtendedMaterial {
             base: StandardMaterial {
-                base_color_texture: Some(base_color_texture),
+                base_color_texture: self.textures.index.get(&face.tile_id).cloned(),
                 // NOTE: transperency is only allowed in flat faces
                 alpha_mode: if face.flat {
                     AlphaMode::AlphaToCoverage
@@ -833,155 +403,89 @@ pub fn partial_corner(
                 },
                 ..default()
             },
-            extension: MyExtension::new(face.flip, rotation),
+            extension: model_face.extension(),
         });
 
         Some(Face {
-            mesh: Mesh3d(mesh.clone()),
-            material: MeshMaterial3d(ext_material),
+            mesh: Mesh3d(mesh),
+            material: MeshMaterial3d(material),
+            info: FaceInfo(face.clone()),
         })
-    };
+    }
+}
 
-    let lid = &voxel.lid;
-    let left = &voxel.left;
-    let top = &voxel.top;
-    let right = &voxel.right;
-    let bottom = &voxel.bottom;
-
-    // let (rotation, left, top, right, bottom) = match partial_pos {
-    //     file::CornerPosition::TopLeft => (0.0, left, top, right, bottom),
-    //     file::CornerPosition::BottomLeft => (0.25 * TAU, bottom, right, top, left),
-    //     file::CornerPosition::BottomRight => (0.5 * TAU, right, top, left, bottom),
-    //     file::CornerPosition::TopRight => (0.75 * TAU, top, left, bottom, right),
-    // };
-
-    let top_flat = top.flat.then(|| {
-        let mut bottom = bottom.clone();
-        bottom.flat = true;
-        get_face(&bottom, "partial_corner.bottom")
-    });
+/// Spawns the block entity of `voxel` at `pos`, with its faces as children.
+pub(crate) fn spawn(
+    pos: Position,
+    voxel: &BlockInfo,
+    faces: &mut FaceBuilder,
+    commands: &mut Commands,
+) {
+    let Some(model) = model(voxel) else {
+        return;
+    };
 
-    let bottom_flat = top.flat.then(|| {
-        let mut top = top.clone();
-        top.flat = true;
-        get_face(&top, "partial_corner.top")
-    });
+    let transform = model
+        .transform
+        .with_translation(Vec3::from(pos) + model.transform.translation);
+    let mut block = commands.spawn((Block { pos }, transform, Visibility::Visible));
+
+    match &voxel.slope_type {
+        SlopeType::None | SlopeType::SlopeAbove => block.insert(Normal),
+        SlopeType::Diagonal(_) => block.insert(Diagonal),
+        SlopeType::ThreeSidedDiagonal(_) => block.insert(ThreeSided),
+        SlopeType::FourSidedDiagonal(_) => block.insert(FourSided),
+        SlopeType::Degree7 { .. } => block.insert(Degree7),
+        SlopeType::Degree26 { .. } => block.insert(Degree26),
+        SlopeType::Degree45(_) => block.insert(Degree45),
+        SlopeType::PartialBlock(_) => block.insert(Partial),
+        SlopeType::PartialCornerBlock(_) => block.insert(PartialCorner),
+        SlopeType::PartialCentre => block.insert(PartialCentre),
+        SlopeType::Ignore(_) => &mut block,
+    };
 
-    let left_flat = left.flat.then(|| {
-        let mut right = right.clone();
-        right.flat = true;
-        get_face(&right, "partial_corner.right")
-    });
+    block.with_children(|parent| {
+        for model_face in &model.faces {
+            let Some(face) = faces.face(model_face) else {
+                continue;
+            };
 
-    let right_flat = right.flat.then(|| {
-        let mut left = left.clone();
-        left.flat = true;
-        get_face(&left, "partial_corner.left")
+            match model_face.mesh.part {
+                Part::Lid => parent.spawn((face::Lid, face)),
+                Part::Left => parent.spawn((face::Left, face)),
+                Part::Right => parent.spawn((face::Right, face)),
+                Part::Top => parent.spawn((face::Top, face)),
+                Part::Bottom => parent.spawn((face::Bottom, face)),
+                Part::Diagonal => parent.spawn(face),
+            };
+        }
     });
+}
 
-    let lid = get_face(lid, "partial_corner.lid");
-    let left = get_face(left, "partial_corner.left");
-    let top = get_face(top, "partial_corner.top");
-    let right = get_face(right, "partial_corner.right");
-    let bottom = get_face(bottom, "partial_corner.bottom");
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    const PARTIAL_POS_OFFSET: f32 = (64.0 - 24.0) / 64.0 / 2.0;
-    let transform = match partial_pos {
-        file::CornerPosition::TopLeft => {
-            Transform::from_xyz(-PARTIAL_POS_OFFSET, PARTIAL_POS_OFFSET, 0.0)
+    #[test]
+    fn positions_convert_to_file_coordinates_and_back() {
+        for (x, row, z) in [(0, 0, 0), (255, 255, 7), (10, 200, 3)] {
+            let pos = Position::from_file(x, row, z);
+            assert_eq!(pos.file_coordinates(), Some((x, row, z)));
         }
-        file::CornerPosition::BottomLeft => {
-            Transform::from_xyz(-PARTIAL_POS_OFFSET, -PARTIAL_POS_OFFSET, 0.0)
-        }
-        file::CornerPosition::BottomRight => {
-            Transform::from_xyz(PARTIAL_POS_OFFSET, -PARTIAL_POS_OFFSET, 0.0)
-        }
-        file::CornerPosition::TopRight => {
-            Transform::from_xyz(PARTIAL_POS_OFFSET, PARTIAL_POS_OFFSET, 0.0)
-        }
-    };
-    let transform = Transform::from_translation(Vec3::from(pos)).mul_transform(transform);
-    // .with_rotation(Quat::from_rotation_z(rotation));
-
-    commands
-        .spawn((Block { pos }, Degree45, transform, Visibility::Visible))
-        .with_children(|parent| {
-            const FLAT_OFFSET: f32 = 24.0 / 64.0;
-            lid.map(|face| parent.spawn((face::Lid, face)));
-
-            match (left_flat, right_flat) {
-                (None, Some(right_flat)) => {
-                    right.map(|face| parent.spawn((face::Right, face)));
-                    right_flat.map(|face| {
-                        parent.spawn((
-                            face::Right,
-                            face,
-                            Transform::from_xyz(FLAT_OFFSET, 0.0, 0.0),
-                        ))
-                    });
-                }
-                (Some(left_flat), None) => {
-                    left.map(|face| parent.spawn((face::Left, face)));
-                    left_flat.map(|face| {
-                        parent.spawn((
-                            face::Left,
-                            face,
-                            Transform::from_xyz(-FLAT_OFFSET, 0.0, 0.0),
-                        ))
-                    });
-                }
-                (left_flat, right_flat) => {
-                    left.map(|face| parent.spawn((face::Left, face)));
-                    left_flat.flatten().map(|face| {
-                        parent.spawn((
-                            face::Left,
-                            face,
-                            Transform::from_xyz(-FLAT_OFFSET, 0.0, 0.0),
-                        ))
-                    });
-                    right.map(|face| parent.spawn((face::Right, face)));
-                    right_flat.flatten().map(|face| {
-                        parent.spawn((
-                            face::Right,
-                            face,
-                            Transform::from_xyz(FLAT_OFFSET, 0.0, 0.0),
-                        ))
-                    });
-                }
-            };
 
-            match (top_flat, bottom_flat) {
-                (None, Some(bottom_flat)) => {
-                    bottom.map(|face| parent.spawn((face::Bottom, face)));
-                    bottom_flat.map(|face| {
-                        parent.spawn((
-                            face::Bottom,
-                            face,
-                            Transform::from_xyz(0.0, -FLAT_OFFSET, 0.0),
-                        ))
-                    });
-                }
-                (Some(top_flat), None) => {
-                    top.map(|face| parent.spawn((face::Top, face)));
-                    top_flat.map(|face| {
-                        parent.spawn((face::Top, face, Transform::from_xyz(0.0, FLAT_OFFSET, 0.0)))
-                    });
-                }
-                (top_flat, bottom_flat) => {
-                    top.map(|face| parent.spawn((face::Top, face)));
-                    top_flat.flatten().map(|face| {
-                        parent.spawn((face::Top, face, Transform::from_xyz(0.0, FLAT_OFFSET, 0.0)))
-                    });
-
-                    bottom.map(|face| parent.spawn((face::Bottom, face)));
-                    bottom_flat.flatten().map(|face| {
-                        parent.spawn((
-                            face::Bottom,
-                            face,
-                            Transform::from_xyz(0.0, -FLAT_OFFSET, 0.0),
-                        ))
-                    });
-                }
-            }
-        });
+        // the top row is at the top of the world, not wrapped to 0
+        assert_eq!(Position::from_file(0, 0, 0).y, 256);
+        assert_eq!(Position::from_file(0, 255, 0).y, 1);
+    }
+
+    #[test]
+    fn positions_outside_of_the_map_have_no_file_coordinates() {
+        for pos in [
+            Position { x: 0, y: 0, z: 0 },
+            Position { x: 0, y: 257, z: 0 },
+            Position { x: 0, y: 1, z: 8 },
+        ] {
+            assert_eq!(pos.file_coordinates(), None, "{pos:?}");
+        }
+    }
 }
@@ -4,7 +4,7 @@ use bevy::{
     render::mesh::Mesh3d,
 };
 
-use crate::map::MyExtension;
+use crate::map::{FaceInfo, MyExtension};
 
 #[derive(Component)]
 // #[require(Face)]
@@ -26,8 +26,9 @@ pub struct Top;
 // #[require(Face)]
 pub struct Bottom;
 
-#[derive(Bundle, Default, Clone)]
+#[derive(Bundle, Clone)]
 pub struct Face {
     pub mesh: Mesh3d,
     pub material: MeshMaterial3d<ExtendedMaterial<StandardMaterial, MyExtension>>,
+    pub info: FaceInfo,
 }
@@ -0,0 +1,435 @@
+//! Go-to dialog and named bookmarks of map cells.
+//!
+//! Cells are referenced by links like `bil.gmp @ 112,87,3`, using the block
+//! positions the viewer shows on click. Bookmarks are stored one link per
+//! line, followed by the name, in a `<map>.bookmarks` file next to the map.
+
+use std::{
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use bevy::{
+    input::{
+        keyboard::{Key, KeyboardInput},
+        InputSystem,
+    },
+    prelude::*,
+};
+use bevy_panorbit_camera::PanOrbitCamera;
+
+use super::{file::UncompressedMap, CurrentMap, GameFilesPath};
+use crate::camera::CameraMode;
+
+pub fn plugin(app: &mut App) {
+    app.init_resource::<Dialog>()
+        .add_systems(Startup, (load_bookmarks, spawn_dialog))
+        .add_systems(PreUpdate, dialog_input.after(InputSystem))
+        .add_systems(
+            Update,
+            update_dialog_text.run_if(resource_changed::<Dialog>),
+        );
+}
+
+const GO_TO_KEY: KeyCode = KeyCode::KeyL;
+const ADD_BOOKMARK_KEY: KeyCode = KeyCode::KeyB;
+const LINK_SEPARATOR: &str = " @ ";
+
+/// Shareable reference to a map cell, written like `bil.gmp @ 112,87,3`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MapLink {
+    /// File name of the map, `None` for plain coordinates.
+    pub map: Option<String>,
+    pub cell: IVec3,
+}
+
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub enum MapLinkError {
+    #[error("Expected coordinates like 112,87,3 but got \"{0}\"")]
+    Coordinates(String),
+    #[error("Level {0} is not between 0 and {max}", max = UncompressedMap::Z - 1)]
+    Level(i32),
+}
+
+impl Display for MapLink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(map) = &self.map {
+            write!(f, "{map}{LINK_SEPARATOR}")?;
+        }
+
+        write!(f, "{},{},{}", self.cell.x, self.cell.y, self.cell.z)
+    }
+}
+
+impl FromStr for MapLink {
+    type Err = MapLinkError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (map, coordinates) = match s.split_once(LINK_SEPARATOR.trim()) {
+            Some((map, coordinates)) => (Some(map.trim().to_string()), coordinates),
+            None => (None, s),
+        };
+
+        let values: Vec<i32> = coordinates
+            .split(',')
+            .map(|value| value.trim().parse())
+            .collect::<Result<_, _>>()
+            .map_err(|_| MapLinkError::Coordinates(coordinates.trim().to_string()))?;
+
+        let [x, y, z] = values[..] else {
+            return Err(MapLinkError::Coordinates(coordinates.trim().to_string()));
+        };
+        // NOTE: x and y may be outside of the map, the camera can go there
+        if !(0..UncompressedMap::Z as i32).contains(&z) {
+            return Err(MapLinkError::Level(z));
+        }
+
+        Ok(Self {
+            map,
+            cell: IVec3::new(x, y, z),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Bookmark {
+    pub name: String,
+    pub link: MapLink,
+}
+
+#[derive(Resource, Debug, Default)]
+pub struct Bookmarks {
+    path: PathBuf,
+    pub entries: Vec<Bookmark>,
+}
+
+impl Bookmarks {
+    /// Reads the bookmarks file, lines which are not a link followed by a
+    /// name are skipped.
+    pub fn load(path: &Path) -> std::io::Result<Vec<Bookmark>> {
+        let content = match fs::read_to_string(path) {
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            result => result?,
+        };
+
+        Ok(content.lines().filter_map(parse_bookmark).collect())
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let content: String = self
+            .entries
+            .iter()
+            .map(|bookmark| format!("{} {}\n", bookmark.link, bookmark.name))
+            .collect();
+
+        fs::write(&self.path, content)
+    }
+
+    /// Bookmark by name, ignoring case, or by its number in the list.
+    pub fn find(&self, query: &str) -> Option<&Bookmark> {
+        if let Ok(number) = query.parse::<usize>() {
+            return number.checked_sub(1).and_then(|i| self.entries.get(i));
+        }
+
+        self.entries
+            .iter()
+            .find(|bookmark| bookmark.name.eq_ignore_ascii_case(query))
+    }
+}
+
+fn parse_bookmark(line: &str) -> Option<Bookmark> {
+    let (map, rest) = line.split_once(LINK_SEPARATOR)?;
+    let (coordinates, name) = rest.trim().split_once(char::is_whitespace)?;
+    let link = format!("{map}{LINK_SEPARATOR}{coordinates}").parse().ok()?;
+
+    Some(Bookmark {
+        name: name.trim().to_string(),
+        link,
+    })
+}
+
+fn load_bookmarks(
+    mut commands: Commands,
+    game_files_path: Res<GameFilesPath>,
+    current_map: Res<CurrentMap>,
+) {
+    let path = game_files_path
+        .0
+        .join(format!("{}.bookmarks", current_map.0.get_base_name()));
+
+    let entries = Bookmarks::load(&path).unwrap_or_else(|err| {
+        error!("Could not read bookmarks {}: {err}", path.display());
+        Vec::new()
+    });
+
+    commands.insert_resource(Bookmarks { path, entries });
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DialogMode {
+    GoTo,
+    AddBookmark,
+}
+
+#[derive(Resource, Debug, Default)]
+struct Dialog {
+    mode: Option<DialogMode>,
+    input: String,
+    error: Option<String>,
+}
+
+#[derive(Component)]
+struct DialogText;
+
+fn spawn_dialog(mut commands: Commands) {
+    commands.spawn((
+        DialogText,
+        Text::default(),
+        TextFont {
+            font_size: 14.0,
+            ..default()
+        },
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(10.0),
+            left: Val::Percent(35.0),
+            padding: UiRect::all(Val::Px(8.0)),
+            ..default()
+        },
+        BackgroundColor(Color::BLACK.with_alpha(0.7)),
+        Visibility::Hidden,
+    ));
+}
+
+/// Handles the dialog keys before any other system sees them, so typing
+/// does not trigger other key bindings.
+fn dialog_input(
+    mut dialog: ResMut<Dialog>,
+    mut keyboard: EventReader<KeyboardInput>,
+    mut keys: ResMut<ButtonInput<KeyCode>>,
+    mut bookmarks: Option<ResMut<Bookmarks>>,
+    current_map: Res<CurrentMap>,
+    mut cameras: Query<&mut PanOrbitCamera>,
+    mut next_camera_mode: ResMut<NextState<CameraMode>>,
+) {
+    let Some(mode) = dialog.mode else {
+        keyboard.clear();
+
+        let mode = if keys.just_pressed(GO_TO_KEY) {
+            DialogMode::GoTo
+        } else if keys.just_pressed(ADD_BOOKMARK_KEY) {
+            DialogMode::AddBookmark
+        } else {
+            return;
+        };
+
+        *dialog = Dialog {
+            mode: Some(mode),
+            ..default()
+        };
+        keys.reset_all();
+        return;
+    };
+
+    for event in keyboard.read() {
+        // the dialog was closed by a previous key
+        if dialog.mode.is_none() {
+            break;
+        }
+        if !event.state.is_pressed() {
+            continue;
+        }
+
+        match &event.logical_key {
+            Key::Escape => *dialog = Dialog::default(),
+            Key::Backspace => {
+                dialog.input.pop();
+            }
+            Key::Enter => {
+                let input = dialog.input.trim().to_string();
+                let Some(bookmarks) = bookmarks.as_deref_mut() else {
+                    continue;
+                };
+                let focus = cameras.iter().next().map(|camera| camera.focus);
+
+                let result = match mode {
+                    DialogMode::GoTo => go_to(&input, bookmarks, &current_map).map(|cell| {
+                        for mut camera in &mut cameras {
+                            camera.target_focus = cell.as_vec3();
+                        }
+                        next_camera_mode.set(CameraMode::Orbit);
+                    }),
+                    DialogMode::AddBookmark => {
+                        add_bookmark(input, focus.unwrap_or_default(), bookmarks, &current_map)
+                    }
+                };
+
+                match result {
+                    Ok(()) => *dialog = Dialog::default(),
+                    Err(err) => dialog.error = Some(err),
+                }
+            }
+            Key::Character(text) => dialog.input.push_str(text),
+            Key::Space => dialog.input.push(' '),
+            _ => {}
+        }
+    }
+
+    keys.reset_all();
+}
+
+fn go_to(input: &str, bookmarks: &Bookmarks, current_map: &CurrentMap) -> Result<IVec3, String> {
+    let link = match bookmarks.find(input) {
+        Some(bookmark) => bookmark.link.clone(),
+        None => input.parse::<MapLink>().map_err(|err| err.to_string())?,
+    };
+
+    let map_file_name = current_map.0.get_map_file_name();
+    if let Some(map) = link
+        .map
+        .as_deref()
+        .filter(|map| Path::new(map) != map_file_name)
+    {
+        warn!(
+            "Link is for {map} but {} is loaded, going to the same position",
+            map_file_name.display()
+        );
+    }
+
+    info!("Go to {link}");
+    Ok(link.cell)
+}
+
+fn add_bookmark(
+    name: String,
+    focus: Vec3,
+    bookmarks: &mut Bookmarks,
+    current_map: &CurrentMap,
+) -> Result<(), String> {
+    if name.is_empty() {
+        return Err("The bookmark needs a name".to_string());
+    }
+
+    // NOTE: links outside of the levels would not be read back
+    let mut cell = focus.round().as_ivec3();
+    cell.z = cell.z.clamp(0, UncompressedMap::Z as i32 - 1);
+    let link = MapLink {
+        map: Some(current_map.0.get_map_file_name().display().to_string()),
+        cell,
+    };
+    info!("Bookmark {link} {name}");
+
+    bookmarks.entries.push(Bookmark { name, link });
+    bookmarks
+        .save()
+        .map_err(|err| format!("Could not save {}: {err}", bookmarks.path.display()))
+}
+
+fn update_dialog_text(
+    dialog: Res<Dialog>,
+    bookmarks: Option<Res<Bookmarks>>,
+    mut texts: Query<(&mut Text, &mut Visibility), With<DialogText>>,
+) {
+    let Ok((mut text, mut visibility)) = texts.get_single_mut() else {
+        return;
+    };
+
+    let Some(mode) = dialog.mode else {
+        *visibility = Visibility::Hidden;
+        return;
+    };
+    *visibility = Visibility::Visible;
+
+    let mut content = match mode {
+        DialogMode::GoTo => format!("Go to x,y,z, link or bookmark: {}_", dialog.input),
+        DialogMode::AddBookmark => format!("Bookmark name: {}_", dialog.input),
+    };
+
+    if let Some(error) = &dialog.error {
+        content += &format!("\n{error}");
+    }
+
+    if let (DialogMode::GoTo, Some(bookmarks)) = (mode, bookmarks) {
+        for (i, bookmark) in bookmarks.entries.iter().enumerate() {
+            content += &format!("\n{:>3}  {}  {}", i + 1, bookmark.link, bookmark.name);
+        }
+    }
+
+    **text = content;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(map: Option<&str>, x: i32, y: i32, z: i32) -> MapLink {
+        MapLink {
+            map: map.map(str::to_string),
+            cell: IVec3::new(x, y, z),
+        }
+    }
+
+    #[test]
+    fn links_round_trip_through_display() {
+        for link in [link(Some("bil.gmp"), 112, 87, 3), link(None, 0, 256, 7)] {
+            assert_eq!(link.to_string().parse::<MapLink>().unwrap(), link);
+        }
+        assert_eq!(
+            link(Some("bil.gmp"), 112, 87, 3).to_string(),
+            "bil.gmp @ 112,87,3"
+        );
+    }
+
+    #[test]
+    fn whitespace_around_parts_is_ignored() {
+        assert_eq!(
+            "  bil.gmp@112 , 87,3 ".parse::<MapLink>().unwrap(),
+            link(Some("bil.gmp"), 112, 87, 3)
+        );
+        assert_eq!(" 1, 2 ,3".parse::<MapLink>().unwrap(), link(None, 1, 2, 3));
+    }
+
+    #[test]
+    fn broken_coordinates_are_errors() {
+        for input in [
+            "",
+            "bil.gmp @ ",
+            "1,2",
+            "1,2,3,4",
+            "1,two,3",
+            "bil.gmp @ 1,,3",
+        ] {
+            assert!(
+                matches!(input.parse::<MapLink>(), Err(MapLinkError::Coordinates(_))),
+                "{input:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn levels_outside_of_the_map_are_errors() {
+        assert!(matches!(
+            "bil.gmp @ 1,2,8".parse::<MapLink>(),
+            Err(MapLinkError::Level(8))
+        ));
+        assert!(matches!(
+            "1,2,-1".parse::<MapLink>(),
+            Err(MapLinkError::Level(-1))
+        ));
+    }
+
+    #[test]
+    fn bookmarks_are_a_link_followed_by_a_name() {
+        let bookmark = parse_bookmark("bil.gmp @ 112,87,3   Police station ").unwrap();
+        assert_eq!(bookmark.link, link(Some("bil.gmp"), 112, 87, 3));
+        assert_eq!(bookmark.name, "Police station");
+
+        // without a name, map or valid level
+        assert!(parse_bookmark("bil.gmp @ 112,87,3").is_none());
+        assert!(parse_bookmark("112,87,3 Police station").is_none());
+        assert!(parse_bookmark("bil.gmp @ 112,87,9 Roof").is_none());
+    }
+}
@@ -0,0 +1,765 @@
+//! Collision world derived from the blocks of a map, for gameplay and AI
+//! without rendering.
+//!
+//! The solid part of a block is everything below its floor, see
+//! [`ground::floor_height`], so slopes are ramps, and partial blocks and
+//! diagonals are only solid on their footprint. Side faces with the wall
+//! flag block movement across the block edge, those with the bullet wall
+//! flag block bullets. Positions are in world space like the spawned blocks.
+
+use bevy::{
+    ecs::system::Resource,
+    math::{BVec3, IVec2, IVec3, Vec2, Vec3},
+};
+
+use super::{
+    file::{BlockInfo, DiagonalType, GroundType, SlopeType, UncompressedMap},
+    geometry::PARTIAL_SIZE,
+    ground::{self, level_at, Ground},
+};
+
+/// Room peds and cars need above the floor they step onto, in blocks.
+pub const STEP_BODY_SIZE: Vec3 = Vec3::new(0.2, 0.2, 0.3);
+/// Largest height difference between the floors of neighbouring cells
+/// peds and cars step across, enough for the 26° slopes.
+pub const MAX_CLIMB: f32 = 0.5;
+
+/// Tolerance for touching surfaces, boxes resting on a floor or against a
+/// wall do not overlap it.
+const CONTACT: f32 = 1e-4;
+const GRAVITY: f32 = 9.81;
+/// Lowest possible floor, the bottom of the map.
+const MAP_BOTTOM: f32 = -0.5;
+/// Largest step of swept boxes, small enough to not skip the thinnest parts
+/// of slopes.
+const SWEEP_STEP: f32 = 1.0 / 16.0;
+/// Lines in a block along which floors change their slope or end, besides
+/// the diagonals.
+const FLOOR_EDGES: [f32; 5] = [
+    PARTIAL_SIZE - 0.5,
+    -PARTIAL_SIZE / 2.0,
+    0.0,
+    PARTIAL_SIZE / 2.0,
+    0.5 - PARTIAL_SIZE,
+];
+
+/// What a query collides with besides the solid parts of blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Blocking {
+    /// Walls, for peds and cars.
+    Movement,
+    /// Bullet walls.
+    Bullets,
+}
+
+/// A flag of each side face of a block.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Sides {
+    pub left: bool,
+    pub right: bool,
+    pub top: bool,
+    pub bottom: bool,
+}
+
+impl Sides {
+    fn new(block: &BlockInfo, flag: impl Fn(&super::file::Face) -> bool) -> Self {
+        let sides = Self {
+            left: flag(&block.left),
+            right: flag(&block.right),
+            top: flag(&block.top),
+            bottom: flag(&block.bottom),
+        };
+
+        // NOTE: the open sides of diagonals hold the diagonal face, which is
+        // solid through the floor of the block and not on the block edge
+        let open = |diagonal_type: &DiagonalType| match diagonal_type {
+            DiagonalType::UpLeft => Self {
+                left: true,
+                top: true,
+                ..Self::default()
+            },
+            DiagonalType::UpRight => Self {
+                right: true,
+                top: true,
+                ..Self::default()
+            },
+            DiagonalType::DownLeft => Self {
+                left: true,
+                bottom: true,
+                ..Self::default()
+            },
+            DiagonalType::DownRight => Self {
+                right: true,
+                bottom: true,
+                ..Self::default()
+            },
+        };
+
+        match &block.slope_type {
+            SlopeType::Diagonal(diagonal_type)
+            | SlopeType::ThreeSidedDiagonal(diagonal_type)
+            | SlopeType::FourSidedDiagonal(diagonal_type) => {
+                let open = open(diagonal_type);
+                Self {
+                    left: sides.left && !open.left,
+                    right: sides.right && !open.right,
+                    top: sides.top && !open.top,
+                    bottom: sides.bottom && !open.bottom,
+                }
+            }
+            _ => sides,
+        }
+    }
+
+    /// The side facing the neighbouring cell `step` away.
+    fn towards(&self, step: IVec2) -> bool {
+        (step.x > 0 && self.right)
+            || (step.x < 0 && self.left)
+            || (step.y > 0 && self.top)
+            || (step.y < 0 && self.bottom)
+    }
+}
+
+/// Collision data of a block.
+#[derive(Debug, Clone, Default)]
+struct Cell {
+    /// Shape of the floor, `None` for air.
+    floor: Option<SlopeType>,
+    walls: Sides,
+    bullet_walls: Sides,
+}
+
+impl Cell {
+    fn new(block: &BlockInfo) -> Self {
+        Self {
+            floor: (block.ground_type != GroundType::Air).then(|| block.slope_type.clone()),
+            walls: Sides::new(block, |face| face.wall),
+            bullet_walls: Sides::new(block, |face| face.bullet_wall),
+        }
+    }
+
+    fn floor_height(&self, local: Vec2) -> Option<f32> {
+        ground::slope_height(self.floor.as_ref()?, local)
+    }
+
+    fn sides(&self, blocking: Blocking) -> &Sides {
+        match blocking {
+            Blocking::Movement => &self.walls,
+            Blocking::Bullets => &self.bullet_walls,
+        }
+    }
+}
+
+/// First surface hit by a ray.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RayHit {
+    pub distance: f32,
+    pub point: Vec3,
+    /// Outward normal of the surface, against the ray for rays starting
+    /// inside a solid.
+    pub normal: Vec3,
+    /// World cell and level of the block hit.
+    pub block: IVec3,
+}
+
+/// Where a swept box stopped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SweepHit {
+    /// Fraction of the motion done before the collision.
+    pub time: f32,
+    /// Centre of the box at `time`.
+    pub center: Vec3,
+    /// Axes in which the box can not move further, to slide along the
+    /// others.
+    pub blocked: BVec3,
+}
+
+/// Solid blocks and walls of a map. The loaded map is kept as resource.
+#[derive(Resource, Debug, Clone)]
+pub struct CollisionMap {
+    cells: Vec<Cell>,
+}
+
+impl From<&UncompressedMap> for CollisionMap {
+    fn from(map: &UncompressedMap) -> Self {
+        Self {
+            cells: map.0.iter().map(Cell::new).collect(),
+        }
+    }
+}
+
+impl CollisionMap {
+    /// Cell at the world `cell`, `None` outside the map.
+    fn cell(&self, cell: IVec3) -> Option<&Cell> {
+        let row = UncompressedMap::row(cell.y);
+        let in_map = (0..UncompressedMap::X as i32).contains(&cell.x)
+            && (0..UncompressedMap::Y as i32).contains(&row)
+            && (0..UncompressedMap::Z as i32).contains(&cell.z);
+        if !in_map {
+            return None;
+        }
+
+        let index = (cell.z as usize * UncompressedMap::Y + row as usize) * UncompressedMap::X
+            + cell.x as usize;
+        self.cells.get(index)
+    }
+
+    /// Whether a wall separates the neighbouring cells `from` and `to` at
+    /// `level`.
+    pub fn wall_between(&self, from: IVec2, to: IVec2, level: i32, blocking: Blocking) -> bool {
+        let step = to - from;
+        let blocks = |cell: IVec2, step: IVec2| {
+            self.cell(cell.extend(level))
+                .is_some_and(|cell| cell.sides(blocking).towards(step))
+        };
+
+        blocks(from, step) || blocks(to, -step)
+    }
+
+    /// Whether a wall separates the neighbouring cells `from` and `to`
+    /// anywhere between the heights `bottom` and `top`.
+    pub fn wall_across(
+        &self,
+        from: IVec2,
+        to: IVec2,
+        bottom: f32,
+        top: f32,
+        blocking: Blocking,
+    ) -> bool {
+        (level_at(bottom + CONTACT)..=level_at(top - CONTACT))
+            .any(|level| self.wall_between(from, to, level, blocking))
+    }
+
+    /// Floor of the neighbouring cell `to` a body of `size` standing on
+    /// `ground` at `from` can move onto, at most `max_climb` higher or lower.
+    /// There must be room for the body above it and no wall on the way.
+    pub fn step(
+        &self,
+        map: &UncompressedMap,
+        from: IVec2,
+        ground: &Ground,
+        to: IVec2,
+        size: Vec3,
+        max_climb: f32,
+    ) -> Option<Ground> {
+        let next = ground::ground_below(map, to.as_vec2(), ground.height + max_climb)?;
+        let climbable = (next.height - ground.height).abs() <= max_climb + f32::EPSILON;
+
+        // NOTE: the body is lifted above the slope of the floor below it
+        let body = to.as_vec2().extend(next.height + size.x + size.z / 2.0);
+        let blocked = self.overlaps_box(body, size / 2.0, Blocking::Movement);
+        let bottom = ground.height.max(next.height);
+        let walled = self.wall_across(from, to, bottom, bottom + size.z, Blocking::Movement);
+
+        (climbable && !blocked && !walled).then_some(next)
+    }
+
+    /// Highest floor at the world `position` which is not above `max_z`.
+    pub fn height_at(&self, position: Vec2, max_z: f32) -> Option<f32> {
+        let cell = position.round();
+        let local = position - cell;
+        let top_level = level_at(max_z).min(UncompressedMap::Z as i32 - 1);
+
+        (0..=top_level).rev().find_map(|level| {
+            let block = self.cell(cell.as_ivec2().extend(level))?;
+            let floor = level as f32 - 0.5 + block.floor_height(local)?;
+
+            (floor <= max_z + f32::EPSILON).then_some(floor)
+        })
+    }
+
+    /// Height after `dt` seconds of a body standing at `position` and
+    /// falling with `vertical_speed`. Floors up to `step_height` above are
+    /// climbed, bodies on the ground stick to floors up to `step_height`
+    /// below and fall off higher edges.
+    pub fn fall(&self, position: Vec3, vertical_speed: &mut f32, step_height: f32, dt: f32) -> f32 {
+        let floor = self
+            .height_at(position.truncate(), position.z + step_height)
+            .unwrap_or(MAP_BOTTOM);
+        let grounded = *vertical_speed == 0.0;
+
+        if floor >= position.z || (grounded && floor >= position.z - step_height) {
+            *vertical_speed = 0.0;
+            return floor;
+        }
+
+        *vertical_speed -= GRAVITY * dt;
+        let z = (position.z + *vertical_speed * dt).max(floor);
+        if z == floor {
+            *vertical_speed = 0.0;
+        }
+
+        z
+    }
+
+    /// First solid surface or wall along the ray from `origin` in
+    /// `direction`, up to `max_distance`.
+    pub fn ray_cast(
+        &self,
+        origin: Vec3,
+        direction: Vec3,
+        max_distance: f32,
+        blocking: Blocking,
+    ) -> Option<RayHit> {
+        let direction = direction.try_normalize()?;
+        let (start, end) = clip_to_map(origin, direction)?;
+        let end = end.min(max_distance);
+        if start > end {
+            return None;
+        }
+
+        let hit = |distance: f32, normal: Vec3, block: IVec3| RayHit {
+            distance,
+            point: origin + direction * distance,
+            normal,
+            block,
+        };
+
+        // NOTE: voxel traversal, the next boundary of each axis is crossed
+        // after `next` and the boundaries are `delta` apart
+        let entry = origin + direction * start;
+        let mut block = (entry + 0.5).floor().as_ivec3().clamp(
+            IVec3::new(0, 1, 0),
+            IVec3::new(
+                UncompressedMap::X as i32 - 1,
+                UncompressedMap::Y as i32,
+                UncompressedMap::Z as i32 - 1,
+            ),
+        );
+        let step = direction.signum().as_ivec3();
+        let delta = direction.recip().abs();
+        let mut next = Vec3::select(
+            direction.cmpeq(Vec3::ZERO),
+            Vec3::INFINITY,
+            start + (block.as_vec3() + step.as_vec3() * 0.5 - entry) / direction,
+        );
+        let mut enter = (start, -direction);
+
+        loop {
+            let exit = next.min_element().min(end);
+            let cell = self.cell(block);
+            if let Some(distance_normal) =
+                cell.and_then(|cell| floor_hit(cell, block, origin, direction, enter, exit))
+            {
+                let (distance, normal) = distance_normal;
+                return Some(hit(distance, normal, block));
+            }
+
+            if next.min_element() > end {
+                return None;
+            }
+
+            let axis = (0..3)
+                .min_by(|a, b| next[*a].total_cmp(&next[*b]))
+                .unwrap_or(0);
+            let mut normal = Vec3::ZERO;
+            normal[axis] = -(step[axis] as f32);
+            let exit = next[axis];
+
+            if axis < 2 {
+                let mut neighbour = block;
+                neighbour[axis] += step[axis];
+                if self.wall_between(block.truncate(), neighbour.truncate(), block.z, blocking) {
+                    return Some(hit(exit, normal, block));
+                }
+            }
+
+            block[axis] += step[axis];
+            next[axis] += delta[axis];
+            enter = (exit, normal);
+        }
+    }
+
+    /// Whether a box with `half_size` around `center` overlaps solid blocks
+    /// or reaches across walls.
+    pub fn overlaps_box(&self, center: Vec3, half_size: Vec3, blocking: Blocking) -> bool {
+        let (min, max) = (center - half_size, center + half_size);
+        let first = (min + 0.5 + CONTACT).floor().as_ivec3();
+        let last = (max + 0.5 - CONTACT).floor().as_ivec3();
+
+        for z in first.z.max(0)..=last.z.min(UncompressedMap::Z as i32 - 1) {
+            for y in first.y..=last.y {
+                for x in first.x..=last.x {
+                    let block = IVec3::new(x, y, z);
+                    let centre = block.as_vec3();
+
+                    if let Some(cell) = self.cell(block) {
+                        let bottom = centre.z - 0.5;
+                        let footprint = (
+                            (min.truncate() - centre.truncate()).max(Vec2::splat(-0.5)) + CONTACT,
+                            (max.truncate() - centre.truncate()).min(Vec2::splat(0.5)) - CONTACT,
+                        );
+
+                        let solid = max.z > bottom + CONTACT
+                            && highest_floor(cell, footprint)
+                                .is_some_and(|floor| min.z < bottom + floor - CONTACT);
+                        if solid {
+                            return true;
+                        }
+                    }
+
+                    let from = IVec2::new(x, y);
+                    let across = (x < last.x
+                        && self.wall_between(from, from + IVec2::X, z, blocking))
+                        || (y < last.y && self.wall_between(from, from + IVec2::Y, z, blocking));
+                    if across {
+                        return true;
+                    }
+                }
+            }
+        }
+
+        false
+    }
+
+    /// Moves a box with `half_size` from `center` by `motion`, `None` if it
+    /// gets through, otherwise where it stops. Boxes starting in a collision
+    /// stop at once.
+    pub fn sweep_box(
+        &self,
+        center: Vec3,
+        half_size: Vec3,
+        motion: Vec3,
+        blocking: Blocking,
+    ) -> Option<SweepHit> {
+        let overlaps = |time: f32| self.overlaps_box(center + motion * time, half_size, blocking);
+        let length = motion.length();
+        let step = SWEEP_STEP.min(half_size.min_element());
+        let steps = (length / step).ceil().max(1.0) as u32;
+
+        let (mut free, mut blocked) = (0.0, None);
+        for i in 0..=steps {
+            let time = i as f32 / steps as f32;
+            if overlaps(time) {
+                blocked = Some(time);
+                break;
+            }
+            free = time;
+        }
+        let mut blocked = blocked?;
+
+        if blocked > 0.0 {
+            while (blocked - free) * length > CONTACT {
+                let time = (free + blocked) / 2.0;
+                if overlaps(time) {
+                    blocked = time;
+                } else {
+                    free = time;
+                }
+            }
+        }
+
+        let stop = center + motion * free;
+        let blocked_axis = |axis: usize| {
+            let mut probe = Vec3::ZERO;
+            probe[axis] = motion[axis].signum() * CONTACT * 10.0;
+            motion[axis] != 0.0 && self.overlaps_box(stop + probe, half_size, blocking)
+        };
+
+        Some(SweepHit {
+            time: free,
+            center: stop,
+            blocked: BVec3::new(blocked_axis(0), blocked_axis(1), blocked_axis(2)),
+        })
+    }
+}
+
+/// Distances at which the ray enters and leaves the map, `None` if it
+/// misses.
+fn clip_to_map(origin: Vec3, direction: Vec3) -> Option<(f32, f32)> {
+    let min = Vec3::new(-0.5, 0.5, -0.5);
+    let max = Vec3::new(
+        UncompressedMap::X as f32 - 0.5,
+        UncompressedMap::Y as f32 + 0.5,
+        UncompressedMap::Z as f32 - 0.5,
+    );
+
+    let (mut start, mut end) = (0.0_f32, f32::INFINITY);
+    for axis in 0..3 {
+        if direction[axis] == 0.0 {
+            if origin[axis] < min[axis] || origin[axis] > max[axis] {
+                return None;
+            }
+            continue;
+        }
+
+        let a = (min[axis] - origin[axis]) / direction[axis];
+        let b = (max[axis] - origin[axis]) / direction[axis];
+        start = start.max(a.min(b));
+        end = end.min(a.max(b));
+    }
+
+    (start <= end).then_some((start, end))
+}
+
+/// Where the ray first goes below the floor of `cell` between the
+/// distances `enter` and `exit`, with the normal of the surface. `enter`
+/// holds the normal of the face the ray came in through.
+fn floor_hit(
+    cell: &Cell,
+    block: IVec3,
+    origin: Vec3,
+    direction: Vec3,
+    (enter, enter_normal): (f32, Vec3),
+    exit: f32,
+) -> Option<(f32, Vec3)> {
+    let slope_type = cell.floor.as_ref()?;
+    let bottom = block.z as f32 - 0.5;
+    let local =
+        |distance: f32| (origin + direction * distance).truncate() - block.truncate().as_vec2();
+    let start = local(0.0);
+    let flat = direction.truncate();
+
+    // NOTE: the floor is linear between the lines it changes along, the
+    // ray is split where it crosses them
+    let mut crossings = vec![(enter, enter_normal), (exit, Vec3::ZERO)];
+    let mut cross = |offset: f32, along: f32, normal: Vec2| {
+        if along != 0.0 {
+            let distance = (offset - start.dot(normal)) / along;
+            if distance > enter && distance < exit {
+                let outward = -normal * along.signum();
+                crossings.push((distance, outward.extend(0.0).normalize()));
+            }
+        }
+    };
+    for edge in FLOOR_EDGES {
+        cross(edge, flat.x, Vec2::X);
+        cross(edge, flat.y, Vec2::Y);
+    }
+    cross(0.0, flat.x + flat.y, Vec2::ONE);
+    cross(0.0, flat.x - flat.y, Vec2::new(1.0, -1.0));
+    crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    for pair in crossings.windows(2) {
+        let ((a, normal), (b, _)) = (pair[0], pair[1]);
+        if b <= a {
+            continue;
+        }
+
+        // the floor is sampled inside the piece, its ends may belong to
+        // the neighbouring pieces
+        let (q1, q3) = (a + (b - a) / 4.0, a + (b - a) * 3.0 / 4.0);
+        let (Some(f1), Some(f3)) = (
+            ground::slope_height(slope_type, local(q1)),
+            ground::slope_height(slope_type, local(q3)),
+        ) else {
+            continue;
+        };
+        let floor_a = f1 - (f3 - f1) / 2.0;
+        let floor_b = f3 + (f3 - f1) / 2.0;
+
+        let above = |distance: f32, floor: f32| (origin + direction * distance).z - bottom - floor;
+        let (above_a, above_b) = (above(a, floor_a), above(b, floor_b));
+
+        if above_a <= 0.0 {
+            return Some((a, normal));
+        }
+        if above_b <= 0.0 {
+            let distance = a + (b - a) * above_a / (above_a - above_b);
+            let gradient = ground::slope_gradient(slope_type, local((a + b) / 2.0));
+            return Some((distance, (-gradient).extend(1.0).normalize()));
+        }
+    }
+
+    None
+}
+
+/// Highest floor of `cell` on the rectangle from `min` to `max` in block
+/// coordinates, `None` if it has no floor there.
+fn highest_floor(cell: &Cell, (min, max): (Vec2, Vec2)) -> Option<f32> {
+    if min.x > max.x || min.y > max.y {
+        return None;
+    }
+
+    // NOTE: the floor is linear between the lines it changes along, so its
+    // highest point is on a corner of the pieces the lines cut the
+    // rectangle into
+    let lines = |min: f32, max: f32| {
+        let mut values = vec![min, max];
+        values.extend(
+            FLOOR_EDGES
+                .into_iter()
+                .filter(|edge| (min..=max).contains(edge)),
+        );
+        values
+    };
+    let (xs, ys) = (lines(min.x, max.x), lines(min.y, max.y));
+
+    let mut points: Vec<Vec2> = xs
+        .iter()
+        .flat_map(|x| ys.iter().map(|y| Vec2::new(*x, *y)))
+        .collect();
+    for x in &xs {
+        points.extend([Vec2::new(*x, *x), Vec2::new(*x, -x)]);
+    }
+    for y in &ys {
+        points.extend([Vec2::new(*y, *y), Vec2::new(-y, *y)]);
+    }
+
+    points
+        .into_iter()
+        .filter(|point| point.cmpge(min).all() && point.cmple(max).all())
+        .filter_map(|point| cell.floor_height(point))
+        .reduce(f32::max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bevy::utils::default;
+
+    use crate::map::file::{tests::test_map_with_blocks, SlopeDirection, SlopeLevel};
+
+    /// Map with `blocks` at world cells, everything else air.
+    fn map(blocks: &[(IVec3, BlockInfo)]) -> CollisionMap {
+        CollisionMap::from(&test_map_with_blocks(blocks.iter().cloned()))
+    }
+
+    fn solid(slope_type: SlopeType) -> BlockInfo {
+        BlockInfo {
+            ground_type: GroundType::Pavement,
+            slope_type,
+            ..default()
+        }
+    }
+
+    #[test]
+    fn rays_stop_on_floors() {
+        let map = map(&[(IVec3::new(10, 10, 0), solid(SlopeType::None))]);
+
+        let hit = map
+            .ray_cast(
+                Vec3::new(10.2, 10.0, 5.0),
+                Vec3::NEG_Z,
+                10.0,
+                Blocking::Movement,
+            )
+            .unwrap();
+        assert!((hit.point.z - 0.5).abs() < 1e-4);
+        assert_eq!(hit.normal, Vec3::Z);
+        assert_eq!(hit.block, IVec3::new(10, 10, 0));
+
+        let side = map
+            .ray_cast(Vec3::new(5.0, 10.0, 0.0), Vec3::X, 10.0, Blocking::Movement)
+            .unwrap();
+        assert!((side.point.x - 9.5).abs() < 1e-4);
+        assert_eq!(side.normal, Vec3::NEG_X);
+
+        assert!(map
+            .ray_cast(Vec3::new(5.0, 10.0, 2.0), Vec3::X, 10.0, Blocking::Movement)
+            .is_none());
+    }
+
+    #[test]
+    fn rays_follow_ramps() {
+        let ramp = solid(SlopeType::Degree26 {
+            direction: SlopeDirection::Up,
+            level: SlopeLevel::High,
+        });
+        let map = map(&[(IVec3::new(10, 10, 0), ramp)]);
+
+        let hit = map
+            .ray_cast(
+                Vec3::new(10.0, 10.25, 5.0),
+                Vec3::NEG_Z,
+                10.0,
+                Blocking::Movement,
+            )
+            .unwrap();
+        // halfway up the upper half of the slope
+        assert!((hit.point.z - 0.375).abs() < 1e-4);
+        assert!(hit.normal.y < 0.0 && hit.normal.z > 0.0);
+        assert_eq!(
+            map.height_at(Vec2::new(10.0, 10.25), 5.0),
+            Some(hit.point.z)
+        );
+    }
+
+    #[test]
+    fn walls_block_movement_and_bullet_walls_bullets() {
+        let mut fence = BlockInfo::default();
+        fence.right.wall = true;
+        let map = map(&[(IVec3::new(10, 10, 0), fence)]);
+
+        let walk = map.ray_cast(Vec3::new(9.0, 10.0, 0.0), Vec3::X, 5.0, Blocking::Movement);
+        assert!((walk.unwrap().point.x - 10.5).abs() < 1e-4);
+        assert!(map
+            .ray_cast(Vec3::new(9.0, 10.0, 0.0), Vec3::X, 5.0, Blocking::Bullets)
+            .is_none());
+
+        let half_size = Vec3::splat(0.2);
+        assert!(!map.overlaps_box(Vec3::new(10.0, 10.0, 0.0), half_size, Blocking::Movement));
+        assert!(map.overlaps_box(Vec3::new(10.5, 10.0, 0.0), half_size, Blocking::Movement));
+    }
+
+    #[test]
+    fn open_sides_of_diagonals_are_not_walls() {
+        let mut diagonal = solid(SlopeType::Diagonal(DiagonalType::UpLeft));
+        diagonal.left.wall = true;
+        diagonal.right.wall = true;
+        let map = map(&[(IVec3::new(10, 10, 0), diagonal)]);
+
+        let (cell, level) = (IVec2::new(10, 10), 0);
+        assert!(!map.wall_between(cell - IVec2::X, cell, level, Blocking::Movement));
+        assert!(map.wall_between(cell, cell + IVec2::X, level, Blocking::Movement));
+    }
+
+    #[test]
+    fn bodies_climb_steps_and_fall_off_edges() {
+        let map = map(&[(IVec3::new(5, 5, 0), solid(SlopeType::None))]);
+        let mut vertical_speed = 0.0;
+
+        // onto the block from the floor of the empty level below
+        assert_eq!(
+            map.fall(Vec3::new(5.0, 5.0, 0.4), &mut vertical_speed, 0.2, 0.1),
+            0.5
+        );
+        // next to it there is no floor down to the bottom of the map
+        let z = map.fall(Vec3::new(6.0, 5.0, 0.5), &mut vertical_speed, 0.2, 0.1);
+        assert!(z < 0.5 && vertical_speed < 0.0);
+
+        let mut z = z;
+        while vertical_speed != 0.0 {
+            z = map.fall(Vec3::new(6.0, 5.0, z), &mut vertical_speed, 0.2, 0.1);
+        }
+        assert_eq!(z, MAP_BOTTOM);
+    }
+
+    #[test]
+    fn swept_boxes_stop_at_blocks() {
+        let map = map(&[(IVec3::new(12, 10, 0), solid(SlopeType::None))]);
+        let half_size = Vec3::splat(0.25);
+
+        let hit = map
+            .sweep_box(
+                Vec3::new(9.0, 10.0, 0.0),
+                half_size,
+                Vec3::new(4.0, 0.0, 0.0),
+                Blocking::Movement,
+            )
+            .unwrap();
+        assert!((hit.center.x - 11.25).abs() < 1e-3);
+        assert!(hit.blocked.x && !hit.blocked.y && !hit.blocked.z);
+
+        // a box resting on the block slides over it
+        assert!(map
+            .sweep_box(
+                Vec3::new(11.0, 10.0, 0.75),
+                half_size,
+                Vec3::new(2.0, 0.0, 0.0),
+                Blocking::Movement,
+            )
+            .is_none());
+    }
+
+    #[test]
+    fn partial_blocks_are_solid_on_their_footprint() {
+        let map = map(&[(IVec3::new(10, 10, 0), solid(SlopeType::PartialCentre))]);
+        let half_size = Vec3::splat(0.1);
+
+        assert!(map.overlaps_box(Vec3::new(10.0, 10.0, 0.0), half_size, Blocking::Movement));
+        assert!(!map.overlaps_box(Vec3::new(10.35, 10.0, 0.0), half_size, Blocking::Movement));
+        assert_eq!(map.height_at(Vec2::new(10.4, 10.0), 5.0), None);
+    }
+}
@@ -0,0 +1,495 @@
+//! Editor mode changing the faces and slopes of the selected block, adding
+//! and removing blocks, with undo and saving as GMP file.
+
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+use bevy::{ecs::system::SystemParam, input::common_conditions::input_just_pressed, prelude::*};
+use bevy_panorbit_camera::PanOrbitCamera;
+
+use super::{
+    block,
+    file::{BlockInfo, Face, Rotate, SlopeType, UncompressedMap},
+    inspector::{Selection, FACE_NAMES},
+    writer, CurrentMap, GameFilesPath, Map, MapFileAsset, MapState, TextureIndex, UnloadedBlock,
+};
+use crate::camera::CameraMode;
+
+pub fn plugin(app: &mut App) {
+    app.init_resource::<Editor>()
+        .init_resource::<History>()
+        .add_systems(Startup, spawn_editor_text)
+        .add_systems(Update, toggle_editor.run_if(input_just_pressed(TOGGLE_KEY)))
+        .add_systems(
+            Update,
+            (move_selection, edit_block, undo_redo, save_map)
+                .chain()
+                .run_if(editor_enabled)
+                .run_if(in_state(CameraMode::Orbit))
+                .run_if(in_state(MapState::Loaded)),
+        )
+        .add_systems(
+            Update,
+            update_editor_text.run_if(resource_changed::<Editor>.or(resource_changed::<History>)),
+        );
+}
+
+const TOGGLE_KEY: KeyCode = KeyCode::KeyE;
+const FACE_KEYS: [KeyCode; 5] = [
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+];
+const PREVIOUS_TILE_KEY: KeyCode = KeyCode::BracketLeft;
+const NEXT_TILE_KEY: KeyCode = KeyCode::BracketRight;
+const ROTATE_KEY: KeyCode = KeyCode::KeyR;
+const FLIP_KEY: KeyCode = KeyCode::KeyH;
+const FLAT_KEY: KeyCode = KeyCode::KeyT;
+const PREVIOUS_SLOPE_KEY: KeyCode = KeyCode::Comma;
+const NEXT_SLOPE_KEY: KeyCode = KeyCode::Period;
+const COPY_KEY: KeyCode = KeyCode::KeyK;
+const PLACE_KEY: KeyCode = KeyCode::Insert;
+const REMOVE_KEY: KeyCode = KeyCode::Delete;
+// with control
+const UNDO_KEY: KeyCode = KeyCode::KeyZ;
+const REDO_KEY: KeyCode = KeyCode::KeyY;
+const SAVE_KEY: KeyCode = KeyCode::KeyS;
+
+/// Tiles skipped at once while holding shift.
+const FAST_TILE_STEP: usize = 10;
+const SLOPE_TYPES_COUNT: u8 = 64;
+
+#[derive(Resource, Debug, Default)]
+struct Editor {
+    enabled: bool,
+    /// Index into [`FACE_NAMES`] of the edited face.
+    face: usize,
+    /// Block placed by the place key, set by copying or removing a block.
+    brush: Option<BlockInfo>,
+}
+
+#[derive(Debug)]
+struct Edit {
+    pos: block::Position,
+    before: BlockInfo,
+    after: BlockInfo,
+}
+
+#[derive(Resource, Debug, Default)]
+struct History {
+    undo: Vec<Edit>,
+    redo: Vec<Edit>,
+    unsaved: bool,
+}
+
+impl History {
+    /// Records a new edit, which can not be redone after other edits.
+    fn push(&mut self, edit: Edit) {
+        self.undo.push(edit);
+        self.redo.clear();
+        self.unsaved = true;
+    }
+
+    /// Edit to revert by setting its block back to `before`.
+    fn undo(&mut self) -> Option<&Edit> {
+        let edit = self.undo.pop()?;
+        self.redo.push(edit);
+        self.unsaved = true;
+        self.redo.last()
+    }
+
+    /// Edit to apply again by setting its block to `after`.
+    fn redo(&mut self) -> Option<&Edit> {
+        let edit = self.redo.pop()?;
+        self.undo.push(edit);
+        self.unsaved = true;
+        self.undo.last()
+    }
+}
+
+/// Blocks of the loaded map and their entities.
+#[derive(SystemParam)]
+struct MapBlocks<'w, 's> {
+    commands: Commands<'w, 's>,
+    map: Option<Res<'w, Map>>,
+    map_assets: ResMut<'w, Assets<MapFileAsset>>,
+    blocks: Query<'w, 's, (Entity, &'static block::Block)>,
+}
+
+impl MapBlocks<'_, '_> {
+    fn get(&self, pos: block::Position) -> Option<&BlockInfo> {
+        let (x, y, z) = pos.file_coordinates()?;
+        let map = self.map_assets.get(&self.map.as_ref()?.asset)?;
+
+        map.0.uncompressed_map.as_ref()?.get(x, y, z)
+    }
+
+    /// Stores `info` at `pos` and spawns the block again. Blocks are meshed
+    /// on their own, flat faces drawn into a neighbouring cell are children
+    /// of their block, so no other block needs to be spawned again.
+    fn set(&mut self, pos: block::Position, info: BlockInfo) {
+        let Some((x, y, z)) = pos.file_coordinates() else {
+            return;
+        };
+        let Some(map) = self
+            .map
+            .as_ref()
+            .and_then(|map| self.map_assets.get_mut(&map.asset))
+        else {
+            return;
+        };
+        let Some(block) = map
+            .0
+            .uncompressed_map
+            .as_mut()
+            .and_then(|map| map.get_mut(x, y, z))
+        else {
+            return;
+        };
+        *block = info.clone();
+
+        for (entity, block) in &self.blocks {
+            if block.pos == pos {
+                self.commands.entity(entity).despawn_recursive();
+            }
+        }
+        self.commands.spawn(UnloadedBlock { info, pos });
+    }
+}
+
+fn editor_enabled(editor: Res<Editor>) -> bool {
+    editor.enabled
+}
+
+#[derive(Component)]
+struct EditorText;
+
+fn spawn_editor_text(mut commands: Commands) {
+    commands.spawn((
+        EditorText,
+        Text::default(),
+        TextFont {
+            font_size: 12.0,
+            ..default()
+        },
+        Node {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(10.0),
+            left: Val::Px(10.0),
+            padding: UiRect::all(Val::Px(8.0)),
+            ..default()
+        },
+        BackgroundColor(Color::BLACK.with_alpha(0.7)),
+        Visibility::Hidden,
+    ));
+}
+
+fn toggle_editor(mut editor: ResMut<Editor>) {
+    editor.enabled = !editor.enabled;
+    info!("Editor: {}", editor.enabled);
+}
+
+/// Moves the selection with the arrow keys, up and down a level while
+/// holding shift, and keeps the camera on it.
+fn move_selection(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut selection: ResMut<Selection>,
+    mut cameras: Query<&mut PanOrbitCamera>,
+) {
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    let mut step = IVec3::ZERO;
+    for (key, direction) in [
+        (KeyCode::ArrowUp, if shift { IVec3::Z } else { IVec3::Y }),
+        (
+            KeyCode::ArrowDown,
+            if shift { IVec3::NEG_Z } else { IVec3::NEG_Y },
+        ),
+        (KeyCode::ArrowLeft, IVec3::NEG_X),
+        (KeyCode::ArrowRight, IVec3::X),
+    ] {
+        if keys.just_pressed(key) {
+            step += direction;
+        }
+    }
+    if step == IVec3::ZERO {
+        return;
+    }
+
+    let pos = selection.0.or_else(|| {
+        // start at the camera focus
+        let focus = cameras.iter().next()?.focus.round();
+        Some(block::Position {
+            x: focus.x.clamp(0.0, 255.0) as u8,
+            y: focus.y.clamp(1.0, UncompressedMap::Y as f32) as u16,
+            z: focus.z.clamp(0.0, (UncompressedMap::Z - 1) as f32) as u8,
+        })
+    });
+    let Some(pos) = pos else {
+        return;
+    };
+
+    let moved = (|| {
+        Some(block::Position {
+            x: pos.x.checked_add_signed(step.x as i8)?,
+            y: pos.y.checked_add_signed(step.y as i16)?,
+            z: pos.z.checked_add_signed(step.z as i8)?,
+        })
+    })()
+    .filter(|moved| moved.file_coordinates().is_some())
+    .unwrap_or(pos);
+
+    selection.0 = Some(moved);
+    for mut camera in &mut cameras {
+        camera.target_focus = Vec3::from(moved);
+    }
+}
+
+fn edit_block(
+    keys: Res<ButtonInput<KeyCode>>,
+    textures: Res<TextureIndex>,
+    mut editor: ResMut<Editor>,
+    mut history: ResMut<History>,
+    mut selection: ResMut<Selection>,
+    mut blocks: MapBlocks,
+) {
+    if keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        return;
+    }
+    let Some(pos) = selection.0 else {
+        return;
+    };
+    let Some(before) = blocks.get(pos).cloned() else {
+        return;
+    };
+
+    if let Some(face) = FACE_KEYS.iter().position(|&key| keys.just_pressed(key)) {
+        editor.face = face;
+    }
+
+    let mut after = before.clone();
+    let tile_step = if keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        FAST_TILE_STEP
+    } else {
+        1
+    };
+    let last_tile = textures.index.len().saturating_sub(1);
+
+    let face = face_mut(&mut after, editor.face);
+    if keys.just_pressed(NEXT_TILE_KEY) {
+        face.tile_id = (face.tile_id + tile_step).min(last_tile);
+    }
+    if keys.just_pressed(PREVIOUS_TILE_KEY) {
+        face.tile_id = face.tile_id.saturating_sub(tile_step);
+    }
+    if keys.just_pressed(ROTATE_KEY) {
+        face.rotate = Rotate::from((u8::from(face.rotate) + 1) % 4);
+    }
+    if keys.just_pressed(FLIP_KEY) {
+        face.flip = !face.flip;
+    }
+    if keys.just_pressed(FLAT_KEY) {
+        face.flat = !face.flat;
+    }
+
+    let mut slope = u8::from(&after.slope_type);
+    if keys.just_pressed(NEXT_SLOPE_KEY) {
+        slope = (slope + 1) % SLOPE_TYPES_COUNT;
+    }
+    if keys.just_pressed(PREVIOUS_SLOPE_KEY) {
+        slope = (slope + SLOPE_TYPES_COUNT - 1) % SLOPE_TYPES_COUNT;
+    }
+    // NOTE: diagonals turn three-sided when their lid is set to the marker
+    after.slope_type = SlopeType::decode(slope << 2, after.lid.tile_id);
+
+    if keys.just_pressed(COPY_KEY) {
+        editor.brush = Some(before.clone());
+    }
+    if keys.just_pressed(PLACE_KEY) {
+        match &editor.brush {
+            Some(brush) => after = brush.clone(),
+            None => warn!("Copy a block first to place it"),
+        }
+    }
+    if keys.just_pressed(REMOVE_KEY) {
+        editor.brush = Some(before.clone());
+        after = BlockInfo::default();
+    }
+
+    if after.to_bytes() == before.to_bytes() {
+        return;
+    }
+
+    blocks.set(pos, after.clone());
+    history.push(Edit { pos, before, after });
+    selection.set_changed();
+}
+
+fn undo_redo(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut history: ResMut<History>,
+    mut selection: ResMut<Selection>,
+    mut blocks: MapBlocks,
+) {
+    if !keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        return;
+    }
+
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    let redo = keys.just_pressed(REDO_KEY) || (shift && keys.just_pressed(UNDO_KEY));
+    let undo = !redo && keys.just_pressed(UNDO_KEY);
+
+    let edit = if undo {
+        history.undo().map(|edit| (edit.pos, edit.before.clone()))
+    } else if redo {
+        history.redo().map(|edit| (edit.pos, edit.after.clone()))
+    } else {
+        None
+    };
+
+    if let Some((pos, info)) = edit {
+        blocks.set(pos, info);
+        selection.0 = Some(pos);
+    }
+}
+
+fn save_map(
+    keys: Res<ButtonInput<KeyCode>>,
+    game_files_path: Res<GameFilesPath>,
+    current_map: Res<CurrentMap>,
+    map: Option<Res<Map>>,
+    map_assets: Res<Assets<MapFileAsset>>,
+    mut history: ResMut<History>,
+) {
+    if !keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight])
+        || !keys.just_pressed(SAVE_KEY)
+    {
+        return;
+    }
+    let Some(uncompressed) = map
+        .and_then(|map| map_assets.get(&map.asset))
+        .and_then(|map| map.0.uncompressed_map.as_ref())
+    else {
+        return;
+    };
+
+    // the original file provides the chunks which are not edited
+    let original_path = game_files_path.0.join(current_map.0.get_map_file_name());
+    let path: PathBuf = game_files_path
+        .0
+        .join(format!("{}_edited.gmp", current_map.0.get_base_name()));
+
+    let result = fs::read(&original_path).and_then(|original| {
+        let mut file = BufWriter::new(File::create(&path)?);
+        writer::write_map(uncompressed, &original, &mut file)?;
+        file.flush()
+    });
+
+    match result {
+        Ok(()) => {
+            info!("Saved map to {}", path.display());
+            history.unsaved = false;
+        }
+        Err(err) => error!("Could not save map to {}: {err}", path.display()),
+    }
+}
+
+fn face_mut(block: &mut BlockInfo, index: usize) -> &mut Face {
+    match index {
+        0 => &mut block.lid,
+        1 => &mut block.left,
+        2 => &mut block.right,
+        3 => &mut block.top,
+        _ => &mut block.bottom,
+    }
+}
+
+fn update_editor_text(
+    editor: Res<Editor>,
+    history: Res<History>,
+    mut texts: Query<(&mut Text, &mut Visibility), With<EditorText>>,
+) {
+    let Ok((mut text, mut visibility)) = texts.get_single_mut() else {
+        return;
+    };
+
+    if !editor.enabled {
+        *visibility = Visibility::Hidden;
+        return;
+    }
+    *visibility = Visibility::Visible;
+
+    **text = format!(
+        "Editing {} face, {} edits{}, brush: {}\n\
+         Arrows (+Shift up/down) select  1-5 face  [ ] tile (+Shift by {FAST_TILE_STEP})\n\
+         R rotate  H flip  T flat  , . slope  K copy  Insert place  Delete remove\n\
+         Ctrl+Z undo  Ctrl+Y redo  Ctrl+S save",
+        FACE_NAMES[editor.face],
+        history.undo.len(),
+        if history.unsaved { " (unsaved)" } else { "" },
+        editor
+            .brush
+            .as_ref()
+            .map_or("-".to_string(), |brush| format!("{:?}", brush.slope_type)),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edit(x: u8, tile_id: usize) -> Edit {
+        let mut after = BlockInfo::default();
+        after.lid.tile_id = tile_id;
+
+        Edit {
+            pos: block::Position { x, y: 1, z: 0 },
+            before: BlockInfo::default(),
+            after,
+        }
+    }
+
+    #[test]
+    fn edits_are_undone_and_redone_in_order() {
+        let mut history = History::default();
+        history.push(edit(1, 10));
+        history.push(edit(2, 20));
+
+        assert_eq!(history.undo().map(|edit| edit.after.lid.tile_id), Some(20));
+        assert_eq!(history.undo().map(|edit| edit.after.lid.tile_id), Some(10));
+        assert!(history.undo().is_none());
+
+        assert_eq!(history.redo().map(|edit| edit.pos.x), Some(1));
+        assert_eq!(history.redo().map(|edit| edit.pos.x), Some(2));
+        assert!(history.redo().is_none());
+        assert_eq!(history.undo.len(), 2);
+    }
+
+    #[test]
+    fn new_edits_clear_the_redo_stack() {
+        let mut history = History::default();
+        history.push(edit(1, 10));
+        history.push(edit(2, 20));
+        history.undo();
+
+        history.push(edit(3, 30));
+
+        assert!(history.redo().is_none());
+        assert_eq!(history.undo().map(|edit| edit.pos.x), Some(3));
+        assert_eq!(history.undo().map(|edit| edit.pos.x), Some(1));
+    }
+
+    #[test]
+    fn undo_without_edits_does_nothing() {
+        let mut history = History::default();
+
+        assert!(history.undo().is_none());
+        assert!(history.redo().is_none());
+        assert!(history.undo.is_empty() && history.redo.is_empty());
+        assert!(!history.unsaved);
+    }
+}
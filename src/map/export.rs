@@ -0,0 +1,666 @@
+//! Export of the map geometry as glTF binary or OBJ+MTL.
+//!
+//! The writers work on plain [`ExportFace`]s in map space (Z up) and convert
+//! to the Y up convention of both formats.
+
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use bevy::{input::common_conditions::input_just_pressed, prelude::*};
+use bevy_panorbit_camera::PanOrbitCamera;
+use serde_json::{json, Value};
+
+use super::{
+    block::{self, ModelFace},
+    file::UncompressedMap,
+    CurrentMap, Map, MapFileAsset, MapState, TextureIndex,
+};
+use crate::{
+    style::tile::IMAGE_SIZE,
+    tile_sheet::{self, TileSheetError},
+};
+
+pub fn plugin(app: &mut App) {
+    app.add_systems(
+        Update,
+        export_map
+            .run_if(input_just_pressed(EXPORT_GLB_KEY).or(input_just_pressed(EXPORT_OBJ_KEY)))
+            .run_if(in_state(MapState::Loaded)),
+    );
+}
+
+const EXPORT_GLB_KEY: KeyCode = KeyCode::KeyG;
+const EXPORT_OBJ_KEY: KeyCode = KeyCode::KeyO;
+/// While pressed, only the region around the camera focus is exported.
+const REGION_MODIFIER_KEY: KeyCode = KeyCode::ShiftLeft;
+const REGION_RADIUS: i32 = 16;
+const EXPORT_DIR: &str = "export";
+
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub enum ExportError {
+    /// An [IO](std::io) Error
+    #[error("Could not write file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not write texture: {0}")]
+    Texture(#[from] TileSheetError),
+    #[error("Could not serialize glTF: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ExportFormat {
+    Glb,
+    Obj,
+}
+
+/// Inclusive range of block positions.
+#[derive(Debug, Clone, Copy)]
+pub struct Region {
+    pub min: IVec3,
+    pub max: IVec3,
+}
+
+impl Region {
+    /// All Z levels of the square with `radius` around `center`.
+    pub fn around(center: Vec3, radius: i32) -> Self {
+        let center = center.round().as_ivec3();
+
+        Self {
+            min: IVec3::new(center.x - radius, center.y - radius, 0),
+            max: IVec3::new(center.x + radius, center.y + radius, i32::MAX),
+        }
+    }
+
+    pub fn contains(&self, position: Vec3) -> bool {
+        let position = position.round().as_ivec3();
+        position.cmpge(self.min).all() && position.cmple(self.max).all()
+    }
+}
+
+/// A single face in map space with flip and rotation already applied to its
+/// UVs.
+#[derive(Debug, Clone, Default)]
+pub struct ExportFace {
+    pub tile_id: usize,
+    pub transparent: bool,
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub uvs: Vec<[f32; 2]>,
+    pub indices: Vec<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct MaterialKey {
+    tile_id: usize,
+    transparent: bool,
+}
+
+impl MaterialKey {
+    fn name(&self) -> String {
+        if self.transparent {
+            format!("tile_{:04}_flat", self.tile_id)
+        } else {
+            format!("tile_{:04}", self.tile_id)
+        }
+    }
+}
+
+fn group_by_material(faces: &[ExportFace]) -> BTreeMap<MaterialKey, Vec<&ExportFace>> {
+    let mut groups: BTreeMap<_, Vec<_>> = BTreeMap::new();
+    for face in faces {
+        let key = MaterialKey {
+            tile_id: face.tile_id,
+            transparent: face.transparent,
+        };
+        groups.entry(key).or_default().push(face);
+    }
+
+    groups
+}
+
+fn y_up(v: [f32; 3]) -> [f32; 3] {
+    [v[0], v[2], -v[1]]
+}
+
+/// Writes the faces as a single glTF binary with one primitive and one
+/// embedded PNG texture per tile. `textures` resolves a tile id into 64x64
+/// RGBA pixels.
+pub fn write_glb<W: Write>(
+    faces: &[ExportFace],
+    textures: impl Fn(usize) -> Option<Vec<u8>>,
+    writer: &mut W,
+) -> Result<(), ExportError> {
+    let mut glb = GlbBuilder::default();
+    let mut images = Vec::new();
+    let mut image_index = BTreeMap::new();
+    let mut materials = Vec::new();
+    let mut primitives = Vec::new();
+
+    for (key, faces) in group_by_material(faces) {
+        let texture = match image_index.get(&key.tile_id) {
+            Some(index) => Some(*index),
+            None => match textures(key.tile_id) {
+                Some(rgba) => {
+                    let mut png = Vec::new();
+                    tile_sheet::encode_png(&mut png, IMAGE_SIZE, IMAGE_SIZE, &rgba)?;
+                    let view = glb.push_view(&png, None);
+                    images.push(json!({ "bufferView": view, "mimeType": "image/png" }));
+
+                    let index = images.len() - 1;
+                    image_index.insert(key.tile_id, index);
+                    Some(index)
+                }
+                None => None,
+            },
+        };
+
+        let mut material = json!({
+            "name": key.name(),
+            "pbrMetallicRoughness": { "metallicFactor": 0.0, "roughnessFactor": 1.0 },
+        });
+        if let Some(texture) = texture {
+            material["pbrMetallicRoughness"]["baseColorTexture"] = json!({ "index": texture });
+        }
+        if key.transparent {
+            material["alphaMode"] = json!("MASK");
+            material["alphaCutoff"] = json!(0.5);
+        }
+        materials.push(material);
+
+        let mut positions = Vec::new();
+        let mut normals = Vec::new();
+        let mut uvs = Vec::new();
+        let mut indices = Vec::new();
+        for face in faces {
+            let offset = positions.len() as u32;
+            positions.extend(face.positions.iter().copied().map(y_up));
+            normals.extend(face.normals.iter().copied().map(y_up));
+            uvs.extend(face.uvs.iter().copied());
+            indices.extend(face.indices.iter().map(|i| i + offset));
+        }
+
+        primitives.push(json!({
+            "attributes": {
+                "POSITION": glb.push_vec3(&positions, true),
+                "NORMAL": glb.push_vec3(&normals, false),
+                "TEXCOORD_0": glb.push_vec2(&uvs),
+            },
+            "indices": glb.push_indices(&indices),
+            "material": materials.len() - 1,
+        }));
+    }
+
+    let textures: Vec<_> = (0..images.len())
+        .map(|source| json!({ "source": source, "sampler": 0 }))
+        .collect();
+
+    const NEAREST: u32 = 9728;
+    const CLAMP_TO_EDGE: u32 = 33071;
+    let mut document = json!({
+        "asset": { "version": "2.0", "generator": "arrie" },
+        "scene": 0,
+        "scenes": [{ "nodes": [] }],
+    });
+
+    // NOTE: glTF does not allow empty arrays at top level
+    let mut insert = |key: &str, values: Vec<Value>| {
+        if !values.is_empty() {
+            document[key] = Value::Array(values);
+        }
+    };
+    if !primitives.is_empty() {
+        insert("nodes", vec![json!({ "name": "map", "mesh": 0 })]);
+        insert(
+            "meshes",
+            vec![json!({ "name": "map", "primitives": primitives })],
+        );
+    }
+    insert("materials", materials);
+    insert("textures", textures);
+    insert("images", images);
+    insert("accessors", glb.accessors);
+    insert("bufferViews", glb.views);
+    if !glb.bin.is_empty() {
+        insert("buffers", vec![json!({ "byteLength": glb.bin.len() })]);
+        insert(
+            "samplers",
+            vec![json!({
+                "magFilter": NEAREST,
+                "minFilter": NEAREST,
+                "wrapS": CLAMP_TO_EDGE,
+                "wrapT": CLAMP_TO_EDGE,
+            })],
+        );
+    }
+    if document.get("nodes").is_some() {
+        document["scenes"][0]["nodes"] = json!([0]);
+    }
+
+    let mut json = serde_json::to_vec(&document)?;
+    pad(&mut json, b' ');
+    let mut bin = glb.bin;
+    pad(&mut bin, 0);
+
+    const GLB_MAGIC: &[u8; 4] = b"glTF";
+    const GLB_VERSION: u32 = 2;
+    const CHUNK_JSON: &[u8; 4] = b"JSON";
+    const CHUNK_BIN: &[u8; 4] = b"BIN\0";
+    let bin_chunk_len = if bin.is_empty() { 0 } else { 8 + bin.len() };
+    let total_len = 12 + 8 + json.len() + bin_chunk_len;
+
+    writer.write_all(GLB_MAGIC)?;
+    writer.write_all(&GLB_VERSION.to_le_bytes())?;
+    writer.write_all(&(total_len as u32).to_le_bytes())?;
+    writer.write_all(&(json.len() as u32).to_le_bytes())?;
+    writer.write_all(CHUNK_JSON)?;
+    writer.write_all(&json)?;
+    if !bin.is_empty() {
+        writer.write_all(&(bin.len() as u32).to_le_bytes())?;
+        writer.write_all(CHUNK_BIN)?;
+        writer.write_all(&bin)?;
+    }
+
+    Ok(())
+}
+
+fn pad(bytes: &mut Vec<u8>, value: u8) {
+    while bytes.len() % 4 != 0 {
+        bytes.push(value);
+    }
+}
+
+#[derive(Default)]
+struct GlbBuilder {
+    bin: Vec<u8>,
+    views: Vec<Value>,
+    accessors: Vec<Value>,
+}
+
+impl GlbBuilder {
+    const ARRAY_BUFFER: u32 = 34962;
+    const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+    const FLOAT: u32 = 5126;
+    const UNSIGNED_INT: u32 = 5125;
+
+    fn push_view(&mut self, data: &[u8], target: Option<u32>) -> usize {
+        pad(&mut self.bin, 0);
+
+        let mut view = json!({
+            "buffer": 0,
+            "byteOffset": self.bin.len(),
+            "byteLength": data.len(),
+        });
+        if let Some(target) = target {
+            view["target"] = json!(target);
+        }
+
+        self.bin.extend_from_slice(data);
+        self.views.push(view);
+        self.views.len() - 1
+    }
+
+    fn push_accessor(&mut self, accessor: Value) -> usize {
+        self.accessors.push(accessor);
+        self.accessors.len() - 1
+    }
+
+    fn push_vec3(&mut self, values: &[[f32; 3]], bounds: bool) -> usize {
+        let data: Vec<u8> = values
+            .iter()
+            .flatten()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        let view = self.push_view(&data, Some(Self::ARRAY_BUFFER));
+
+        let mut accessor = json!({
+            "bufferView": view,
+            "componentType": Self::FLOAT,
+            "count": values.len(),
+            "type": "VEC3",
+        });
+        if bounds {
+            let mut min = [f32::MAX; 3];
+            let mut max = [f32::MIN; 3];
+            for value in values {
+                for i in 0..3 {
+                    min[i] = min[i].min(value[i]);
+                    max[i] = max[i].max(value[i]);
+                }
+            }
+            accessor["min"] = json!(min);
+            accessor["max"] = json!(max);
+        }
+
+        self.push_accessor(accessor)
+    }
+
+    fn push_vec2(&mut self, values: &[[f32; 2]]) -> usize {
+        let data: Vec<u8> = values
+            .iter()
+            .flatten()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        let view = self.push_view(&data, Some(Self::ARRAY_BUFFER));
+
+        self.push_accessor(json!({
+            "bufferView": view,
+            "componentType": Self::FLOAT,
+            "count": values.len(),
+            "type": "VEC2",
+        }))
+    }
+
+    fn push_indices(&mut self, indices: &[u32]) -> usize {
+        let data: Vec<u8> = indices.iter().flat_map(|i| i.to_le_bytes()).collect();
+        let view = self.push_view(&data, Some(Self::ELEMENT_ARRAY_BUFFER));
+
+        self.push_accessor(json!({
+            "bufferView": view,
+            "componentType": Self::UNSIGNED_INT,
+            "count": indices.len(),
+            "type": "SCALAR",
+        }))
+    }
+}
+
+/// Writes `<name>.obj` and `<name>.mtl` into `dir` and the used tiles as PNG
+/// into `dir/textures`.
+pub fn write_obj(
+    faces: &[ExportFace],
+    textures: impl Fn(usize) -> Option<Vec<u8>>,
+    dir: &Path,
+    name: &str,
+) -> Result<(), ExportError> {
+    const TEXTURE_DIR: &str = "textures";
+    fs::create_dir_all(dir.join(TEXTURE_DIR))?;
+
+    let mut obj = BufWriter::new(File::create(dir.join(format!("{name}.obj")))?);
+    let mut mtl = BufWriter::new(File::create(dir.join(format!("{name}.mtl")))?);
+
+    writeln!(obj, "mtllib {name}.mtl")?;
+    writeln!(obj, "o {name}")?;
+
+    let mut written_textures = BTreeMap::new();
+    let mut offset = 1;
+    for (key, faces) in group_by_material(faces) {
+        let material = key.name();
+        writeln!(mtl, "newmtl {material}")?;
+        writeln!(mtl, "Kd 1.0 1.0 1.0")?;
+
+        let texture = match written_textures.get(&key.tile_id) {
+            Some(texture) => Some(texture),
+            None => match textures(key.tile_id) {
+                Some(rgba) => {
+                    let texture =
+                        format!("{TEXTURE_DIR}/{}", tile_sheet::tile_file_name(key.tile_id));
+                    let file = BufWriter::new(File::create(dir.join(&texture))?);
+                    tile_sheet::encode_png(file, IMAGE_SIZE, IMAGE_SIZE, &rgba)?;
+
+                    written_textures.insert(key.tile_id, texture);
+                    written_textures.get(&key.tile_id)
+                }
+                None => None,
+            },
+        };
+        if let Some(texture) = texture {
+            writeln!(mtl, "map_Kd {texture}")?;
+            if key.transparent {
+                writeln!(mtl, "map_d {texture}")?;
+            }
+        }
+        writeln!(mtl)?;
+
+        writeln!(obj, "usemtl {material}")?;
+        for face in faces {
+            for [x, y, z] in face.positions.iter().copied().map(y_up) {
+                writeln!(obj, "v {x} {y} {z}")?;
+            }
+            for [u, v] in &face.uvs {
+                // NOTE: OBJ has its UV origin in the bottom left corner
+                writeln!(obj, "vt {u} {}", 1.0 - v)?;
+            }
+            for [x, y, z] in face.normals.iter().copied().map(y_up) {
+                writeln!(obj, "vn {x} {y} {z}")?;
+            }
+            for triangle in face.indices.chunks_exact(3) {
+                let [a, b, c] = [0, 1, 2].map(|i| triangle[i] + offset);
+                writeln!(obj, "f {a}/{a}/{a} {b}/{b}/{b} {c}/{c}/{c}")?;
+            }
+
+            offset += face.positions.len() as u32;
+        }
+    }
+
+    obj.flush()?;
+    mtl.flush()?;
+
+    Ok(())
+}
+
+/// Faces of every block of `map`, or only of those in `region`, built from
+/// the block models without spawning them.
+pub fn map_faces(map: &UncompressedMap, region: Option<Region>) -> Vec<ExportFace> {
+    let mut faces = Vec::new();
+
+    for (i, info) in map.0.iter().enumerate() {
+        let x = i % UncompressedMap::X;
+        let row = (i / UncompressedMap::X) % UncompressedMap::Y;
+        let z = i / (UncompressedMap::X * UncompressedMap::Y);
+        let pos = block::Position::from_file(x, row, z);
+
+        if region.is_some_and(|region| !region.contains(Vec3::from(pos))) {
+            continue;
+        }
+
+        let Some(model) = block::model(info) else {
+            continue;
+        };
+        let transform = model
+            .transform
+            .with_translation(Vec3::from(pos) + model.transform.translation);
+
+        faces.extend(
+            model
+                .faces
+                .iter()
+                .filter_map(|face| export_face(face, &transform)),
+        );
+    }
+
+    faces
+}
+
+fn export_map(
+    keys: Res<ButtonInput<KeyCode>>,
+    current_map: Res<CurrentMap>,
+    map: Res<Map>,
+    map_assets: Res<Assets<MapFileAsset>>,
+    texture_index: Res<TextureIndex>,
+    images: Res<Assets<Image>>,
+    cameras: Query<&PanOrbitCamera>,
+) {
+    let format = if keys.just_pressed(EXPORT_GLB_KEY) {
+        ExportFormat::Glb
+    } else {
+        ExportFormat::Obj
+    };
+
+    let Some(blocks) = map_assets
+        .get(&map.asset)
+        .and_then(|map| map.0.uncompressed_map.as_ref())
+    else {
+        return;
+    };
+
+    let region = keys
+        .pressed(REGION_MODIFIER_KEY)
+        .then(|| cameras.iter().next())
+        .flatten()
+        .map(|camera| Region::around(camera.focus, REGION_RADIUS));
+
+    let export_faces = map_faces(blocks, region);
+
+    let textures = |tile_id| {
+        let handle = texture_index.index.get(&tile_id)?;
+        let mut pixels = images.get(handle)?.data.clone();
+        tile_sheet::bgra_to_rgba(&mut pixels);
+        Some(pixels)
+    };
+
+    let name = current_map.0.get_base_name();
+    let result = fs::create_dir_all(EXPORT_DIR)
+        .map_err(ExportError::from)
+        .and_then(|_| match format {
+            ExportFormat::Glb => {
+                let path = PathBuf::from(EXPORT_DIR).join(format!("{name}.glb"));
+                let mut file = BufWriter::new(File::create(&path)?);
+                write_glb(&export_faces, textures, &mut file)?;
+                file.flush()?;
+                Ok(path)
+            }
+            ExportFormat::Obj => {
+                let dir = PathBuf::from(EXPORT_DIR);
+                write_obj(&export_faces, textures, &dir, name)?;
+                Ok(dir.join(format!("{name}.obj")))
+            }
+        });
+
+    match result {
+        Ok(path) => info!(
+            "Exported {} faces to {}",
+            export_faces.len(),
+            path.display()
+        ),
+        Err(err) => error!("Map export failed: {err}"),
+    }
+}
+
+fn export_face(face: &ModelFace, transform: &Transform) -> Option<ExportFace> {
+    let geometry = face.mesh.geometry()?;
+    let extension = face.extension();
+
+    Some(ExportFace {
+        tile_id: face.face.tile_id,
+        transparent: face.face.flat,
+        positions: geometry
+            .positions
+            .iter()
+            .map(|p| transform.transform_point(Vec3::from(*p)).to_array())
+            .collect(),
+        normals: geometry
+            .normals
+            .iter()
+            .map(|n| (transform.rotation * Vec3::from(*n)).to_array())
+            .collect(),
+        uvs: geometry
+            .uvs
+            .iter()
+            .map(|uv| extension.transform_uv(*uv))
+            .collect(),
+        indices: geometry.indices,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bevy::utils::default;
+
+    use crate::map::file::{tests::test_map_with_blocks, BlockInfo, Face, FaceKind, RawFace};
+
+    /// Map with a single cube with tile 1 on its lid and tile 2 on its sides.
+    fn one_block_faces() -> Vec<ExportFace> {
+        let side = |tile_id| Face::new(FaceKind::Normal, RawFace::from(tile_id));
+        let block = BlockInfo {
+            lid: Face::new(FaceKind::Lid, RawFace::from(1)),
+            left: side(2),
+            right: side(2),
+            top: side(2),
+            bottom: side(2),
+            ..default()
+        };
+        let map = test_map_with_blocks([(IVec3::new(10, UncompressedMap::world_y(20), 0), block)]);
+
+        map_faces(&map, None)
+    }
+
+    fn texture(tile_id: usize) -> Option<Vec<u8>> {
+        Some(vec![tile_id as u8; IMAGE_SIZE * IMAGE_SIZE * 4])
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> usize {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap()) as usize
+    }
+
+    #[test]
+    fn glb_chunks_are_aligned_and_sized() {
+        let faces = one_block_faces();
+        assert!(!faces.is_empty());
+
+        let mut glb = Vec::new();
+        write_glb(&faces, texture, &mut glb).unwrap();
+
+        assert_eq!(&glb[0..4], b"glTF");
+        assert_eq!(u32_at(&glb, 4), 2);
+        assert_eq!(u32_at(&glb, 8), glb.len());
+
+        let json_len = u32_at(&glb, 12);
+        assert_eq!(&glb[16..20], b"JSON");
+        assert_eq!(json_len % 4, 0);
+        let document: Value = serde_json::from_slice(&glb[20..20 + json_len]).unwrap();
+
+        let bin = 20 + json_len;
+        let bin_len = u32_at(&glb, bin);
+        assert_eq!(&glb[bin + 4..bin + 8], b"BIN\0");
+        assert_eq!(bin_len % 4, 0);
+        assert_eq!(bin + 8 + bin_len, glb.len());
+        assert_eq!(document["buffers"][0]["byteLength"], bin_len);
+
+        for view in document["bufferViews"].as_array().unwrap() {
+            let offset = view["byteOffset"].as_u64().unwrap() as usize;
+            let len = view["byteLength"].as_u64().unwrap() as usize;
+            assert_eq!(offset % 4, 0);
+            assert!(offset + len <= bin_len);
+        }
+
+        // one material and texture per tile
+        assert_eq!(document["materials"].as_array().unwrap().len(), 2);
+        assert_eq!(document["images"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn obj_lists_every_vertex_and_triangle() {
+        let faces = one_block_faces();
+        let dir = std::env::temp_dir().join(format!("arrie-export-{}", std::process::id()));
+
+        write_obj(&faces, texture, &dir, "block").unwrap();
+        let obj = fs::read_to_string(dir.join("block.obj")).unwrap();
+        let mtl = fs::read_to_string(dir.join("block.mtl")).unwrap();
+        let textures_written = dir.join("textures").read_dir().unwrap().count();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let count = |text: &str, prefix: &str| {
+            text.lines()
+                .filter(|line| line.starts_with(&format!("{prefix} ")))
+                .count()
+        };
+        let vertices: usize = faces.iter().map(|face| face.positions.len()).sum();
+        let triangles: usize = faces.iter().map(|face| face.indices.len() / 3).sum();
+
+        assert_eq!(count(&obj, "v"), vertices);
+        assert_eq!(count(&obj, "vt"), vertices);
+        assert_eq!(count(&obj, "f"), triangles);
+        assert_eq!(count(&obj, "usemtl"), 2);
+
+        assert_eq!(count(&mtl, "newmtl"), 2);
+        assert_eq!(count(&mtl, "map_Kd"), 2);
+        assert!(mtl.contains("map_Kd textures/"));
+        assert_eq!(textures_written, 2);
+    }
+}
@@ -1,32 +1,122 @@
 use std::{
+    collections::HashMap,
     f32::consts::TAU,
     fs::File,
-    io::{BufReader, Cursor, Read, Seek, SeekFrom},
+    io::{self, BufReader, Cursor, Read, Seek, SeekFrom},
     path::Path,
     str::FromStr,
 };
 
 use byteorder::{NativeEndian, ReadBytesExt};
 
-#[derive(Debug)]
-pub struct FileHeader {
-    pub file_type: String,
-    pub version: u16,
-}
+pub use crate::chunk::FileHeader;
 
 #[derive(Debug)]
 pub struct Map {
     pub uncompressed_map: Option<UncompressedMap>,
     //compressed_map_16bit: CompressedMap,
     pub compressed_map_32bit: CompressedMap32,
-    //zones: Vec<Zone>,
+    pub zones: Vec<Zone>,
     //objects: Vec<Object>,
     //psx_mapping_table: PsxMappingTable,
     //tile_animations: Vec<TileAnimation>,
-    //lights: Vec<Light>,
+    pub lights: Vec<Light>,
     //junctions: Vec<Junction>
 }
 
+/// Light placed in the map. Positions and radius are in blocks, with `y`
+/// counting rows from the top like the file coordinates.
+#[derive(Debug, Clone)]
+pub struct Light {
+    /// ARGB, the alpha is unused.
+    pub color: u32,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub radius: f32,
+    pub intensity: u8,
+    pub shape: u8,
+    /// Frames the light is on and off while flickering, both 0 for a steady
+    /// light.
+    pub on_time: u8,
+    pub off_time: u8,
+}
+
+/// Named area of the map, in blocks with `y` counting rows from the top like
+/// the file coordinates.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Zone {
+    pub zone_type: ZoneType,
+    pub x: u8,
+    pub y: u8,
+    pub width: u8,
+    pub height: u8,
+    pub name: String,
+}
+
+impl Zone {
+    /// Whether the world cell `x`, `y` is inside the zone.
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        let row = UncompressedMap::row(y);
+        (i32::from(self.x)..i32::from(self.x) + i32::from(self.width)).contains(&x)
+            && (i32::from(self.y)..i32::from(self.y) + i32::from(self.height)).contains(&row)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZoneType {
+    GeneralPurpose,
+    Navigation,
+    /// Junction controlled by traffic lights.
+    TrafficLight,
+    ArrowBlocker,
+    RailwayStation,
+    BusStop,
+    GeneralTrigger,
+    Information,
+    RailwayStationEntry,
+    RailwayStationExit,
+    RailwayStop,
+    Gang,
+    LocalNavigation,
+    Restart,
+    ArrestRestart,
+    /// Type the game does not use, kept to tell it apart.
+    Unknown(u8),
+}
+
+impl From<u8> for ZoneType {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Self::GeneralPurpose,
+            1 => Self::Navigation,
+            2 => Self::TrafficLight,
+            3 => Self::ArrowBlocker,
+            4 => Self::RailwayStation,
+            5 => Self::BusStop,
+            6 => Self::GeneralTrigger,
+            8 => Self::Information,
+            9 => Self::RailwayStationEntry,
+            10 => Self::RailwayStationExit,
+            11 => Self::RailwayStop,
+            12 => Self::Gang,
+            13 => Self::LocalNavigation,
+            14 => Self::Restart,
+            20 => Self::ArrestRestart,
+            value => Self::Unknown(value),
+        }
+    }
+}
+
+impl Light {
+    pub const SIZE: usize = 16;
+
+    pub fn rgb(&self) -> [u8; 3] {
+        let [_, r, g, b] = self.color.to_be_bytes();
+        [r, g, b]
+    }
+}
+
 #[derive(Debug)]
 pub struct UncompressedMap(pub Vec<BlockInfo>);
 
@@ -60,6 +150,17 @@ impl Rotate {
     }
 }
 
+impl From<Rotate> for u8 {
+    fn from(value: Rotate) -> Self {
+        match value {
+            Rotate::Degree0 => 0,
+            Rotate::Degree90 => 1,
+            Rotate::Degree180 => 2,
+            Rotate::Degree270 => 3,
+        }
+    }
+}
+
 impl From<u8> for Rotate {
     fn from(value: u8) -> Self {
         match value {
@@ -76,6 +177,12 @@ impl From<u8> for Rotate {
 pub struct Face {
     pub kind: FaceKind,
     pub tile_id: usize,
+    /// Side faces only: peds and cars can not pass.
+    pub wall: bool,
+    /// Side faces only: bullets can not pass.
+    pub bullet_wall: bool,
+    /// Lids only: lighting level from 0 to 3.
+    pub lighting: u8,
     pub flat: bool,
     pub flip: bool,
     pub rotate: Rotate,
@@ -83,9 +190,19 @@ pub struct Face {
 
 impl Face {
     pub fn new(kind: FaceKind, raw: RawFace) -> Self {
+        // NOTE: lids use the wall bits for their lighting level
+        let is_side = matches!(kind, FaceKind::Normal);
+
         Self {
             kind,
             tile_id: raw.tile_id,
+            wall: is_side && raw.wall,
+            bullet_wall: is_side && raw.bullet_wall,
+            lighting: if is_side {
+                0
+            } else {
+                u8::from(raw.wall) | (u8::from(raw.bullet_wall) << 1)
+            },
             flat: raw.flat,
             flip: raw.flip,
             rotate: raw.rotate,
@@ -93,8 +210,26 @@ impl Face {
     }
 }
 
+impl From<&Face> for u16 {
+    fn from(face: &Face) -> Self {
+        let (bit_10, bit_11) = match face.kind {
+            FaceKind::Normal => (face.wall, face.bullet_wall),
+            FaceKind::Lid => (face.lighting & 0b01 != 0, face.lighting & 0b10 != 0),
+        };
+
+        (face.tile_id as u16 & 0b0000_0011_1111_1111)
+            | (u16::from(bit_10) << 10)
+            | (u16::from(bit_11) << 11)
+            | (u16::from(face.flat) << 12)
+            | (u16::from(face.flip) << 13)
+            | (u16::from(u8::from(face.rotate)) << 14)
+    }
+}
+
 pub struct RawFace {
     pub tile_id: usize,
+    pub wall: bool,
+    pub bullet_wall: bool,
     pub flat: bool,
     pub flip: bool,
     pub rotate: Rotate,
@@ -103,6 +238,8 @@ pub struct RawFace {
 impl From<u16> for RawFace {
     fn from(value: u16) -> Self {
         let tile_id = (value & 0b0000_0011_1111_1111) as usize;
+        let wall = ((value >> 10) & 0x01) == 1;
+        let bullet_wall = ((value >> 11) & 0x01) == 1;
         let flat = ((value >> 12) & 0x01) == 1;
         let flip = ((value >> 13) & 0x01) == 1;
         let rotate = value >> 14;
@@ -110,6 +247,8 @@ impl From<u16> for RawFace {
 
         Self {
             tile_id,
+            wall,
+            bullet_wall,
             flat,
             flip,
             rotate,
@@ -155,12 +294,135 @@ pub struct BlockInfo {
     pub top: Face,
     pub bottom: Face,
     pub lid: Face,
-    // TODO: use bitflags
-    pub arrows: u8,
+    pub arrows: Arrows,
+    pub ground_type: GroundType,
     // TODO: use bitflags
     pub slope_type: SlopeType,
 }
 
+/// Road arrows of a block, the green ones in the lower and the red ones in
+/// the upper four bits, each with a bit for left, right, up and down. Traffic
+/// follows the green arrows and only takes the red ones where there is no
+/// green arrow.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Arrows(pub u8);
+
+impl Arrows {
+    const DIRECTIONS: [SlopeDirection; 4] = [
+        SlopeDirection::Left,
+        SlopeDirection::Right,
+        SlopeDirection::Up,
+        SlopeDirection::Down,
+    ];
+
+    pub fn green(self) -> impl Iterator<Item = SlopeDirection> {
+        Self::directions(self.0 & 0xf)
+    }
+
+    pub fn red(self) -> impl Iterator<Item = SlopeDirection> {
+        Self::directions(self.0 >> 4)
+    }
+
+    /// Directions of the green and red arrows.
+    pub fn all(self) -> impl Iterator<Item = SlopeDirection> {
+        Self::directions((self.0 | self.0 >> 4) & 0xf)
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    fn directions(bits: u8) -> impl Iterator<Item = SlopeDirection> {
+        Self::DIRECTIONS
+            .into_iter()
+            .enumerate()
+            .filter(move |(i, _)| bits & (1 << i) != 0)
+            .map(|(_, direction)| direction)
+    }
+}
+
+/// Lid tile id marking a diagonal slope block as three-sided instead of
+/// four-sided, it is not drawn. See [`SlopeType::decode`].
+pub const THREE_SIDED_LID_TILE_ID: usize = 1023;
+
+/// Size of a block info in the map file.
+pub const BLOCK_INFO_SIZE: usize = 12;
+
+impl Default for BlockInfo {
+    /// Empty block, also the first block info of compressed maps.
+    fn default() -> Self {
+        Self {
+            left: Face::new(FaceKind::Normal, RawFace::from(0)),
+            right: Face::new(FaceKind::Normal, RawFace::from(0)),
+            top: Face::new(FaceKind::Normal, RawFace::from(0)),
+            bottom: Face::new(FaceKind::Normal, RawFace::from(0)),
+            lid: Face::new(FaceKind::Lid, RawFace::from(0)),
+            arrows: Arrows::default(),
+            ground_type: GroundType::Air,
+            slope_type: SlopeType::None,
+        }
+    }
+}
+
+impl BlockInfo {
+    /// The block as stored in the map file.
+    pub fn to_bytes(&self) -> [u8; BLOCK_INFO_SIZE] {
+        let mut bytes = [0; BLOCK_INFO_SIZE];
+
+        // NOTE: three-sided diagonals are only told apart by their lid
+        let lid = match self.slope_type {
+            SlopeType::ThreeSidedDiagonal(_) => Face {
+                tile_id: THREE_SIDED_LID_TILE_ID,
+                ..self.lid.clone()
+            },
+            _ => self.lid.clone(),
+        };
+
+        for (i, face) in [&self.left, &self.right, &self.top, &self.bottom, &lid]
+            .into_iter()
+            .enumerate()
+        {
+            bytes[i * 2..i * 2 + 2].copy_from_slice(&u16::from(face).to_ne_bytes());
+        }
+        bytes[10] = self.arrows.0;
+        bytes[11] = (u8::from(&self.slope_type) << 2) | u8::from(self.ground_type);
+
+        bytes
+    }
+}
+
+/// Surface of a block, stored in the lowest two bits of the slope byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroundType {
+    /// Not solid, things fall through.
+    Air,
+    Road,
+    Pavement,
+    Field,
+}
+
+impl From<GroundType> for u8 {
+    fn from(value: GroundType) -> Self {
+        match value {
+            GroundType::Air => 0,
+            GroundType::Road => 1,
+            GroundType::Pavement => 2,
+            GroundType::Field => 3,
+        }
+    }
+}
+
+impl From<u8> for GroundType {
+    fn from(value: u8) -> Self {
+        match value & 0b11 {
+            0 => Self::Air,
+            1 => Self::Road,
+            2 => Self::Pavement,
+            _ => Self::Field,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum SlopeType {
     None,
@@ -178,11 +440,15 @@ pub enum SlopeType {
     FourSidedDiagonal(DiagonalType),
     PartialBlock(PartialPosition),
     PartialCornerBlock(CornerPosition),
+    /// Pillar of the size of a partial block in the centre of the block.
+    PartialCentre,
     SlopeAbove,
-    Ignore,
+    /// Code 62, which is not used by the game, kept to write it back
+    /// unchanged.
+    Ignore(u8),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SlopeDirection {
     Up,
     Down,
@@ -190,7 +456,7 @@ pub enum SlopeDirection {
     Right,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SlopeLevel {
     Low,
     High,
@@ -204,7 +470,7 @@ pub enum DiagonalType {
     DownRight,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum PartialPosition {
     Left,
     Right,
@@ -212,7 +478,7 @@ pub enum PartialPosition {
     Bottom,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum CornerPosition {
     TopLeft,
     TopRight,
@@ -220,6 +486,22 @@ pub enum CornerPosition {
     BottomLeft,
 }
 
+impl SlopeType {
+    /// Slope type of a block with the slope byte `value` and the lid tile
+    /// `lid_tile_id`, codes 49-52 are three-sided diagonals if the lid is
+    /// [`THREE_SIDED_LID_TILE_ID`] and four-sided otherwise.
+    pub fn decode(value: u8, lid_tile_id: usize) -> Self {
+        match Self::from(value) {
+            Self::FourSidedDiagonal(diagonal_type) if lid_tile_id == THREE_SIDED_LID_TILE_ID => {
+                Self::ThreeSidedDiagonal(diagonal_type)
+            }
+            slope_type => slope_type,
+        }
+    }
+}
+
+/// Slope type of the slope byte `value`, codes 49-52 are four-sided
+/// diagonals.
 impl From<u8> for SlopeType {
     fn from(value: u8) -> Self {
         let slope_type_id = value >> 2;
@@ -281,11 +563,10 @@ impl From<u8> for SlopeType {
             46 => Self::Diagonal(DiagonalType::UpRight),
             47 => Self::Diagonal(DiagonalType::DownLeft),
             48 => Self::Diagonal(DiagonalType::DownRight),
-            // FIXME: could also be 4-sided
-            49 => Self::ThreeSidedDiagonal(DiagonalType::UpLeft),
-            50 => Self::ThreeSidedDiagonal(DiagonalType::UpRight),
-            51 => Self::ThreeSidedDiagonal(DiagonalType::DownLeft),
-            52 => Self::ThreeSidedDiagonal(DiagonalType::DownRight),
+            49 => Self::FourSidedDiagonal(DiagonalType::UpLeft),
+            50 => Self::FourSidedDiagonal(DiagonalType::UpRight),
+            51 => Self::FourSidedDiagonal(DiagonalType::DownLeft),
+            52 => Self::FourSidedDiagonal(DiagonalType::DownRight),
             53 => Self::PartialBlock(PartialPosition::Left),
             54 => Self::PartialBlock(PartialPosition::Right),
             55 => Self::PartialBlock(PartialPosition::Top),
@@ -294,34 +575,186 @@ impl From<u8> for SlopeType {
             58 => Self::PartialCornerBlock(CornerPosition::TopRight),
             59 => Self::PartialCornerBlock(CornerPosition::BottomRight),
             60 => Self::PartialCornerBlock(CornerPosition::BottomLeft),
-            // 61 => Self.:PartialCenter,
-            // 62 => Self::Reserved
+            61 => Self::PartialCentre,
             63 => SlopeType::SlopeAbove,
-            _ => SlopeType::Ignore,
+            id => SlopeType::Ignore(id),
+        }
+    }
+}
+
+/// Slope type id, without the ground type bits.
+impl From<&SlopeType> for u8 {
+    fn from(value: &SlopeType) -> Self {
+        let direction_index = |direction: &SlopeDirection| match direction {
+            SlopeDirection::Up => 0,
+            SlopeDirection::Down => 1,
+            SlopeDirection::Left => 2,
+            SlopeDirection::Right => 3,
+        };
+        let diagonal_index = |diagonal_type: &DiagonalType| match diagonal_type {
+            DiagonalType::UpLeft => 0,
+            DiagonalType::UpRight => 1,
+            DiagonalType::DownLeft => 2,
+            DiagonalType::DownRight => 3,
+        };
+
+        match value {
+            SlopeType::None => 0,
+            SlopeType::Degree26 { direction, level } => {
+                let level = match level {
+                    SlopeLevel::Low => 1,
+                    SlopeLevel::High => 2,
+                };
+                direction_index(direction) * 2 + level
+            }
+            SlopeType::Degree7 { direction, index } => 9 + direction_index(direction) * 8 + index,
+            SlopeType::Degree45(direction) => 41 + direction_index(direction),
+            SlopeType::Diagonal(diagonal_type) => 45 + diagonal_index(diagonal_type),
+            SlopeType::ThreeSidedDiagonal(diagonal_type)
+            | SlopeType::FourSidedDiagonal(diagonal_type) => 49 + diagonal_index(diagonal_type),
+            SlopeType::PartialBlock(position) => match position {
+                PartialPosition::Left => 53,
+                PartialPosition::Right => 54,
+                PartialPosition::Top => 55,
+                PartialPosition::Bottom => 56,
+            },
+            SlopeType::PartialCornerBlock(position) => match position {
+                CornerPosition::TopLeft => 57,
+                CornerPosition::TopRight => 58,
+                CornerPosition::BottomRight => 59,
+                CornerPosition::BottomLeft => 60,
+            },
+            SlopeType::PartialCentre => 61,
+            SlopeType::SlopeAbove => 63,
+            SlopeType::Ignore(id) => *id,
         }
     }
 }
 
 impl Map {
-    pub fn from_file(path: impl AsRef<Path>) -> Self {
-        let file = File::open(path).unwrap();
+    pub fn from_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = File::open(path)?;
         let mut buf_reader = BufReader::new(file);
 
-        let _header = read_header(&mut buf_reader);
+        let _header = read_header(&mut buf_reader)?;
 
         read_chunks(&mut buf_reader)
     }
 
-    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+    pub fn from_bytes(bytes: Vec<u8>) -> io::Result<Self> {
         let mut cursor = Cursor::new(bytes);
 
-        let header = read_header(&mut cursor);
-        dbg!(header);
+        let _header = read_header(&mut cursor)?;
 
         read_chunks(&mut cursor)
     }
 }
 
+impl CompressedMap32 {
+    pub fn base(&self) -> &[u32] {
+        &self.base
+    }
+
+    pub fn column_infos(&self) -> &[u32] {
+        &self.column_infos
+    }
+
+    pub fn block_infos(&self) -> &[BlockInfo] {
+        &self.block_infos
+    }
+
+    /// Index into [`Self::column_infos`] of the column at `x`, `y` in file
+    /// coordinates.
+    pub fn column_index(&self, x: usize, y: usize) -> Option<usize> {
+        if x >= UncompressedMap::X || y >= UncompressedMap::Y {
+            return None;
+        }
+
+        self.base
+            .get(y * UncompressedMap::X + x)
+            .map(|&i| i as usize)
+    }
+
+    /// Index into [`Self::block_infos`] of the block at `x`, `y`, `z` in file
+    /// coordinates, `None` for the empty blocks below and above the column.
+    pub fn block_info_index(&self, x: usize, y: usize, z: usize) -> Option<usize> {
+        let column_index = self.column_index(x, y)?;
+        let column_info = *self.column_infos.get(column_index)?;
+        let height = (column_info & 0xff) as usize;
+        let offset = ((column_info & 0xff00) >> 8) as usize;
+
+        if z < offset || z >= height {
+            return None;
+        }
+
+        self.column_infos
+            .get(column_index + z - offset + 1)
+            .map(|&i| i as usize)
+    }
+}
+
+/// Compresses the map, sharing identical block infos and columns. The empty
+/// block is the first block info, so the blocks below and above a column
+/// decode to it.
+impl From<&UncompressedMap> for CompressedMap32 {
+    fn from(map: &UncompressedMap) -> Self {
+        let empty = BlockInfo::default().to_bytes();
+
+        let mut block_infos = vec![BlockInfo::default()];
+        let mut block_info_indices = HashMap::from([(empty, 0)]);
+        let mut column_infos = Vec::new();
+        let mut column_indices = HashMap::new();
+        let mut base = Vec::with_capacity(BASE_ARRAY_SIZE);
+
+        for y in 0..UncompressedMap::Y {
+            for x in 0..UncompressedMap::X {
+                let column: Vec<_> = (0..UncompressedMap::Z)
+                    .map(|z| map.get(x, y, z).map_or(empty, BlockInfo::to_bytes))
+                    .collect();
+
+                let height = column.len()
+                    - column
+                        .iter()
+                        .rev()
+                        .take_while(|&&bytes| bytes == empty)
+                        .count();
+                let offset = column
+                    .iter()
+                    .take_while(|&&bytes| bytes == empty)
+                    .count()
+                    .min(height);
+
+                let mut words = vec![(height as u32) | ((offset as u32) << 8)];
+                for (z, bytes) in column.iter().enumerate().take(height).skip(offset) {
+                    let index = *block_info_indices.entry(*bytes).or_insert_with(|| {
+                        block_infos.push(map.get(x, y, z).cloned().unwrap_or_default());
+                        block_infos.len() as u32 - 1
+                    });
+                    words.push(index);
+                }
+
+                let column_index = *column_indices.entry(words).or_insert_with_key(|words| {
+                    column_infos.extend_from_slice(words);
+                    (column_infos.len() - words.len()) as u32
+                });
+                base.push(column_index);
+            }
+        }
+
+        Self {
+            base,
+            column_infos,
+            block_infos,
+        }
+    }
+}
+
+impl Default for UncompressedMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl UncompressedMap {
     pub const X: usize = 256;
     pub const Y: usize = 256;
@@ -332,6 +765,36 @@ impl UncompressedMap {
 
         Self(inner)
     }
+
+    /// Block at column `x`, row `y` and level `z` in file coordinates.
+    pub fn get(&self, x: usize, y: usize, z: usize) -> Option<&BlockInfo> {
+        self.0.get(Self::index(x, y, z)?)
+    }
+
+    pub fn get_mut(&mut self, x: usize, y: usize, z: usize) -> Option<&mut BlockInfo> {
+        let index = Self::index(x, y, z)?;
+        self.0.get_mut(index)
+    }
+
+    fn index(x: usize, y: usize, z: usize) -> Option<usize> {
+        if x >= Self::X || y >= Self::Y || z >= Self::Z {
+            return None;
+        }
+
+        Some(z * Self::X * Self::Y + y * Self::X + x)
+    }
+
+    /// File row of the world `y`. World Y counts up from the bottom row of
+    /// the file, which is at 1, so the rows are at 256 down to 1. Rows
+    /// outside of `0..Y` are outside of the map.
+    pub fn row(y: i32) -> i32 {
+        Self::Y as i32 - y
+    }
+
+    /// World Y of the file `row`, see [`Self::row`].
+    pub fn world_y(row: i32) -> i32 {
+        Self::Y as i32 - row
+    }
 }
 
 enum ChunkTypes {
@@ -373,6 +836,8 @@ enum ParseError {
 struct MapBuilder {
     uncompressed_map: Option<UncompressedMap>,
     compressed_map_32: Option<CompressedMap32>,
+    lights: Vec<Light>,
+    zones: Vec<Zone>,
 }
 
 impl MapBuilder {
@@ -380,6 +845,8 @@ impl MapBuilder {
         MapBuilder {
             uncompressed_map: None,
             compressed_map_32: None,
+            lights: Vec::new(),
+            zones: Vec::new(),
         }
     }
 
@@ -388,21 +855,22 @@ impl MapBuilder {
         chunk_type: ChunkTypes,
         size: u32,
         buf_reader: &mut T,
-    ) -> &mut MapBuilder {
+    ) -> io::Result<&mut MapBuilder> {
         match chunk_type {
             ChunkTypes::UncompressedMap => {
-                self.uncompressed_map = Some(load_uncompressed_map(size, buf_reader));
-                self
+                self.uncompressed_map = Some(load_uncompressed_map(size, buf_reader)?);
             }
             ChunkTypes::CompressedMap32Bit => {
-                self.compressed_map_32 = Some(load_compressed_map_32(size, buf_reader));
-                self
+                self.compressed_map_32 = Some(load_compressed_map_32(size, buf_reader)?);
             }
+            ChunkTypes::Lights => self.lights = load_lights(size, buf_reader)?,
+            ChunkTypes::MapZones => self.zones = load_zones(size, buf_reader)?,
             _ => {
-                buf_reader.seek(SeekFrom::Current(size as i64)).unwrap();
-                self
+                buf_reader.seek(SeekFrom::Current(size as i64))?;
             }
         }
+
+        Ok(self)
     }
 
     pub fn build(self) -> Option<Map> {
@@ -412,97 +880,163 @@ impl MapBuilder {
         Some(Map {
             uncompressed_map: Some(uncompressed_map),
             compressed_map_32bit: self.compressed_map_32?,
+            lights: self.lights,
+            zones: self.zones,
         })
     }
 }
 
+/// Blocks whose column or block info index is out of range are left empty,
+/// `validate` reports them.
 fn create_uncompressed_map_from_compressed(compressed: CompressedMap32) -> UncompressedMap {
-    let base: &[u32] = &compressed.base;
-    let columns: &[u32] = &compressed.column_infos;
+    let empty = compressed.block_infos.first().cloned().unwrap_or_default();
+    let mut block_infos = vec![empty; UncompressedMap::X * UncompressedMap::Y * UncompressedMap::Z];
+
+    for z in 0..UncompressedMap::Z {
+        for y in 0..UncompressedMap::Y {
+            for x in 0..UncompressedMap::X {
+                let Some(block_info) = compressed
+                    .block_info_index(x, y, z)
+                    .and_then(|index| compressed.block_infos.get(index))
+                else {
+                    continue;
+                };
+
+                block_infos
+                    [z * UncompressedMap::X * UncompressedMap::Y + y * UncompressedMap::X + x] =
+                    block_info.clone();
+            }
+        }
+    }
 
-    let mut block_infos =
-        Vec::with_capacity(UncompressedMap::X * UncompressedMap::Y * UncompressedMap::Z);
+    UncompressedMap(block_infos)
+}
 
-    for _ in 0..UncompressedMap::X * UncompressedMap::Y * UncompressedMap::Z {
-        block_infos.push(compressed.block_infos.first().unwrap().clone());
+fn load_uncompressed_map<T: Read + Seek>(
+    size: u32,
+    buf_reader: &mut T,
+) -> io::Result<UncompressedMap> {
+    let blocks_count = UncompressedMap::X * UncompressedMap::Y * UncompressedMap::Z;
+    if blocks_count * BLOCK_INFO_SIZE != size as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "UMAP chunk has {size} bytes instead of {}",
+                blocks_count * BLOCK_INFO_SIZE
+            ),
+        ));
     }
 
-    for x in 0..256 {
-        for y in 0..256 {
-            let col_index = base[y * 256 + x] as usize;
-
-            let col_info = columns[col_index];
-            let height = (col_info & 0xff) as usize;
-            let offset = ((col_info & 0xff00) >> 8) as usize;
+    let blocks = read_block_infos(blocks_count, buf_reader)?;
+    Ok(UncompressedMap(blocks))
+}
 
-            for _ in 0..offset {
-                block_infos.push(compressed.block_infos.first().unwrap().clone());
-            }
+fn load_compressed_map_32<T: Read + Seek>(
+    _size: u32,
+    buf_reader: &mut T,
+) -> io::Result<CompressedMap32> {
+    let base = (0..BASE_ARRAY_SIZE)
+        .map(|_| buf_reader.read_u32::<NativeEndian>())
+        .collect::<io::Result<_>>()?;
+
+    // NOTE: the lengths are not trusted to preallocate, a broken file could
+    // claim billions of entries
+    let column_info_len = buf_reader.read_u32::<NativeEndian>()?;
+    let column_infos = (0..column_info_len)
+        .map(|_| buf_reader.read_u32::<NativeEndian>())
+        .collect::<io::Result<_>>()?;
+
+    let block_info_len = buf_reader.read_u32::<NativeEndian>()?;
+    let block_infos = read_block_infos(block_info_len as usize, buf_reader)?;
+
+    Ok(CompressedMap32 {
+        base,
+        column_infos,
+        block_infos,
+    })
+}
 
-            for z in 0..height {
-                if z >= offset {
-                    let block_info = compressed
-                        .block_infos
-                        .get(columns[col_index + z - offset + 1] as usize)
-                        .unwrap();
+fn load_zones<T: Read + Seek>(size: u32, buf_reader: &mut T) -> io::Result<Vec<Zone>> {
+    let data = crate::chunk::read_data(buf_reader, size)?;
 
-                    if let Some(block) = block_infos.get_mut((y * 256 + x) + z * 256 * 256) {
-                        *block = block_info.clone()
-                    }
-                }
-            }
-        }
+    let mut zones = Vec::new();
+    let mut reader = data.as_slice();
+    // NOTE: zones have a variable length, a truncated last one is dropped
+    while let Ok(zone) = read_zone(&mut reader) {
+        zones.push(zone);
     }
 
-    //assert_eq!(
-    //    block_infos.len(),
-    //    UncompressedMap::X * UncompressedMap::Y * UncompressedMap::Z
-    //);
-
-    UncompressedMap(block_infos)
+    Ok(zones)
 }
 
-const BLOCK_INFO_SIZE: u32 = 12;
-fn load_uncompressed_map<T: Read + Seek>(size: u32, buf_reader: &mut T) -> UncompressedMap {
-    let blocks_count = UncompressedMap::X * UncompressedMap::Y * UncompressedMap::Z;
-    assert!(blocks_count as u32 * BLOCK_INFO_SIZE == size);
-
-    let blocks = read_block_infos(blocks_count, buf_reader);
-    UncompressedMap(blocks)
+fn read_zone(reader: &mut &[u8]) -> std::io::Result<Zone> {
+    let mut bytes = [0; 6];
+    reader.read_exact(&mut bytes)?;
+    let [zone_type, x, y, width, height, name_length] = bytes;
+
+    let mut name = vec![0; usize::from(name_length)];
+    reader.read_exact(&mut name)?;
+
+    Ok(Zone {
+        zone_type: ZoneType::from(zone_type),
+        x,
+        y,
+        width,
+        height,
+        name: String::from_utf8_lossy(&name).into_owned(),
+    })
 }
 
-fn load_compressed_map_32<T: Read + Seek>(_size: u32, buf_reader: &mut T) -> CompressedMap32 {
-    let mut base = Vec::with_capacity(BASE_ARRAY_SIZE);
-    for _ in 0..BASE_ARRAY_SIZE {
-        base.push(buf_reader.read_u32::<NativeEndian>().unwrap());
+fn load_lights<T: Read + Seek>(size: u32, buf_reader: &mut T) -> io::Result<Vec<Light>> {
+    let count = size as usize / Light::SIZE;
+    let mut lights = Vec::with_capacity(count);
+
+    for _ in 0..count {
+        let color = buf_reader.read_u32::<NativeEndian>()?;
+        let x = read_fix16(buf_reader)?;
+        let y = read_fix16(buf_reader)?;
+        let z = read_fix16(buf_reader)?;
+        let radius = read_fix16(buf_reader)?;
+        let mut bytes = [0; 4];
+        buf_reader.read_exact(&mut bytes)?;
+        let [intensity, shape, on_time, off_time] = bytes;
+
+        lights.push(Light {
+            color,
+            x,
+            y,
+            z,
+            radius,
+            intensity,
+            shape,
+            on_time,
+            off_time,
+        });
     }
 
-    let column_info_len = buf_reader.read_u32::<NativeEndian>().unwrap();
-    let mut column_infos = Vec::with_capacity(column_info_len as usize);
-    for _ in 0..column_info_len {
-        //let column_info = read_column_info(buf_reader);
-        column_infos.push(buf_reader.read_u32::<NativeEndian>().unwrap());
-    }
+    // skip a trailing partial light
+    buf_reader.seek(SeekFrom::Current((size as usize % Light::SIZE) as i64))?;
 
-    let block_info_len = buf_reader.read_u32::<NativeEndian>().unwrap();
-    let block_infos = read_block_infos(block_info_len as usize, buf_reader);
+    Ok(lights)
+}
 
-    CompressedMap32 {
-        base,
-        column_infos,
-        block_infos,
-    }
+/// Fixed point number with 7 fractional bits, in blocks.
+fn read_fix16<T: Read>(buf_reader: &mut T) -> io::Result<f32> {
+    Ok(f32::from(buf_reader.read_u16::<NativeEndian>()?) / 128.0)
 }
 
-fn read_block_infos<T: Read + Seek>(len: usize, buf_reader: &mut T) -> Vec<BlockInfo> {
-    let mut blocks = Vec::with_capacity(len);
+fn read_block_infos<T: Read + Seek>(len: usize, buf_reader: &mut T) -> io::Result<Vec<BlockInfo>> {
+    let mut blocks = Vec::new();
 
     for _ in 0..len {
-        let left_raw = RawFace::from(buf_reader.read_u16::<NativeEndian>().unwrap());
-        let right_raw = RawFace::from(buf_reader.read_u16::<NativeEndian>().unwrap());
-        let top_raw = RawFace::from(buf_reader.read_u16::<NativeEndian>().unwrap());
-        let bottom_raw = RawFace::from(buf_reader.read_u16::<NativeEndian>().unwrap());
-        let lid_raw = RawFace::from(buf_reader.read_u16::<NativeEndian>().unwrap());
+        let left_raw = RawFace::from(buf_reader.read_u16::<NativeEndian>()?);
+        let right_raw = RawFace::from(buf_reader.read_u16::<NativeEndian>()?);
+        let top_raw = RawFace::from(buf_reader.read_u16::<NativeEndian>()?);
+        let bottom_raw = RawFace::from(buf_reader.read_u16::<NativeEndian>()?);
+        let lid_raw = RawFace::from(buf_reader.read_u16::<NativeEndian>()?);
+        let arrows = Arrows(buf_reader.read_u8()?);
+        let slope = buf_reader.read_u8()?;
+        let slope_type = SlopeType::decode(slope, lid_raw.tile_id);
 
         let block = BlockInfo {
             left: Face::new(FaceKind::Normal, left_raw),
@@ -510,46 +1044,43 @@ fn read_block_infos<T: Read + Seek>(len: usize, buf_reader: &mut T) -> Vec<Block
             top: Face::new(FaceKind::Normal, top_raw),
             bottom: Face::new(FaceKind::Normal, bottom_raw),
             lid: Face::new(FaceKind::Lid, lid_raw),
-            arrows: buf_reader.read_u8().unwrap(),
-            slope_type: SlopeType::from(buf_reader.read_u8().unwrap()),
+            arrows,
+            ground_type: GroundType::from(slope),
+            slope_type,
         };
 
         blocks.push(block);
     }
 
-    blocks
+    Ok(blocks)
 }
 
-fn read_header<T: Read>(buf_reader: &mut T) -> FileHeader {
+fn read_header<T: Read>(buf_reader: &mut T) -> io::Result<FileHeader> {
     let mut buffer = [0; 4];
 
-    buf_reader.read_exact(&mut buffer).unwrap();
-    let file_type = String::from_utf8(buffer.to_vec()).unwrap();
-    let version = buf_reader.read_u16::<NativeEndian>().unwrap();
-
-    dbg!(&file_type, &version);
+    buf_reader.read_exact(&mut buffer)?;
+    let file_type = String::from_utf8(buffer.to_vec())
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    let version = buf_reader.read_u16::<NativeEndian>()?;
 
-    FileHeader { file_type, version }
+    Ok(FileHeader { file_type, version })
 }
 
-fn read_chunks<T: Read + Seek>(mut buf_reader: &mut T) -> Map {
+fn read_chunks<T: Read + Seek>(buf_reader: &mut T) -> io::Result<Map> {
     let mut buffer = [0; 4];
     let mut map_builder = MapBuilder::new();
 
     loop {
-        buf_reader.read_exact(&mut buffer).unwrap();
+        if buf_reader.read_exact(&mut buffer).is_err() {
+            break;
+        }
 
         let chunk_type = match String::from_utf8(buffer.to_vec()) {
             Ok(s) => s,
             Err(_) => break,
         };
 
-        let size = match buf_reader.read_u32::<NativeEndian>() {
-            Ok(s) => s,
-            Err(_) => unimplemented!(),
-        };
-
-        println!("{}: {} bytes", chunk_type, size);
+        let size = buf_reader.read_u32::<NativeEndian>()?;
 
         // let chunk_type = ChunkTypes::from_str(&chunk_type).unwrap_or_else(|| break);
         let chunk_type = match ChunkTypes::from_str(&chunk_type) {
@@ -557,8 +1088,87 @@ fn read_chunks<T: Read + Seek>(mut buf_reader: &mut T) -> Map {
             Err(_) => break,
         };
 
-        map_builder.load_chunk(chunk_type, size, &mut buf_reader);
+        // NOTE: chunks are read from their own data, so broken lengths inside
+        // a chunk fail instead of reading into the next one
+        let data = crate::chunk::read_data(buf_reader, size)?;
+        map_builder.load_chunk(chunk_type, size, &mut Cursor::new(data))?;
+    }
+
+    map_builder
+        .build()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "map has no DMAP chunk"))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::io::Cursor;
+
+    use bevy::math::IVec3;
+
+    use super::*;
+
+    /// Map with `blocks` at world cells, everything else air.
+    pub(crate) fn test_map_with_blocks(
+        blocks: impl IntoIterator<Item = (IVec3, BlockInfo)>,
+    ) -> UncompressedMap {
+        let count = UncompressedMap::X * UncompressedMap::Y * UncompressedMap::Z;
+        let mut map = UncompressedMap(vec![BlockInfo::default(); count]);
+        for (cell, block) in blocks {
+            let row = UncompressedMap::row(cell.y) as usize;
+            *map.get_mut(cell.x as usize, row, cell.z as usize).unwrap() = block;
+        }
+        map
+    }
+
+    #[test]
+    fn arrows_decode_green_and_red() {
+        let arrows = Arrows(0b1001_0110);
+
+        assert_eq!(
+            arrows.green().collect::<Vec<_>>(),
+            [SlopeDirection::Right, SlopeDirection::Up]
+        );
+        assert_eq!(
+            arrows.red().collect::<Vec<_>>(),
+            [SlopeDirection::Left, SlopeDirection::Down]
+        );
+        assert_eq!(arrows.all().count(), 4);
+        assert!(Arrows::default().is_empty());
     }
 
-    map_builder.build().unwrap()
+    #[test]
+    fn zones_are_read_until_truncated() {
+        let mut data = vec![2, 10, 20, 3, 2, 5];
+        data.extend(b"light");
+        data.extend([1, 0, 0, 255, 255, 0]);
+        // truncated name
+        data.extend([3, 1, 1, 1, 1, 4, b'x']);
+
+        let zones = load_zones(data.len() as u32, &mut Cursor::new(data)).unwrap();
+        assert_eq!(zones.len(), 2);
+        assert_eq!(zones[0].zone_type, ZoneType::TrafficLight);
+        assert_eq!(zones[0].name, "light");
+        assert_eq!((zones[0].x, zones[0].y), (10, 20));
+        assert_eq!((zones[1].width, zones[1].height), (255, 255));
+        assert!(zones[1].name.is_empty());
+    }
+
+    #[test]
+    fn zones_contain_world_cells() {
+        let zone = Zone {
+            zone_type: ZoneType::TrafficLight,
+            x: 10,
+            y: 20,
+            width: 3,
+            height: 2,
+            name: String::new(),
+        };
+
+        // rows 20 and 21 are the world cells 236 and 235
+        assert!(zone.contains(10, 236));
+        assert!(zone.contains(12, 235));
+        assert!(!zone.contains(13, 236));
+        assert!(!zone.contains(10, 237));
+        assert!(!zone.contains(10, 234));
+    }
 }
@@ -0,0 +1,214 @@
+//! Visual regression scene with every slope type, textured with a UV check
+//! pattern to spot wrong flips, rotations and flat faces.
+//!
+//! Run with `cargo run --example slope_gallery`.
+
+use bevy::prelude::*;
+use bevy_panorbit_camera::{PanOrbitCamera, PanOrbitCameraPlugin};
+
+use super::{
+    block::{self, BlockMeshes, FaceBuilder},
+    file::{
+        Arrows, BlockInfo, Face, FaceKind, GroundType, RawFace, Rotate, SlopeType,
+        THREE_SIDED_LID_TILE_ID,
+    },
+    MapMaterial, TextureIndex,
+};
+
+pub fn plugin(app: &mut App) {
+    app.add_plugins((
+        MaterialPlugin::<MapMaterial>::default(),
+        PanOrbitCameraPlugin,
+    ))
+    .add_systems(Startup, setup)
+    .add_systems(
+        Update,
+        spawn_gallery.run_if(resource_exists::<GalleryAssets>),
+    );
+}
+
+/// Slope codes from flat blocks to the unused codes.
+const SLOPE_CODES: std::ops::RangeInclusive<u8> = 0..=63;
+/// Codes of the diagonals which are also drawn three-sided, after the others.
+const DIAGONAL_CODES: std::ops::RangeInclusive<u8> = 49..=52;
+/// Distance between the blocks.
+const SPACING: f32 = 2.0;
+
+const LID_TILE: usize = 1;
+const SIDE_TILES: [usize; 4] = [2, 3, 4, 5];
+
+/// Face variants, one row each.
+const VARIANTS: [Variant; 7] = [
+    Variant::new("plain"),
+    Variant {
+        rotate: Rotate::Degree90,
+        ..Variant::new("rotated 90")
+    },
+    Variant {
+        flip: true,
+        ..Variant::new("flipped")
+    },
+    Variant {
+        flip: true,
+        rotate: Rotate::Degree270,
+        ..Variant::new("flipped, rotated 270")
+    },
+    Variant {
+        flat: [true, false, true, false],
+        ..Variant::new("left and top flat")
+    },
+    Variant {
+        flat: [false, true, false, true],
+        ..Variant::new("right and bottom flat")
+    },
+    Variant {
+        flat: [true; 4],
+        ..Variant::new("all sides flat")
+    },
+];
+
+struct Variant {
+    name: &'static str,
+    flip: bool,
+    rotate: Rotate,
+    /// Left, right, top and bottom.
+    flat: [bool; 4],
+}
+
+impl Variant {
+    const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            flip: false,
+            rotate: Rotate::Degree0,
+            flat: [false; 4],
+        }
+    }
+
+    fn block(&self, slope_type: SlopeType) -> BlockInfo {
+        let face = |kind, tile_id, flat| {
+            Face::new(
+                kind,
+                RawFace {
+                    tile_id,
+                    wall: false,
+                    bullet_wall: false,
+                    flat,
+                    flip: self.flip,
+                    rotate: self.rotate,
+                },
+            )
+        };
+
+        let lid_tile = match slope_type {
+            SlopeType::ThreeSidedDiagonal(_) => THREE_SIDED_LID_TILE_ID,
+            _ => LID_TILE,
+        };
+
+        BlockInfo {
+            lid: face(FaceKind::Lid, lid_tile, false),
+            left: face(FaceKind::Normal, SIDE_TILES[0], self.flat[0]),
+            right: face(FaceKind::Normal, SIDE_TILES[1], self.flat[1]),
+            top: face(FaceKind::Normal, SIDE_TILES[2], self.flat[2]),
+            bottom: face(FaceKind::Normal, SIDE_TILES[3], self.flat[3]),
+            arrows: Arrows::default(),
+            ground_type: GroundType::Pavement,
+            slope_type,
+        }
+    }
+}
+
+/// Every slope type, three-sided diagonals last.
+fn slope_types() -> impl Iterator<Item = SlopeType> {
+    let three_sided =
+        DIAGONAL_CODES.map(|code| SlopeType::decode(code << 2, THREE_SIDED_LID_TILE_ID));
+
+    SLOPE_CODES
+        .map(|code| SlopeType::from(code << 2))
+        .chain(three_sided)
+}
+
+#[derive(Resource)]
+struct GalleryAssets {
+    uv_check: Handle<Image>,
+}
+
+fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(GalleryAssets {
+        uv_check: asset_server.load("uv_check.png"),
+    });
+
+    let width = (slope_types().count() - 1) as f32 * SPACING;
+    let depth = (VARIANTS.len() - 1) as f32 * SPACING;
+    commands.spawn((
+        PanOrbitCamera {
+            focus: Vec3::new(width / 2.0, depth / 2.0, 0.0),
+            ..default()
+        },
+        Transform::from_xyz(width / 2.0, -depth, 40.0),
+    ));
+    commands.spawn((
+        DirectionalLight::default(),
+        Transform::from_xyz(0.0, 0.0, 10.0).looking_at(Vec3::new(1.0, 2.0, 0.0), Vec3::Z),
+    ));
+    commands.insert_resource(AmbientLight {
+        brightness: 500.0,
+        ..default()
+    });
+
+    let rows: Vec<_> = VARIANTS
+        .iter()
+        .enumerate()
+        .map(|(row, variant)| format!("y {}: {}", row as f32 * SPACING, variant.name))
+        .collect();
+    commands.spawn((
+        Text::new(format!(
+            "x: slope code {}-{}, then {}-{} three-sided, {SPACING} apart\n{}",
+            SLOPE_CODES.start(),
+            SLOPE_CODES.end(),
+            DIAGONAL_CODES.start(),
+            DIAGONAL_CODES.end(),
+            rows.join("\n")
+        )),
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(12.0),
+            left: Val::Px(12.0),
+            ..default()
+        },
+    ));
+}
+
+fn spawn_gallery(
+    gallery: Res<GalleryAssets>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<MapMaterial>>,
+    mut commands: Commands,
+) {
+    let mut textures = TextureIndex::default();
+    for tile_id in SIDE_TILES.into_iter().chain([LID_TILE]) {
+        textures.index.insert(tile_id, gallery.uv_check.clone());
+    }
+
+    let mut block_meshes = BlockMeshes::default();
+    let mut faces = FaceBuilder {
+        block_meshes: &mut block_meshes,
+        meshes: &mut meshes,
+        textures: &textures,
+        materials: &mut materials,
+    };
+
+    for (row, variant) in VARIANTS.iter().enumerate() {
+        for (column, slope_type) in slope_types().enumerate() {
+            let pos = block::Position {
+                x: (column as f32 * SPACING) as u8,
+                y: (row as f32 * SPACING) as u16,
+                z: 0,
+            };
+
+            block::spawn(pos, &variant.block(slope_type), &mut faces, &mut commands);
+        }
+    }
+
+    commands.remove_resource::<GalleryAssets>();
+}
@@ -0,0 +1,532 @@
+//! Procedural meshes of the faces of every block model.
+//!
+//! Models are unit cubes centred on the origin with Z up, in the orientation
+//! of a slope going up towards +Y and a diagonal on the bottom left. The
+//! spawners turn and move them into place. Lids are mapped from above, with
+//! the top of the tile towards +Y. Walls are mapped as seen from outside,
+//! with the top of the tile at the top of the block, so partial heights show
+//! the lower part of the tile.
+
+use bevy::{
+    asset::RenderAssetUsages,
+    math::{Vec2, Vec3},
+    render::mesh::{Indices, Mesh, PrimitiveTopology},
+};
+
+use super::file::CornerPosition;
+
+/// Width of partial blocks, 24 of 64 pixels.
+pub const PARTIAL_SIZE: f32 = 24.0 / 64.0;
+/// Offset of partial blocks from the block centre.
+pub const PARTIAL_OFFSET: f32 = (1.0 - PARTIAL_SIZE) / 2.0;
+
+/// Heights of slopes are multiples of an eighth block.
+const SLOPE_STEPS: f32 = 8.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Model {
+    Cube,
+    /// Lid rising from `low` at -Y to `high` at +Y, in eighths of a block.
+    Slope {
+        low: u8,
+        high: u8,
+    },
+    /// Vertical diagonal wall with the lid on the top right.
+    Diagonal,
+    /// Slanted diagonal face from the bottom left edges up to the top right
+    /// corner, without lid.
+    ThreeSided,
+    /// Slanted diagonal face from the bottom left corner up to the lid on
+    /// the top right.
+    FourSided,
+    /// Strip along the X axis, mapped as the bottom of the block.
+    Partial,
+    Corner(CornerPosition),
+    Centre,
+}
+
+impl Model {
+    /// 7° slope `index` of 8.
+    pub fn degree_7(index: u8) -> Self {
+        let index = index.min(7);
+        Self::Slope {
+            low: index,
+            high: index + 1,
+        }
+    }
+
+    /// Lower or upper half of a 26° slope.
+    pub fn degree_26(high: bool) -> Self {
+        let low = if high { 4 } else { 0 };
+        Self::Slope { low, high: low + 4 }
+    }
+
+    pub fn degree_45() -> Self {
+        Self::Slope { low: 0, high: 8 }
+    }
+
+    /// Offset of the model from the block centre the UVs are mapped from.
+    fn uv_offset(&self) -> Vec3 {
+        match self {
+            Model::Partial => Vec3::new(0.0, -PARTIAL_OFFSET, 0.0),
+            Model::Corner(position) => corner_offset(*position).extend(0.0),
+            _ => Vec3::ZERO,
+        }
+    }
+
+    /// Half the size of the footprint of the model.
+    fn half_size(&self) -> Vec2 {
+        match self {
+            Model::Partial => Vec2::new(0.5, PARTIAL_SIZE / 2.0),
+            Model::Corner(_) | Model::Centre => Vec2::splat(PARTIAL_SIZE / 2.0),
+            _ => Vec2::splat(0.5),
+        }
+    }
+}
+
+/// Offset of a partial corner block from the block centre.
+pub fn corner_offset(position: CornerPosition) -> Vec2 {
+    match position {
+        CornerPosition::TopLeft => Vec2::new(-PARTIAL_OFFSET, PARTIAL_OFFSET),
+        CornerPosition::TopRight => Vec2::new(PARTIAL_OFFSET, PARTIAL_OFFSET),
+        CornerPosition::BottomRight => Vec2::new(PARTIAL_OFFSET, -PARTIAL_OFFSET),
+        CornerPosition::BottomLeft => Vec2::new(-PARTIAL_OFFSET, -PARTIAL_OFFSET),
+    }
+}
+
+/// Face of a model, the sides are named by the side of the block they face.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Part {
+    Lid,
+    Left,
+    Right,
+    Top,
+    Bottom,
+    /// The diagonal wall or slanted face of diagonals.
+    Diagonal,
+}
+
+impl Part {
+    /// Horizontal direction a side faces.
+    fn outward(&self) -> Vec3 {
+        match self {
+            Part::Lid => Vec3::Z,
+            Part::Left => Vec3::NEG_X,
+            Part::Right => Vec3::X,
+            Part::Top => Vec3::Y,
+            Part::Bottom => Vec3::NEG_Y,
+            Part::Diagonal => Vec3::new(-1.0, -1.0, 0.0),
+        }
+    }
+}
+
+/// Mesh of a face of a model, `back` is the face seen from inside the
+/// block, which flat faces show as well.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FaceMesh {
+    pub model: Model,
+    pub part: Part,
+    pub back: bool,
+}
+
+impl FaceMesh {
+    pub fn new(model: Model, part: Part) -> Self {
+        Self {
+            model,
+            part,
+            back: false,
+        }
+    }
+
+    pub fn back(self) -> Self {
+        Self { back: true, ..self }
+    }
+
+    /// `None` if the model has no such face.
+    pub fn geometry(&self) -> Option<Geometry> {
+        let geometry = match self.model {
+            Model::Cube | Model::Partial | Model::Corner(_) | Model::Centre => {
+                cuboid(self.model, self.part)
+            }
+            Model::Slope { low, high } => slope(low, high, self.part),
+            Model::Diagonal => diagonal(self.part),
+            Model::ThreeSided => three_sided(self.part),
+            Model::FourSided => four_sided(self.part),
+        }?;
+
+        Some(if self.back {
+            geometry.reversed()
+        } else {
+            geometry
+        })
+    }
+}
+
+/// Plain mesh data, usable without a renderer.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Geometry {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub uvs: Vec<[f32; 2]>,
+    pub indices: Vec<u32>,
+}
+
+impl Geometry {
+    /// Convex polygon facing `outward`, with one UV per point.
+    fn polygon(points: &[Vec3], uvs: &[Vec2], outward: Vec3) -> Self {
+        let normal = (points[1] - points[0])
+            .cross(points[2] - points[0])
+            .normalize_or_zero();
+        let (normal, clockwise) = if normal.dot(outward) < 0.0 {
+            (-normal, true)
+        } else {
+            (normal, false)
+        };
+
+        let indices = (1..points.len() as u32 - 1)
+            .flat_map(|i| {
+                if clockwise {
+                    [0, i + 1, i]
+                } else {
+                    [0, i, i + 1]
+                }
+            })
+            .collect();
+
+        Self {
+            positions: points.iter().map(|p| p.to_array()).collect(),
+            normals: vec![normal.to_array(); points.len()],
+            uvs: uvs.iter().map(|uv| uv.to_array()).collect(),
+            indices,
+        }
+    }
+
+    /// Lid polygon, mapped from above.
+    fn lid(points: &[Vec3], uv_offset: Vec3) -> Self {
+        let uvs: Vec<_> = points
+            .iter()
+            .map(|p| {
+                let p = *p + uv_offset;
+                Vec2::new(p.x + 0.5, 0.5 - p.y)
+            })
+            .collect();
+
+        Self::polygon(points, &uvs, Vec3::Z)
+    }
+
+    /// Vertical wall polygon facing `outward`, mapped from the front. The
+    /// tile spans the wall of a full block, diagonal walls are stretched.
+    fn wall(points: &[Vec3], outward: Vec3, uv_offset: Vec3) -> Self {
+        let right = Vec3::Z.cross(outward);
+        let uvs: Vec<_> = points
+            .iter()
+            .map(|p| {
+                let p = *p + uv_offset;
+                Vec2::new(p.dot(right) / right.length_squared() + 0.5, 0.5 - p.z)
+            })
+            .collect();
+
+        Self::polygon(points, &uvs, outward)
+    }
+
+    /// Seen from the other side, the tile is mirrored.
+    fn reversed(self) -> Self {
+        Self {
+            normals: self
+                .normals
+                .iter()
+                .map(|n| (-Vec3::from(*n)).to_array())
+                .collect(),
+            uvs: self.uvs.iter().map(|[u, v]| [1.0 - u, *v]).collect(),
+            indices: self
+                .indices
+                .chunks(3)
+                .flat_map(|triangle| [triangle[0], triangle[2], triangle[1]])
+                .collect(),
+            ..self
+        }
+    }
+}
+
+impl From<Geometry> for Mesh {
+    fn from(geometry: Geometry) -> Self {
+        Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::default(),
+        )
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, geometry.positions)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, geometry.normals)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, geometry.uvs)
+        .with_inserted_indices(Indices::U32(geometry.indices))
+    }
+}
+
+/// Corners of the wall of `part` of a box with the footprint `half` and
+/// heights `bottom` and `top`.
+fn box_wall(part: Part, half: Vec2, bottom: f32, top: f32) -> [Vec3; 4] {
+    let (a, b) = match part {
+        Part::Left => (Vec2::new(-half.x, half.y), Vec2::new(-half.x, -half.y)),
+        Part::Right => (Vec2::new(half.x, -half.y), Vec2::new(half.x, half.y)),
+        Part::Top => (Vec2::new(half.x, half.y), Vec2::new(-half.x, half.y)),
+        _ => (Vec2::new(-half.x, -half.y), Vec2::new(half.x, -half.y)),
+    };
+
+    [
+        a.extend(bottom),
+        b.extend(bottom),
+        b.extend(top),
+        a.extend(top),
+    ]
+}
+
+fn cuboid(model: Model, part: Part) -> Option<Geometry> {
+    let half = model.half_size();
+    let uv_offset = model.uv_offset();
+
+    match part {
+        Part::Lid => {
+            let points = [
+                Vec3::new(-half.x, -half.y, 0.5),
+                Vec3::new(half.x, -half.y, 0.5),
+                Vec3::new(half.x, half.y, 0.5),
+                Vec3::new(-half.x, half.y, 0.5),
+            ];
+            Some(Geometry::lid(&points, uv_offset))
+        }
+        Part::Diagonal => None,
+        side => Some(Geometry::wall(
+            &box_wall(side, half, -0.5, 0.5),
+            side.outward(),
+            uv_offset,
+        )),
+    }
+}
+
+fn slope(low: u8, high: u8, part: Part) -> Option<Geometry> {
+    let height = |eighths: u8| f32::from(eighths) / SLOPE_STEPS - 0.5;
+    let (low_z, high_z) = (height(low), height(high));
+    let half = Vec2::splat(0.5);
+
+    match part {
+        Part::Lid => {
+            let points = [
+                Vec3::new(-0.5, -0.5, low_z),
+                Vec3::new(0.5, -0.5, low_z),
+                Vec3::new(0.5, 0.5, high_z),
+                Vec3::new(-0.5, 0.5, high_z),
+            ];
+            Some(Geometry::lid(&points, Vec3::ZERO))
+        }
+        Part::Left | Part::Right => {
+            let x = if part == Part::Left { -0.5 } else { 0.5 };
+            let mut points = vec![Vec3::new(x, 0.5, -0.5), Vec3::new(x, -0.5, -0.5)];
+            if low > 0 {
+                points.push(Vec3::new(x, -0.5, low_z));
+            }
+            points.push(Vec3::new(x, 0.5, high_z));
+
+            Some(Geometry::wall(&points, part.outward(), Vec3::ZERO))
+        }
+        Part::Top => Some(Geometry::wall(
+            &box_wall(part, half, -0.5, high_z),
+            part.outward(),
+            Vec3::ZERO,
+        )),
+        Part::Bottom => (low > 0).then(|| {
+            Geometry::wall(
+                &box_wall(part, half, -0.5, low_z),
+                part.outward(),
+                Vec3::ZERO,
+            )
+        }),
+        Part::Diagonal => None,
+    }
+}
+
+/// Lid on the top right half of the block.
+fn diagonal_lid() -> Geometry {
+    let points = [
+        Vec3::new(0.5, 0.5, 0.5),
+        Vec3::new(-0.5, 0.5, 0.5),
+        Vec3::new(0.5, -0.5, 0.5),
+    ];
+    Geometry::lid(&points, Vec3::ZERO)
+}
+
+/// The full right and top walls of diagonals.
+fn diagonal_side(part: Part) -> Option<Geometry> {
+    matches!(part, Part::Right | Part::Top).then(|| cuboid(Model::Cube, part))?
+}
+
+fn diagonal(part: Part) -> Option<Geometry> {
+    match part {
+        Part::Lid => Some(diagonal_lid()),
+        Part::Diagonal => {
+            let points = [
+                Vec3::new(0.5, -0.5, -0.5),
+                Vec3::new(0.5, -0.5, 0.5),
+                Vec3::new(-0.5, 0.5, 0.5),
+                Vec3::new(-0.5, 0.5, -0.5),
+            ];
+            Some(Geometry::wall(&points, part.outward(), Vec3::ZERO))
+        }
+        side => diagonal_side(side),
+    }
+}
+
+fn three_sided(part: Part) -> Option<Geometry> {
+    let slanted = Vec3::new(-1.0, -1.0, 1.0);
+
+    match part {
+        // NOTE: the bottom edge of the tile runs along the bottom diagonal
+        Part::Diagonal => Some(Geometry::polygon(
+            &[
+                Vec3::new(-0.5, 0.5, -0.5),
+                Vec3::new(0.5, 0.5, 0.5),
+                Vec3::new(0.5, -0.5, -0.5),
+            ],
+            &[
+                Vec2::new(0.0, 1.0),
+                Vec2::new(0.5, 0.0),
+                Vec2::new(1.0, 1.0),
+            ],
+            slanted,
+        )),
+        Part::Top => Some(Geometry::wall(
+            &[
+                Vec3::new(0.5, 0.5, -0.5),
+                Vec3::new(-0.5, 0.5, -0.5),
+                Vec3::new(0.5, 0.5, 0.5),
+            ],
+            part.outward(),
+            Vec3::ZERO,
+        )),
+        Part::Right => Some(Geometry::wall(
+            &[
+                Vec3::new(0.5, -0.5, -0.5),
+                Vec3::new(0.5, 0.5, -0.5),
+                Vec3::new(0.5, 0.5, 0.5),
+            ],
+            part.outward(),
+            Vec3::ZERO,
+        )),
+        _ => None,
+    }
+}
+
+fn four_sided(part: Part) -> Option<Geometry> {
+    let slanted = Vec3::new(-1.0, -1.0, 1.0);
+
+    match part {
+        Part::Lid => Some(diagonal_lid()),
+        // NOTE: the top edge of the tile runs along the edge of the lid
+        Part::Diagonal => Some(Geometry::polygon(
+            &[
+                Vec3::new(-0.5, -0.5, -0.5),
+                Vec3::new(0.5, -0.5, 0.5),
+                Vec3::new(-0.5, 0.5, 0.5),
+            ],
+            &[
+                Vec2::new(0.5, 1.0),
+                Vec2::new(1.0, 0.0),
+                Vec2::new(0.0, 0.0),
+            ],
+            slanted,
+        )),
+        side => diagonal_side(side),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn heights(mesh: FaceMesh) -> Vec<f32> {
+        let geometry = mesh.geometry().unwrap();
+        let mut heights: Vec<_> = geometry.positions.iter().map(|p| p[2] + 0.5).collect();
+        heights.sort_by(f32::total_cmp);
+        heights.dedup();
+        heights
+    }
+
+    #[test]
+    fn slopes_rise_by_their_angle() {
+        let lid = |model| heights(FaceMesh::new(model, Part::Lid));
+
+        assert_eq!(lid(Model::degree_7(0)), [0.0, 0.125]);
+        assert_eq!(lid(Model::degree_7(7)), [0.875, 1.0]);
+        assert_eq!(lid(Model::degree_26(false)), [0.0, 0.5]);
+        assert_eq!(lid(Model::degree_26(true)), [0.5, 1.0]);
+        assert_eq!(lid(Model::degree_45()), [0.0, 1.0]);
+    }
+
+    #[test]
+    fn slopes_only_have_walls_below_their_edges() {
+        let bottom = |model| FaceMesh::new(model, Part::Bottom).geometry();
+
+        assert!(bottom(Model::degree_45()).is_none());
+        assert!(bottom(Model::degree_7(0)).is_none());
+        assert_eq!(
+            heights(FaceMesh::new(Model::degree_7(3), Part::Bottom)),
+            [0.0, 0.375]
+        );
+        assert_eq!(
+            heights(FaceMesh::new(Model::degree_26(true), Part::Top)),
+            [0.0, 1.0]
+        );
+    }
+
+    #[test]
+    fn faces_point_outwards() {
+        let models = [
+            Model::Cube,
+            Model::degree_26(true),
+            Model::Diagonal,
+            Model::ThreeSided,
+            Model::FourSided,
+            Model::Partial,
+            Model::Corner(CornerPosition::TopLeft),
+        ];
+        let parts = [
+            Part::Lid,
+            Part::Left,
+            Part::Right,
+            Part::Top,
+            Part::Bottom,
+            Part::Diagonal,
+        ];
+
+        for model in models {
+            for part in parts {
+                let mesh = FaceMesh::new(model, part);
+                let Some(geometry) = mesh.geometry() else {
+                    continue;
+                };
+
+                for triangle in geometry.indices.chunks(3) {
+                    let [a, b, c] =
+                        [0, 1, 2].map(|i| Vec3::from(geometry.positions[triangle[i] as usize]));
+                    let winding = (b - a).cross(c - a);
+                    assert!(winding.dot(part.outward()) > 0.0, "{mesh:?}");
+
+                    let back = mesh.back().geometry().unwrap();
+                    assert_eq!(back.positions, geometry.positions);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn partial_blocks_show_their_part_of_the_tile() {
+        let lid = FaceMesh::new(Model::Partial, Part::Lid).geometry().unwrap();
+        let vs: Vec<_> = lid.uvs.iter().map(|uv| uv[1]).collect();
+        assert!(vs.iter().all(|v| (0.625..=1.0).contains(v)), "{vs:?}");
+
+        let corner = FaceMesh::new(Model::Corner(CornerPosition::TopLeft), Part::Lid);
+        let uvs = corner.geometry().unwrap().uvs;
+        assert!(
+            uvs.iter().all(|[u, v]| *u <= 0.375 && *v <= 0.375),
+            "{uvs:?}"
+        );
+    }
+}
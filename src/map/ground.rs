@@ -0,0 +1,563 @@
+//! Floor shape of blocks, matching the geometry the viewer spawns.
+//!
+//! Blocks are unit cubes centred on their position. Positions inside a block
+//! are given as offset from its centre, so both components are in
+//! `-0.5..=0.5`, and heights are measured from the bottom of the block.
+
+use std::f32::consts::TAU;
+
+use bevy::math::{IVec2, Vec2};
+
+use super::{
+    file::{
+        BlockInfo, CornerPosition, DiagonalType, GroundType, Map, PartialPosition, SlopeDirection,
+        SlopeLevel, SlopeType, UncompressedMap,
+    },
+    geometry::PARTIAL_SIZE,
+};
+
+/// Height of the floor of `block` above its bottom at `local`, or `None` if
+/// there is no floor at this point.
+pub fn floor_height(block: &BlockInfo, local: Vec2) -> Option<f32> {
+    if block.ground_type == GroundType::Air {
+        return None;
+    }
+
+    slope_height(&block.slope_type, local)
+}
+
+/// Height of the floor of a block of `slope_type` which is not air, see
+/// [`floor_height`].
+pub fn slope_height(slope_type: &SlopeType, local: Vec2) -> Option<f32> {
+    match slope_type {
+        SlopeType::None | SlopeType::SlopeAbove => Some(1.0),
+        SlopeType::Degree7 { direction, index } => {
+            Some((f32::from(*index) + rise(direction, local)) / 8.0)
+        }
+        SlopeType::Degree26 { direction, level } => {
+            let base = match level {
+                SlopeLevel::Low => 0.0,
+                SlopeLevel::High => 0.5,
+            };
+            Some(base + rise(direction, local) / 2.0)
+        }
+        SlopeType::Degree45(direction) => Some(rise(direction, local)),
+        SlopeType::Diagonal(diagonal_type) => {
+            (diagonal(diagonal_type, local) >= 0.0).then_some(1.0)
+        }
+        SlopeType::ThreeSidedDiagonal(diagonal_type) => {
+            let distance = diagonal(diagonal_type, local);
+            (distance >= 0.0).then_some(distance)
+        }
+        SlopeType::FourSidedDiagonal(diagonal_type) => {
+            Some((1.0 + diagonal(diagonal_type, local)).min(1.0))
+        }
+        SlopeType::PartialBlock(position) => {
+            let inside = match position {
+                PartialPosition::Left => local.x <= PARTIAL_SIZE - 0.5,
+                PartialPosition::Right => local.x >= 0.5 - PARTIAL_SIZE,
+                PartialPosition::Top => local.y >= 0.5 - PARTIAL_SIZE,
+                PartialPosition::Bottom => local.y <= PARTIAL_SIZE - 0.5,
+            };
+            inside.then_some(1.0)
+        }
+        SlopeType::PartialCornerBlock(position) => {
+            let (left, top) = match position {
+                CornerPosition::TopLeft => (true, true),
+                CornerPosition::TopRight => (false, true),
+                CornerPosition::BottomRight => (false, false),
+                CornerPosition::BottomLeft => (true, false),
+            };
+            let inside_x = if left {
+                local.x <= PARTIAL_SIZE - 0.5
+            } else {
+                local.x >= 0.5 - PARTIAL_SIZE
+            };
+            let inside_y = if top {
+                local.y >= 0.5 - PARTIAL_SIZE
+            } else {
+                local.y <= PARTIAL_SIZE - 0.5
+            };
+            (inside_x && inside_y).then_some(1.0)
+        }
+        SlopeType::PartialCentre => {
+            let inside = local.abs().max_element() <= PARTIAL_SIZE / 2.0;
+            inside.then_some(1.0)
+        }
+        SlopeType::Ignore(_) => None,
+    }
+}
+
+/// How steep the floor of `slope_type` is at `local`, the rise in block
+/// heights per block in X and Y.
+pub fn slope_gradient(slope_type: &SlopeType, local: Vec2) -> Vec2 {
+    let uphill = |direction: &SlopeDirection, rise: f32| {
+        Vec2::from_angle(slope_angle(direction)).rotate(Vec2::Y) * rise
+    };
+    let diagonal_uphill = |diagonal_type: &DiagonalType| {
+        Vec2::from_angle(diagonal_angle(diagonal_type)).rotate(Vec2::ONE)
+    };
+
+    match slope_type {
+        SlopeType::Degree7 { direction, .. } => uphill(direction, 1.0 / 8.0),
+        SlopeType::Degree26 { direction, .. } => uphill(direction, 0.5),
+        SlopeType::Degree45(direction) => uphill(direction, 1.0),
+        SlopeType::ThreeSidedDiagonal(diagonal_type) => diagonal_uphill(diagonal_type),
+        SlopeType::FourSidedDiagonal(diagonal_type) if diagonal(diagonal_type, local) < 0.0 => {
+            diagonal_uphill(diagonal_type)
+        }
+        _ => Vec2::ZERO,
+    }
+}
+
+/// Floor at a world position.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ground {
+    /// World height of the floor.
+    pub height: f32,
+    /// Level of the block the floor belongs to.
+    pub level: i32,
+    pub ground_type: GroundType,
+}
+
+impl Map {
+    /// Highest floor at the world position `x`, `y` which is not above
+    /// `z_hint`, `None` if there is only air below or the map has no blocks.
+    pub fn ground_at(&self, x: f32, y: f32, z_hint: f32) -> Option<Ground> {
+        ground_below(self.uncompressed_map.as_ref()?, Vec2::new(x, y), z_hint)
+    }
+}
+
+/// Floors at the world `position` from the top level down.
+pub fn grounds(map: &UncompressedMap, position: Vec2) -> impl Iterator<Item = Ground> + '_ {
+    let cell = position.round();
+    let local = position - cell;
+
+    (0..UncompressedMap::Z as i32)
+        .rev()
+        .filter_map(move |level| {
+            let block = block_at(map, cell.as_ivec2(), level)?;
+
+            Some(Ground {
+                height: level as f32 - 0.5 + floor_height(block, local)?,
+                level,
+                ground_type: block.ground_type,
+            })
+        })
+}
+
+/// Highest floor at the world `position` which is not above `max_z`.
+pub fn ground_below(map: &UncompressedMap, position: Vec2, max_z: f32) -> Option<Ground> {
+    grounds(map, position).find(|ground| ground.height <= max_z + f32::EPSILON)
+}
+
+/// Height of the highest floor at the world `position` which is not above
+/// `max_z`.
+pub fn floor_below(map: &UncompressedMap, position: Vec2, max_z: f32) -> Option<f32> {
+    ground_below(map, position, max_z).map(|ground| ground.height)
+}
+
+/// Level of the block containing the world height `z`.
+pub(crate) fn level_at(z: f32) -> i32 {
+    (z + 0.5).floor() as i32
+}
+
+/// Block at the world cell `cell` and `level`.
+pub fn block_at(map: &UncompressedMap, cell: IVec2, level: i32) -> Option<&BlockInfo> {
+    let row = UncompressedMap::row(cell.y);
+    if cell.x < 0 || row < 0 || level < 0 {
+        return None;
+    }
+
+    map.get(cell.x as usize, row as usize, level as usize)
+}
+
+/// How far `local` is up a slope in `direction`, from 0 at the low to 1 at
+/// the high edge.
+fn rise(direction: &SlopeDirection, local: Vec2) -> f32 {
+    (unrotate(local, slope_angle(direction)).y + 0.5).clamp(0.0, 1.0)
+}
+
+/// Signed distance of `local` from the diagonal, in units where the solid
+/// corner is at 1 and the open corner at -1.
+fn diagonal(diagonal_type: &DiagonalType, local: Vec2) -> f32 {
+    let local = unrotate(local, diagonal_angle(diagonal_type));
+    (local.x + local.y).clamp(-1.0, 1.0)
+}
+
+/// Rotation of the block model of slopes going up in `direction`.
+fn slope_angle(direction: &SlopeDirection) -> f32 {
+    match direction {
+        SlopeDirection::Up => 0.0,
+        SlopeDirection::Left => 0.25 * TAU,
+        SlopeDirection::Down => 0.5 * TAU,
+        SlopeDirection::Right => 0.75 * TAU,
+    }
+}
+
+/// Rotation of the block model of diagonals.
+fn diagonal_angle(diagonal_type: &DiagonalType) -> f32 {
+    match diagonal_type {
+        DiagonalType::DownLeft => 0.0,
+        DiagonalType::DownRight => 0.25 * TAU,
+        DiagonalType::UpRight => 0.5 * TAU,
+        DiagonalType::UpLeft => 0.75 * TAU,
+    }
+}
+
+/// Rotates `local` into the unrotated block model the meshes are made for.
+fn unrotate(local: Vec2, angle: f32) -> Vec2 {
+    Vec2::from_angle(-angle).rotate(local)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bevy::utils::default;
+
+    use crate::map::file::{tests::test_map_with_blocks, CompressedMap32};
+
+    /// World cell of the tested block, on level 1 above a road.
+    const CELL: Vec2 = Vec2::new(10.0, 10.0);
+    const LEVEL: usize = 1;
+    /// Distance of the sampled points from the block centre.
+    const INSET: f32 = 0.4;
+    const DIRECTIONS: [SlopeDirection; 4] = [
+        SlopeDirection::Up,
+        SlopeDirection::Down,
+        SlopeDirection::Left,
+        SlopeDirection::Right,
+    ];
+    const DIAGONALS: [DiagonalType; 4] = [
+        DiagonalType::UpLeft,
+        DiagonalType::UpRight,
+        DiagonalType::DownLeft,
+        DiagonalType::DownRight,
+    ];
+
+    fn test_map() -> Map {
+        let road = BlockInfo {
+            ground_type: GroundType::Road,
+            ..default()
+        };
+
+        Map {
+            uncompressed_map: Some(test_map_with_blocks([(CELL.as_ivec2().extend(0), road)])),
+            compressed_map_32bit: CompressedMap32::from(&UncompressedMap::new()),
+            lights: Vec::new(),
+            zones: Vec::new(),
+        }
+    }
+
+    fn block(map: &mut Map, level: usize) -> &mut BlockInfo {
+        let row = UncompressedMap::row(CELL.y as i32) as usize;
+        let uncompressed = map.uncompressed_map.as_mut().unwrap();
+        uncompressed.get_mut(CELL.x as usize, row, level).unwrap()
+    }
+
+    fn set(map: &mut Map, slope_type: SlopeType) {
+        *block(map, LEVEL) = BlockInfo {
+            ground_type: GroundType::Pavement,
+            slope_type,
+            ..default()
+        };
+    }
+
+    /// Height of the floor of the tested block above its bottom at `offset`
+    /// from its centre, `None` if the ground is on another level.
+    fn height(map: &Map, offset: Vec2) -> Option<f32> {
+        let position = CELL + offset;
+        let ground = map.ground_at(position.x, position.y, 7.0)?;
+
+        (ground.level == LEVEL as i32).then_some(ground.height - LEVEL as f32 + 0.5)
+    }
+
+    fn assert_height(map: &Map, offset: Vec2, expected: Option<f32>) {
+        let actual = height(map, offset);
+        let close = match (actual, expected) {
+            (Some(actual), Some(expected)) => (actual - expected).abs() < 1e-5,
+            (actual, expected) => actual == expected,
+        };
+        assert!(close, "at {offset}: expected {expected:?}, got {actual:?}");
+    }
+
+    /// Unit vector towards the high edge of a slope.
+    fn uphill(direction: SlopeDirection) -> Vec2 {
+        match direction {
+            SlopeDirection::Up => Vec2::Y,
+            SlopeDirection::Down => Vec2::NEG_Y,
+            SlopeDirection::Left => Vec2::NEG_X,
+            SlopeDirection::Right => Vec2::X,
+        }
+    }
+
+    /// Corner of a diagonal which keeps the lid.
+    fn solid_corner(diagonal_type: DiagonalType) -> Vec2 {
+        match diagonal_type {
+            DiagonalType::UpLeft => Vec2::new(1.0, -1.0),
+            DiagonalType::UpRight => Vec2::new(-1.0, -1.0),
+            DiagonalType::DownLeft => Vec2::new(1.0, 1.0),
+            DiagonalType::DownRight => Vec2::new(-1.0, 1.0),
+        }
+    }
+
+    /// Checks the low edge, centre and high edge of a slope which rises from
+    /// `low` to `high` in every direction.
+    fn assert_slope(
+        map: &mut Map,
+        slope_type: impl Fn(SlopeDirection) -> SlopeType,
+        low: f32,
+        high: f32,
+    ) {
+        for direction in DIRECTIONS {
+            set(map, slope_type(direction));
+            let uphill = uphill(direction);
+
+            for t in [-INSET, 0.0, INSET] {
+                let expected = low + (high - low) * (t + 0.5);
+                assert_height(map, uphill * t, Some(expected));
+            }
+            // across the slope the height does not change
+            let across = uphill.perp() * INSET;
+            assert_height(map, across, Some((low + high) / 2.0));
+        }
+    }
+
+    #[test]
+    fn flat_blocks_are_full_height() {
+        let mut map = test_map();
+
+        for slope_type in [SlopeType::None, SlopeType::SlopeAbove] {
+            set(&mut map, slope_type);
+            for offset in [Vec2::ZERO, Vec2::splat(INSET), Vec2::splat(-INSET)] {
+                assert_height(&map, offset, Some(1.0));
+            }
+        }
+    }
+
+    #[test]
+    fn ground_types_are_reported() {
+        let mut map = test_map();
+
+        for ground_type in [GroundType::Road, GroundType::Pavement, GroundType::Field] {
+            block(&mut map, LEVEL).ground_type = ground_type;
+            let ground = map.ground_at(CELL.x, CELL.y, 7.0).unwrap();
+
+            assert_eq!(ground.ground_type, ground_type);
+            assert_eq!(ground.level, LEVEL as i32);
+            assert_eq!(ground.height, 1.5);
+        }
+    }
+
+    #[test]
+    fn air_has_no_ground() {
+        let mut map = test_map();
+        block(&mut map, LEVEL).ground_type = GroundType::Air;
+
+        let ground = map.ground_at(CELL.x, CELL.y, 7.0).unwrap();
+        assert_eq!(ground.level, 0);
+        assert_eq!(ground.ground_type, GroundType::Road);
+
+        block(&mut map, 0).ground_type = GroundType::Air;
+        assert_eq!(map.ground_at(CELL.x, CELL.y, 7.0), None);
+    }
+
+    #[test]
+    fn the_hint_picks_the_floor_below_it() {
+        let mut map = test_map();
+        set(&mut map, SlopeType::None);
+
+        let ground = |z_hint| {
+            map.ground_at(CELL.x, CELL.y, z_hint)
+                .map(|ground| ground.height)
+        };
+        assert_eq!(ground(7.0), Some(1.5));
+        assert_eq!(ground(1.5), Some(1.5));
+        assert_eq!(ground(1.4), Some(0.5));
+        assert_eq!(ground(0.4), None);
+    }
+
+    #[test]
+    fn maps_without_blocks_have_no_ground() {
+        let map = Map {
+            uncompressed_map: None,
+            ..test_map()
+        };
+
+        assert_eq!(map.ground_at(CELL.x, CELL.y, 7.0), None);
+    }
+
+    #[test]
+    fn degree_7_slopes_rise_an_eighth_per_step() {
+        let mut map = test_map();
+
+        for index in 0..8u8 {
+            let low = f32::from(index) / 8.0;
+            assert_slope(
+                &mut map,
+                |direction| SlopeType::Degree7 { direction, index },
+                low,
+                low + 1.0 / 8.0,
+            );
+        }
+    }
+
+    #[test]
+    fn degree_26_slopes_rise_half_a_block() {
+        let mut map = test_map();
+
+        for (level, low) in [(SlopeLevel::Low, 0.0), (SlopeLevel::High, 0.5)] {
+            assert_slope(
+                &mut map,
+                |direction| SlopeType::Degree26 { direction, level },
+                low,
+                low + 0.5,
+            );
+        }
+    }
+
+    #[test]
+    fn degree_45_slopes_rise_a_block() {
+        let mut map = test_map();
+
+        assert_slope(&mut map, SlopeType::Degree45, 0.0, 1.0);
+    }
+
+    #[test]
+    fn slope_codes_decode_to_their_heights() {
+        let mut map = test_map();
+
+        // codes 1-8 are 26°, 9-40 are 7° and 41-44 are 45°
+        for code in 1..=44u8 {
+            let slope_type = SlopeType::from(code << 2);
+            let (direction, low, high) = match slope_type {
+                SlopeType::Degree26 { direction, level } => {
+                    let low = if level == SlopeLevel::High { 0.5 } else { 0.0 };
+                    (direction, low, low + 0.5)
+                }
+                SlopeType::Degree7 { direction, index } => {
+                    let low = f32::from(index) / 8.0;
+                    (direction, low, low + 1.0 / 8.0)
+                }
+                SlopeType::Degree45(direction) => (direction, 0.0, 1.0),
+                other => panic!("code {code} is {other:?}"),
+            };
+
+            set(&mut map, slope_type);
+            assert_height(
+                &map,
+                uphill(direction) * -INSET,
+                Some(low + (high - low) * 0.1),
+            );
+            assert_height(
+                &map,
+                uphill(direction) * INSET,
+                Some(low + (high - low) * 0.9),
+            );
+        }
+    }
+
+    #[test]
+    fn diagonals_have_ground_on_their_solid_half() {
+        let mut map = test_map();
+
+        for diagonal_type in DIAGONALS {
+            set(&mut map, SlopeType::Diagonal(diagonal_type));
+            let solid = solid_corner(diagonal_type) * INSET;
+
+            assert_height(&map, solid, Some(1.0));
+            assert_height(&map, Vec2::ZERO, Some(1.0));
+            assert_height(&map, -solid, None);
+        }
+    }
+
+    #[test]
+    fn three_sided_diagonals_rise_towards_their_corner() {
+        let mut map = test_map();
+
+        for diagonal_type in DIAGONALS {
+            set(&mut map, SlopeType::ThreeSidedDiagonal(diagonal_type));
+            let solid = solid_corner(diagonal_type) * INSET;
+
+            assert_height(&map, solid, Some(2.0 * INSET));
+            assert_height(&map, solid / 2.0, Some(INSET));
+            assert_height(&map, Vec2::ZERO, Some(0.0));
+            assert_height(&map, -solid, None);
+        }
+    }
+
+    #[test]
+    fn four_sided_diagonals_fall_towards_their_open_corner() {
+        let mut map = test_map();
+
+        for diagonal_type in DIAGONALS {
+            set(&mut map, SlopeType::FourSidedDiagonal(diagonal_type));
+            let solid = solid_corner(diagonal_type) * INSET;
+
+            assert_height(&map, solid, Some(1.0));
+            assert_height(&map, Vec2::ZERO, Some(1.0));
+            assert_height(&map, -solid / 2.0, Some(1.0 - INSET));
+            assert_height(&map, -solid, Some(1.0 - 2.0 * INSET));
+        }
+    }
+
+    #[test]
+    fn partial_blocks_have_ground_on_their_strip() {
+        let mut map = test_map();
+
+        for (position, inside) in [
+            (PartialPosition::Left, Vec2::NEG_X),
+            (PartialPosition::Right, Vec2::X),
+            (PartialPosition::Top, Vec2::Y),
+            (PartialPosition::Bottom, Vec2::NEG_Y),
+        ] {
+            set(&mut map, SlopeType::PartialBlock(position));
+
+            assert_height(&map, inside * INSET, Some(1.0));
+            assert_height(&map, inside * INSET + inside.perp() * INSET, Some(1.0));
+            assert_height(&map, Vec2::ZERO, None);
+            assert_height(&map, -inside * INSET, None);
+        }
+    }
+
+    #[test]
+    fn partial_corners_have_ground_in_their_corner() {
+        let mut map = test_map();
+
+        for (position, corner) in [
+            (CornerPosition::TopLeft, Vec2::new(-1.0, 1.0)),
+            (CornerPosition::TopRight, Vec2::new(1.0, 1.0)),
+            (CornerPosition::BottomRight, Vec2::new(1.0, -1.0)),
+            (CornerPosition::BottomLeft, Vec2::new(-1.0, -1.0)),
+        ] {
+            set(&mut map, SlopeType::PartialCornerBlock(position));
+
+            assert_height(&map, corner * INSET, Some(1.0));
+            assert_height(&map, Vec2::ZERO, None);
+            assert_height(&map, corner * Vec2::new(INSET, -INSET), None);
+            assert_height(&map, corner * Vec2::new(-INSET, INSET), None);
+            assert_height(&map, -corner * INSET, None);
+        }
+    }
+
+    #[test]
+    fn partial_centres_have_ground_in_the_middle() {
+        let mut map = test_map();
+        set(&mut map, SlopeType::PartialCentre);
+
+        assert_height(&map, Vec2::ZERO, Some(1.0));
+        assert_height(&map, Vec2::splat(PARTIAL_SIZE / 2.0 - 0.01), Some(1.0));
+        assert_height(&map, Vec2::new(INSET, 0.0), None);
+        assert_height(&map, Vec2::new(0.0, -INSET), None);
+    }
+
+    #[test]
+    fn ignored_codes_have_no_ground() {
+        let mut map = test_map();
+        set(&mut map, SlopeType::Ignore(62));
+
+        assert_height(&map, Vec2::ZERO, None);
+        assert_eq!(map.ground_at(CELL.x, CELL.y, 7.0).unwrap().level, 0);
+    }
+}
@@ -0,0 +1,278 @@
+//! Selects the clicked block, outlines it and shows its [`BlockInfo`] in a
+//! panel, together with where it is stored in the compressed map.
+
+use bevy::{color::palettes::css::GOLD, input::common_conditions::input_just_pressed, prelude::*};
+
+use super::{
+    block,
+    bookmarks::MapLink,
+    file::{Arrows, BlockInfo, Face, SlopeDirection},
+    CurrentMap, Map, MapFileAsset, TextureIndex,
+};
+
+pub fn plugin(app: &mut App) {
+    app.init_resource::<Selection>()
+        .add_systems(Startup, spawn_inspector)
+        .add_systems(
+            Update,
+            (
+                add_select_observer,
+                clear_selection.run_if(input_just_pressed(CLEAR_KEY)),
+                draw_selection,
+                update_inspector.run_if(resource_changed::<Selection>),
+            ),
+        );
+}
+
+const CLEAR_KEY: KeyCode = KeyCode::Escape;
+const THUMBNAIL_SIZE: f32 = 48.0;
+pub(super) const FACE_NAMES: [&str; 5] = ["Lid", "Left", "Right", "Top", "Bottom"];
+
+/// Block shown in the inspector.
+#[derive(Resource, Debug, Default)]
+pub(crate) struct Selection(pub Option<block::Position>);
+
+#[derive(Component)]
+struct Inspector;
+
+#[derive(Component)]
+struct InspectorText;
+
+/// Index into [`FACE_NAMES`].
+#[derive(Component)]
+struct FaceThumbnail(usize);
+
+#[derive(Component)]
+struct FaceText(usize);
+
+fn spawn_inspector(mut commands: Commands) {
+    let font = TextFont {
+        font_size: 12.0,
+        ..default()
+    };
+
+    commands
+        .spawn((
+            Inspector,
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Px(10.0),
+                left: Val::Px(10.0),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(8.0),
+                padding: UiRect::all(Val::Px(8.0)),
+                ..default()
+            },
+            BackgroundColor(Color::BLACK.with_alpha(0.7)),
+            Visibility::Hidden,
+        ))
+        .with_children(|panel| {
+            panel.spawn((InspectorText, Text::default(), font.clone()));
+
+            panel
+                .spawn(Node {
+                    flex_direction: FlexDirection::Row,
+                    column_gap: Val::Px(12.0),
+                    ..default()
+                })
+                .with_children(|faces| {
+                    for (i, name) in FACE_NAMES.into_iter().enumerate() {
+                        faces
+                            .spawn(Node {
+                                flex_direction: FlexDirection::Column,
+                                row_gap: Val::Px(4.0),
+                                ..default()
+                            })
+                            .with_children(|face| {
+                                face.spawn((Text::new(name), font.clone(), TextColor(GOLD.into())));
+                                face.spawn((
+                                    FaceThumbnail(i),
+                                    ImageNode::default(),
+                                    Node {
+                                        width: Val::Px(THUMBNAIL_SIZE),
+                                        height: Val::Px(THUMBNAIL_SIZE),
+                                        ..default()
+                                    },
+                                ));
+                                face.spawn((FaceText(i), Text::default(), font.clone()));
+                            });
+                    }
+                });
+        });
+}
+
+fn add_select_observer(mut commands: Commands, blocks: Query<(Entity, Ref<block::Block>)>) {
+    for (entity, block) in &blocks {
+        if block.is_added() {
+            commands.entity(entity).observe(on_click_select);
+        }
+    }
+}
+
+/// Clicks on faces bubble up to their block.
+fn on_click_select(
+    click: Trigger<Pointer<Click>>,
+    blocks: Query<&block::Block>,
+    mut selection: ResMut<Selection>,
+) {
+    let Ok(block) = blocks.get(click.entity()) else {
+        return;
+    };
+
+    selection.0 = Some(block.pos);
+}
+
+fn clear_selection(mut selection: ResMut<Selection>) {
+    selection.0 = None;
+}
+
+fn draw_selection(selection: Res<Selection>, mut gizmos: Gizmos) {
+    let Some(pos) = selection.0 else {
+        return;
+    };
+
+    // slightly larger than the block to not fight with its faces
+    let transform = Transform::from_translation(Vec3::from(pos)).with_scale(Vec3::splat(1.02));
+    gizmos.cuboid(transform, GOLD);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_inspector(
+    selection: Res<Selection>,
+    map: Option<Res<Map>>,
+    map_assets: Res<Assets<MapFileAsset>>,
+    textures: Option<Res<TextureIndex>>,
+    current_map: Res<CurrentMap>,
+    mut panels: Query<&mut Visibility, With<Inspector>>,
+    mut summaries: Query<&mut Text, With<InspectorText>>,
+    mut face_texts: Query<(&mut Text, &FaceText), Without<InspectorText>>,
+    mut thumbnails: Query<(&mut ImageNode, &FaceThumbnail)>,
+) {
+    let map_file = map.and_then(|map| map_assets.get(&map.asset));
+    let selected = selection.0.zip(map_file).and_then(|(pos, map_file)| {
+        let (x, y, z) = pos.file_coordinates()?;
+        let block = map_file.0.uncompressed_map.as_ref()?.get(x, y, z)?;
+
+        Some((pos, (x, y, z), block, map_file))
+    });
+
+    for mut visibility in &mut panels {
+        *visibility = if selected.is_some() {
+            Visibility::Visible
+        } else {
+            Visibility::Hidden
+        };
+    }
+
+    let Some((pos, (x, y, z), block, map_file)) = selected else {
+        return;
+    };
+
+    let faces = faces(block);
+    let compressed = &map_file.0.compressed_map_32bit;
+    let link = MapLink {
+        map: Some(current_map.0.get_map_file_name().display().to_string()),
+        cell: Vec3::from(pos).as_ivec3(),
+    };
+
+    for mut text in &mut summaries {
+        **text = format!(
+            "Link: {link}\n\
+             File position: {x},{y},{z}\n\
+             Column: {}\n\
+             Block info: {}\n\
+             Slope: {:?}\n\
+             Ground: {:?}\n\
+             Arrows: {}",
+            optional_index(compressed.column_index(x, y)),
+            optional_index(compressed.block_info_index(x, y, z)),
+            block.slope_type,
+            block.ground_type,
+            describe_arrows(block.arrows),
+        );
+    }
+
+    for (mut text, FaceText(i)) in &mut face_texts {
+        **text = describe_face(faces[*i]);
+    }
+
+    for (mut image, FaceThumbnail(i)) in &mut thumbnails {
+        let face = faces[*i];
+        let tile = (face.tile_id != 0)
+            .then(|| textures.as_ref()?.index.get(&face.tile_id).cloned())
+            .flatten();
+
+        *image = match tile {
+            Some(tile) => ImageNode {
+                flip_x: face.flip,
+                ..ImageNode::new(tile)
+            },
+            // transparent placeholder to keep the layout
+            None => ImageNode::solid_color(Color::NONE),
+        };
+    }
+}
+
+/// Faces in the order of [`FACE_NAMES`].
+fn faces(block: &BlockInfo) -> [&Face; 5] {
+    [
+        &block.lid,
+        &block.left,
+        &block.right,
+        &block.top,
+        &block.bottom,
+    ]
+}
+
+fn describe_face(face: &Face) -> String {
+    if face.tile_id == 0 {
+        return "No tile".to_string();
+    }
+
+    let yes_no = |value| if value { "yes" } else { "no" };
+    let mut description = format!(
+        "Tile: {}\nRotation: {}°\nFlat: {}\nFlip: {}",
+        face.tile_id,
+        face.rotate.clockwise_rad().to_degrees().round(),
+        yes_no(face.flat),
+        yes_no(face.flip),
+    );
+
+    if face.lighting != 0 {
+        description += &format!("\nLighting: {}", face.lighting);
+    }
+
+    if face.wall || face.bullet_wall {
+        description += &format!(
+            "\nWall: {}\nBullet wall: {}",
+            yes_no(face.wall),
+            yes_no(face.bullet_wall)
+        );
+    }
+
+    description
+}
+
+fn describe_arrows(arrows: Arrows) -> String {
+    let names = |directions: &mut dyn Iterator<Item = SlopeDirection>| {
+        let names: Vec<_> = directions
+            .map(|direction| format!("{direction:?}").to_lowercase())
+            .collect();
+
+        if names.is_empty() {
+            "-".to_string()
+        } else {
+            names.join(" ")
+        }
+    };
+
+    format!(
+        "green {}, red {}",
+        names(&mut arrows.green()),
+        names(&mut arrows.red())
+    )
+}
+
+fn optional_index(index: Option<usize>) -> String {
+    index.map_or("-".to_string(), |index| index.to_string())
+}
@@ -34,7 +34,7 @@ impl AssetLoader for MapFileAssetLoader {
         let mut bytes = Vec::new();
         reader.read_to_end(&mut bytes).await?;
 
-        let map = file::Map::from_bytes(bytes);
+        let map = file::Map::from_bytes(bytes)?;
 
         Ok(MapFileAsset(map))
     }
@@ -0,0 +1,77 @@
+// NOTE: the `ShaderType` derive emits a size check function which is never
+// called and can only be silenced on module level
+#![allow(dead_code)]
+
+use bevy::{
+    pbr::MaterialExtension,
+    prelude::*,
+    render::render_resource::{AsBindGroup, ShaderRef, ShaderType},
+};
+
+#[derive(Asset, AsBindGroup, Reflect, Debug, Clone)]
+pub(crate) struct MyExtension {
+    #[uniform(100)]
+    holder: MyExtensionHolder,
+}
+
+impl MyExtension {
+    pub fn new(flip: bool, angle: f32) -> Self {
+        let flip = if flip { 1 } else { 0 };
+
+        Self {
+            holder: MyExtensionHolder {
+                flip,
+                angle,
+                lighting: 0,
+            },
+        }
+    }
+
+    /// Darkens the face by the lighting level of a lid, from 0 for full
+    /// brightness to 3 for the darkest shade.
+    pub fn with_lighting(mut self, lighting: u8) -> Self {
+        self.holder.lighting = u32::from(lighting.min(3));
+        self
+    }
+
+    /// Applies the flip and rotation of `extended_material.wgsl` to a mesh UV,
+    /// for consumers which can not run the shader.
+    pub fn transform_uv(&self, uv: [f32; 2]) -> [f32; 2] {
+        let [mut x, y] = uv;
+        if self.holder.flip == 1 {
+            x = 1.0 - x;
+        }
+
+        let (s, c) = self.holder.angle.sin_cos();
+        let (x, y) = (x - 0.5, y - 0.5);
+
+        [c * x + s * y + 0.5, -s * x + c * y + 0.5]
+    }
+}
+
+/// Brightness lost per lid lighting level, as in `extended_material.wgsl`.
+const LIGHTING_STEP: f32 = 0.2;
+
+/// Factor the colour of a lid with `lighting` level is multiplied with.
+pub fn lighting_brightness(lighting: u8) -> f32 {
+    1.0 - LIGHTING_STEP * f32::from(lighting.min(3))
+}
+
+#[derive(ShaderType, Reflect, Default, Clone, Debug)]
+struct MyExtensionHolder {
+    flip: u32,
+    angle: f32,
+    lighting: u32,
+}
+
+const SHADER_ASSET_PATH: &str = "shaders/extended_material.wgsl";
+
+impl MaterialExtension for MyExtension {
+    fn fragment_shader() -> ShaderRef {
+        SHADER_ASSET_PATH.into()
+    }
+
+    fn deferred_fragment_shader() -> ShaderRef {
+        SHADER_ASSET_PATH.into()
+    }
+}
@@ -0,0 +1,342 @@
+//! Top-down orthographic rendering of the map lids.
+//!
+//! [`TopDownRenderer`] works on the plain map and style data, so the same
+//! code draws the in-app mini-map overlay and renders whole maps to PNG from
+//! `arrie-dump render-map`.
+
+use bevy::{
+    asset::RenderAssetUsages, color::palettes::css::RED,
+    input::common_conditions::input_just_pressed, prelude::*,
+};
+use bevy_panorbit_camera::PanOrbitCamera;
+use wgpu::{Extent3d, TextureDimension, TextureFormat};
+
+use super::{
+    file::{BlockInfo, Face, Rotate, SlopeType, UncompressedMap},
+    material, Map, MapFileAsset, MapState, MyExtension, Style,
+};
+use crate::{loader::StyleFileAsset, style::tile::IMAGE_SIZE, StyleFile};
+
+pub fn plugin(app: &mut App) {
+    app.add_systems(Startup, spawn_minimap)
+        .add_systems(Update, update_minimap.run_if(in_state(MapState::Loaded)))
+        .add_systems(
+            Update,
+            toggle_minimap.run_if(input_just_pressed(TOGGLE_KEY)),
+        );
+}
+
+const TOGGLE_KEY: KeyCode = KeyCode::KeyM;
+/// Width and height of the mini-map in blocks.
+const MINIMAP_BLOCKS: u32 = 64;
+const MINIMAP_PIXELS_PER_BLOCK: usize = 4;
+const MINIMAP_SIZE: f32 = (MINIMAP_BLOCKS as usize * MINIMAP_PIXELS_PER_BLOCK) as f32;
+const MARKER_SIZE: f32 = 6.0;
+
+/// Rectangle of map cells in file coordinates, column `x` and row `y`.
+///
+/// Rows run from north to south, so a block at row `y` is spawned at world
+/// `256 - y`. Cells outside of the map are rendered transparent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Area {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Area {
+    pub const WHOLE_MAP: Self = Self {
+        x: 0,
+        y: 0,
+        width: UncompressedMap::X as u32,
+        height: UncompressedMap::Y as u32,
+    };
+
+    /// The `size` x `size` cells centred on the world position `focus`.
+    pub fn around(focus: Vec3, size: u32) -> Self {
+        let (x, y) = cell_at(focus);
+        let half = (size / 2) as i32;
+
+        Self {
+            x: x - half,
+            y: y - half,
+            width: size,
+            height: size,
+        }
+    }
+}
+
+/// Map cell (column, row) below the world position.
+pub fn cell_at(position: Vec3) -> (i32, i32) {
+    (
+        position.x.round() as i32,
+        UncompressedMap::row(position.y.round() as i32),
+    )
+}
+
+/// An 8 bit RGBA image.
+#[derive(Debug, Clone)]
+pub struct TopDownImage {
+    pub width: usize,
+    pub height: usize,
+    pub rgba: Vec<u8>,
+}
+
+/// Draws the topmost visible lid of every map cell as seen from straight
+/// above.
+///
+/// Each cell becomes a square of `pixels_per_block` pixels: 1 gives one
+/// average colour per cell, 64 the full resolution tiles. Transparent lid
+/// pixels show the lids below them.
+pub struct TopDownRenderer {
+    pixels_per_block: usize,
+    /// The tiles downsampled to `pixels_per_block`, in RGBA.
+    tiles: Vec<Vec<[u8; 4]>>,
+    /// Source pixel of each pixel per flip (index 0, 1) and rotation
+    /// (index 0 to 3).
+    orientations: [Vec<usize>; 8],
+}
+
+impl TopDownRenderer {
+    /// `pixels_per_block` is clamped to the range `1..=64`.
+    pub fn new(style: &StyleFile, pixels_per_block: usize) -> Self {
+        let size = pixels_per_block.clamp(1, IMAGE_SIZE);
+
+        let tiles = (0..style.tiles.len())
+            .map(|id| {
+                style
+                    .tile_bgra(id)
+                    .map(|bgra| downsample(&bgra, size))
+                    .unwrap_or_default()
+            })
+            .collect();
+
+        let orientations = std::array::from_fn(|i| {
+            let flip = i >= 4;
+            let angle = Rotate::from((i % 4) as u8).clockwise_rad();
+            let extension = MyExtension::new(flip, angle);
+
+            (0..size * size)
+                .map(|pixel| {
+                    let uv = [
+                        ((pixel % size) as f32 + 0.5) / size as f32,
+                        ((pixel / size) as f32 + 0.5) / size as f32,
+                    ];
+                    let [u, v] = extension.transform_uv(uv);
+                    let to_pixel = |t: f32| ((t * size as f32) as usize).min(size - 1);
+
+                    to_pixel(v) * size + to_pixel(u)
+                })
+                .collect()
+        });
+
+        Self {
+            pixels_per_block: size,
+            tiles,
+            orientations,
+        }
+    }
+
+    pub fn pixels_per_block(&self) -> usize {
+        self.pixels_per_block
+    }
+
+    pub fn render(&self, map: &UncompressedMap, area: Area) -> TopDownImage {
+        let size = self.pixels_per_block;
+        let width = area.width as usize * size;
+        let height = area.height as usize * size;
+        let mut rgba = vec![0; width * height * 4];
+
+        let mut lids = Vec::with_capacity(UncompressedMap::Z);
+        for row in 0..area.height as usize {
+            for column in 0..area.width as usize {
+                lids.clear();
+                lids.extend(visible_lids(
+                    map,
+                    area.x + column as i32,
+                    area.y + row as i32,
+                ));
+                if lids.is_empty() {
+                    continue;
+                }
+
+                for pixel in 0..size * size {
+                    let Some(color) = lids.iter().find_map(|lid| {
+                        let tile = self.tiles.get(lid.tile_id)?;
+                        let orientation = usize::from(lid.flip) * 4 + lid.rotate as usize;
+                        let mut color = *tile.get(self.orientations[orientation][pixel])?;
+
+                        let brightness = material::lighting_brightness(lid.lighting);
+                        for channel in &mut color[..3] {
+                            *channel = (f32::from(*channel) * brightness) as u8;
+                        }
+
+                        (color[3] != 0).then_some(color)
+                    }) else {
+                        continue;
+                    };
+
+                    let x = column * size + pixel % size;
+                    let y = row * size + pixel / size;
+                    let index = (y * width + x) * 4;
+                    rgba[index..index + 4].copy_from_slice(&color);
+                }
+            }
+        }
+
+        TopDownImage {
+            width,
+            height,
+            rgba,
+        }
+    }
+}
+
+/// Lids of the cell from the top down, skipping empty ones.
+fn visible_lids(map: &UncompressedMap, x: i32, y: i32) -> impl Iterator<Item = &Face> + '_ {
+    let inside = x >= 0 && y >= 0;
+
+    (0..UncompressedMap::Z)
+        .rev()
+        .filter(move |_| inside)
+        .filter_map(move |z| map.get(x as usize, y as usize, z))
+        .filter(|block: &&BlockInfo| {
+            block.lid.tile_id != 0 && !matches!(block.slope_type, SlopeType::Ignore(_))
+        })
+        .map(|block| &block.lid)
+}
+
+/// Box filters a 64x64 BGRA tile down to `size` x `size` RGBA pixels. A
+/// pixel is opaque if at least half of its source pixels are.
+fn downsample(bgra: &[u8], size: usize) -> Vec<[u8; 4]> {
+    let bounds = |i: usize| (i * IMAGE_SIZE / size, (i + 1) * IMAGE_SIZE / size);
+
+    (0..size * size)
+        .map(|pixel| {
+            let (x_start, x_end) = bounds(pixel % size);
+            let (y_start, y_end) = bounds(pixel / size);
+
+            let mut sum = [0u32; 3];
+            let mut opaque = 0;
+            for y in y_start..y_end {
+                for x in x_start..x_end {
+                    let source = &bgra[(y * IMAGE_SIZE + x) * 4..][..4];
+                    if source[3] != 0 {
+                        sum[0] += u32::from(source[2]);
+                        sum[1] += u32::from(source[1]);
+                        sum[2] += u32::from(source[0]);
+                        opaque += 1;
+                    }
+                }
+            }
+
+            let total = (x_end - x_start) * (y_end - y_start);
+            if opaque == 0 || opaque * 2 < total {
+                return [0; 4];
+            }
+
+            let average = |channel: u32| (channel / opaque as u32) as u8;
+            [average(sum[0]), average(sum[1]), average(sum[2]), 255]
+        })
+        .collect()
+}
+
+#[derive(Component)]
+struct MiniMap {
+    image: Handle<Image>,
+    renderer: Option<TopDownRenderer>,
+    area: Option<Area>,
+}
+
+fn spawn_minimap(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+    let size = Extent3d {
+        width: MINIMAP_SIZE as u32,
+        height: MINIMAP_SIZE as u32,
+        depth_or_array_layers: 1,
+    };
+    let image = images.add(Image::new_fill(
+        size,
+        TextureDimension::D2,
+        &[0, 0, 0, 0],
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+    ));
+
+    commands
+        .spawn((
+            MiniMap {
+                image: image.clone(),
+                renderer: None,
+                area: None,
+            },
+            ImageNode::new(image),
+            Node {
+                position_type: PositionType::Absolute,
+                right: Val::Px(10.0),
+                bottom: Val::Px(10.0),
+                width: Val::Px(MINIMAP_SIZE),
+                height: Val::Px(MINIMAP_SIZE),
+                ..default()
+            },
+            BackgroundColor(Color::BLACK.with_alpha(0.5)),
+        ))
+        .with_child((
+            Node {
+                position_type: PositionType::Absolute,
+                left: Val::Px((MINIMAP_SIZE - MARKER_SIZE) / 2.0),
+                top: Val::Px((MINIMAP_SIZE - MARKER_SIZE) / 2.0),
+                width: Val::Px(MARKER_SIZE),
+                height: Val::Px(MARKER_SIZE),
+                ..default()
+            },
+            BackgroundColor(RED.into()),
+        ));
+}
+
+fn update_minimap(
+    map: Res<Map>,
+    style: Res<Style>,
+    map_assets: Res<Assets<MapFileAsset>>,
+    style_assets: Res<Assets<StyleFileAsset>>,
+    cameras: Query<&PanOrbitCamera>,
+    mut images: ResMut<Assets<Image>>,
+    mut minimaps: Query<&mut MiniMap>,
+) {
+    let Some(camera) = cameras.iter().next() else {
+        return;
+    };
+    let Some(uncompressed) = map_assets
+        .get(&map.asset)
+        .and_then(|map| map.0.uncompressed_map.as_ref())
+    else {
+        return;
+    };
+
+    let area = Area::around(camera.focus, MINIMAP_BLOCKS);
+    for mut minimap in &mut minimaps {
+        if minimap.area == Some(area) {
+            continue;
+        }
+
+        if minimap.renderer.is_none() {
+            let Some(style) = style_assets.get(&style.asset) else {
+                continue;
+            };
+            minimap.renderer = Some(TopDownRenderer::new(&style.0, MINIMAP_PIXELS_PER_BLOCK));
+        }
+
+        let (Some(renderer), Some(image)) = (&minimap.renderer, images.get_mut(&minimap.image))
+        else {
+            continue;
+        };
+        image.data = renderer.render(uncompressed, area).rgba;
+        minimap.area = Some(area);
+    }
+}
+
+fn toggle_minimap(mut minimaps: Query<&mut Visibility, With<MiniMap>>) {
+    for mut visibility in &mut minimaps {
+        visibility.toggle_visible_hidden();
+    }
+}
@@ -0,0 +1,149 @@
+//! Hides the blocks above a chosen Z level, or renders them semi-transparent
+//! in x-ray mode, to look at tunnels and floors below bridges and roofs.
+
+use bevy::{prelude::*, utils::HashMap};
+
+use super::{block, file::UncompressedMap, MapMaterial, MapState};
+
+pub fn plugin(app: &mut App) {
+    app.init_resource::<ZSlice>()
+        .init_resource::<XRayMaterials>()
+        .add_systems(Startup, spawn_slice_text)
+        .add_systems(
+            Update,
+            (change_slice, apply_slice, update_slice_text)
+                .chain()
+                .run_if(in_state(MapState::Loaded)),
+        );
+}
+
+const LOWER_KEY: KeyCode = KeyCode::PageDown;
+const RAISE_KEY: KeyCode = KeyCode::PageUp;
+const XRAY_KEY: KeyCode = KeyCode::KeyX;
+const XRAY_ALPHA: f32 = 0.2;
+const TOP_LEVEL: u8 = UncompressedMap::Z as u8 - 1;
+
+#[derive(Resource, Debug)]
+pub struct ZSlice {
+    /// Highest level drawn as usual.
+    pub max_z: u8,
+    /// Draw the levels above `max_z` semi-transparent instead of hiding them.
+    pub xray: bool,
+}
+
+impl Default for ZSlice {
+    fn default() -> Self {
+        Self {
+            max_z: TOP_LEVEL,
+            xray: false,
+        }
+    }
+}
+
+/// Alpha mode of the materials currently drawn in x-ray mode, to restore
+/// them. Keyed by material, as flat faces share it with their mirrored copy.
+#[derive(Resource, Debug, Default)]
+struct XRayMaterials(HashMap<AssetId<MapMaterial>, AlphaMode>);
+
+#[derive(Component)]
+struct SliceText;
+
+fn spawn_slice_text(mut commands: Commands) {
+    commands.spawn((
+        SliceText,
+        Text::default(),
+        TextFont {
+            font_size: 14.0,
+            ..default()
+        },
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(10.0),
+            right: Val::Px(10.0),
+            ..default()
+        },
+    ));
+}
+
+fn change_slice(keys: Res<ButtonInput<KeyCode>>, mut slice: ResMut<ZSlice>) {
+    if keys.just_pressed(LOWER_KEY) {
+        slice.max_z = slice.max_z.saturating_sub(1);
+    }
+    if keys.just_pressed(RAISE_KEY) {
+        slice.max_z = (slice.max_z + 1).min(TOP_LEVEL);
+    }
+    if keys.just_pressed(XRAY_KEY) {
+        slice.xray = !slice.xray;
+    }
+}
+
+fn apply_slice(
+    mut commands: Commands,
+    slice: Res<ZSlice>,
+    mut xray_materials: ResMut<XRayMaterials>,
+    mut materials: ResMut<Assets<MapMaterial>>,
+    mut blocks: Query<(Ref<block::Block>, &mut Visibility, &Children)>,
+    faces: Query<&MeshMaterial3d<MapMaterial>>,
+) {
+    for (block, mut visibility, children) in &mut blocks {
+        // newly spawned blocks still need to be sliced
+        if !slice.is_changed() && !block.is_added() {
+            continue;
+        }
+
+        let above = block.pos.z > slice.max_z;
+        visibility.set_if_neq(if above && !slice.xray {
+            Visibility::Hidden
+        } else {
+            Visibility::Visible
+        });
+
+        let xray = above && slice.xray;
+        for &child in children {
+            let Ok(material) = faces.get(child) else {
+                continue;
+            };
+
+            let id = material.id();
+            match (xray, xray_materials.0.contains_key(&id)) {
+                (true, false) => {
+                    let Some(material) = materials.get_mut(id) else {
+                        continue;
+                    };
+                    xray_materials.0.insert(id, material.base.alpha_mode);
+                    material.base.alpha_mode = AlphaMode::Blend;
+                    material.base.base_color.set_alpha(XRAY_ALPHA);
+
+                    // click through to the visible blocks
+                    commands.entity(child).insert(PickingBehavior::IGNORE);
+                }
+                (false, true) => {
+                    let (Some(alpha_mode), Some(material)) =
+                        (xray_materials.0.remove(&id), materials.get_mut(id))
+                    else {
+                        continue;
+                    };
+                    material.base.alpha_mode = alpha_mode;
+                    material.base.base_color.set_alpha(1.0);
+
+                    commands.entity(child).remove::<PickingBehavior>();
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+fn update_slice_text(slice: Res<ZSlice>, mut texts: Query<&mut Text, With<SliceText>>) {
+    if !slice.is_changed() {
+        return;
+    }
+
+    for mut text in &mut texts {
+        **text = match (slice.max_z, slice.xray) {
+            (TOP_LEVEL, false) => String::new(),
+            (max_z, false) => format!("Max Z: {max_z}"),
+            (max_z, true) => format!("Max Z: {max_z} (x-ray)"),
+        };
+    }
+}
@@ -0,0 +1,467 @@
+//! Scrollable list of all tiles of the style with their physical palette and
+//! how often the map uses them, searchable by id. Clicking a tile outlines
+//! every face using it.
+
+use bevy::{
+    color::palettes::css::{GOLD, LIME},
+    input::{
+        common_conditions::input_just_pressed,
+        keyboard::{Key, KeyboardInput},
+        mouse::{MouseScrollUnit, MouseWheel},
+        InputSystem,
+    },
+    picking::focus::HoverMap,
+    prelude::*,
+    render::primitives::Aabb,
+};
+
+use super::{
+    tile_usage::{self, TileUsage},
+    FaceInfo, Map, MapFileAsset, Style, TextureIndex,
+};
+use crate::loader::StyleFileAsset;
+
+pub fn plugin(app: &mut App) {
+    app.init_resource::<TileBrowser>()
+        .init_resource::<HighlightedFaces>()
+        .add_systems(Startup, spawn_browser)
+        .add_systems(PreUpdate, search_input.after(InputSystem))
+        .add_systems(
+            Update,
+            (
+                toggle_browser.run_if(input_just_pressed(TOGGLE_KEY)),
+                toggle_unused_only.run_if(input_just_pressed(UNUSED_KEY)),
+                fill_browser.run_if(resource_added::<TextureIndex>),
+                update_tile_usage,
+                update_uses_texts.run_if(resource_exists_and_changed::<TileUsageStats>),
+                scroll_browser,
+                filter_tiles.run_if(
+                    resource_changed::<TileBrowser>
+                        .or(resource_exists_and_changed::<TileUsageStats>),
+                ),
+                collect_highlighted_faces,
+                update_header.run_if(
+                    resource_changed::<TileBrowser>
+                        .or(resource_changed::<HighlightedFaces>)
+                        .or(resource_exists_and_changed::<TileUsageStats>),
+                ),
+                draw_highlighted_faces,
+            )
+                .chain(),
+        );
+}
+
+const TOGGLE_KEY: KeyCode = KeyCode::KeyP;
+const SEARCH_KEY: KeyCode = KeyCode::Slash;
+const UNUSED_KEY: KeyCode = KeyCode::KeyU;
+const THUMBNAIL_SIZE: f32 = 48.0;
+/// Lets the wheel reach the scrolled list through its children.
+const PASS_THROUGH: PickingBehavior = PickingBehavior {
+    should_block_lower: false,
+    is_hoverable: true,
+};
+/// Pixels scrolled per line of mouse wheel movement.
+const LINE_HEIGHT: f32 = 24.0;
+
+#[derive(Resource, Debug, Default)]
+struct TileBrowser {
+    open: bool,
+    /// Typing into the search field.
+    searching: bool,
+    /// Shows the tiles whose id starts with it.
+    search: String,
+    /// Only show the tiles no face uses.
+    unused_only: bool,
+    highlighted: Option<usize>,
+}
+
+/// Tile usage of the loaded map, updated when it is edited.
+#[derive(Resource, Debug)]
+struct TileUsageStats(TileUsage);
+
+/// Faces using the highlighted tile.
+#[derive(Resource, Debug, Default)]
+struct HighlightedFaces(Vec<Entity>);
+
+#[derive(Component)]
+struct BrowserPanel;
+
+#[derive(Component)]
+struct BrowserHeader;
+
+#[derive(Component)]
+struct TileList;
+
+#[derive(Component)]
+struct TileCell(usize);
+
+#[derive(Component)]
+struct TileUses(usize);
+
+fn spawn_browser(mut commands: Commands) {
+    commands
+        .spawn((
+            BrowserPanel,
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Px(40.0),
+                right: Val::Px(10.0),
+                width: Val::Px(380.0),
+                height: Val::Percent(60.0),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(8.0),
+                padding: UiRect::all(Val::Px(8.0)),
+                ..default()
+            },
+            BackgroundColor(Color::BLACK.with_alpha(0.8)),
+            Visibility::Hidden,
+        ))
+        .with_children(|panel| {
+            panel.spawn((
+                BrowserHeader,
+                Text::default(),
+                TextFont {
+                    font_size: 12.0,
+                    ..default()
+                },
+            ));
+            panel.spawn((
+                TileList,
+                Node {
+                    flex_direction: FlexDirection::Row,
+                    flex_wrap: FlexWrap::Wrap,
+                    align_content: AlignContent::FlexStart,
+                    flex_grow: 1.0,
+                    overflow: Overflow::scroll_y(),
+                    ..default()
+                },
+            ));
+        });
+}
+
+fn toggle_browser(mut browser: ResMut<TileBrowser>) {
+    browser.open = !browser.open;
+}
+
+fn toggle_unused_only(mut browser: ResMut<TileBrowser>) {
+    if browser.open {
+        browser.unused_only = !browser.unused_only;
+    }
+}
+
+fn update_tile_usage(
+    mut commands: Commands,
+    mut events: EventReader<AssetEvent<MapFileAsset>>,
+    stats: Option<Res<TileUsageStats>>,
+    map: Option<Res<Map>>,
+    map_assets: Res<Assets<MapFileAsset>>,
+    style: Option<Res<Style>>,
+    style_assets: Res<Assets<StyleFileAsset>>,
+) {
+    let (Some(map), Some(style)) = (map, style) else {
+        return;
+    };
+
+    let edited = events.read().any(|event| event.is_modified(&map.asset));
+    if stats.is_some() && !edited {
+        return;
+    }
+
+    let Some(uncompressed) = map_assets
+        .get(&map.asset)
+        .and_then(|map| map.0.uncompressed_map.as_ref())
+    else {
+        return;
+    };
+    let Some(style) = style_assets.get(&style.asset) else {
+        return;
+    };
+
+    commands.insert_resource(TileUsageStats(TileUsage::new(uncompressed, &style.0)));
+}
+
+fn fill_browser(
+    mut commands: Commands,
+    textures: Res<TextureIndex>,
+    style: Res<Style>,
+    style_assets: Res<Assets<StyleFileAsset>>,
+    lists: Query<Entity, With<TileList>>,
+) {
+    let palettes = style_assets
+        .get(&style.asset)
+        .map(|style| style.0.palette_index.physical_index.as_slice())
+        .unwrap_or_default();
+
+    let mut ids: Vec<_> = textures.index.keys().copied().collect();
+    ids.sort_unstable();
+
+    for list in &lists {
+        commands.entity(list).with_children(|list| {
+            for &id in &ids {
+                let palette = palettes
+                    .get(id)
+                    .map_or("-".to_string(), |palette| palette.to_string());
+
+                list.spawn((
+                    TileCell(id),
+                    Node {
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::Center,
+                        width: Val::Px(THUMBNAIL_SIZE + 16.0),
+                        padding: UiRect::all(Val::Px(2.0)),
+                        ..default()
+                    },
+                    BackgroundColor(Color::NONE),
+                    PASS_THROUGH,
+                ))
+                .with_children(|cell| {
+                    cell.spawn((
+                        ImageNode::new(textures.index[&id].clone()),
+                        Node {
+                            width: Val::Px(THUMBNAIL_SIZE),
+                            height: Val::Px(THUMBNAIL_SIZE),
+                            ..default()
+                        },
+                        PASS_THROUGH,
+                    ));
+                    cell.spawn((
+                        Text::new(format!("{id}\npal {palette}")),
+                        TextFont {
+                            font_size: 10.0,
+                            ..default()
+                        },
+                        TextLayout::new_with_justify(JustifyText::Center),
+                        PASS_THROUGH,
+                    ));
+                    cell.spawn((
+                        TileUses(id),
+                        Text::default(),
+                        TextFont {
+                            font_size: 10.0,
+                            ..default()
+                        },
+                        TextColor(GOLD.into()),
+                        PASS_THROUGH,
+                    ));
+                })
+                .observe(on_click_highlight);
+            }
+        });
+    }
+}
+
+fn on_click_highlight(
+    click: Trigger<Pointer<Click>>,
+    cells: Query<&TileCell>,
+    mut browser: ResMut<TileBrowser>,
+) {
+    let Ok(TileCell(id)) = cells.get(click.entity()) else {
+        return;
+    };
+
+    // clicking the highlighted tile again clears it
+    browser.highlighted = (browser.highlighted != Some(*id)).then_some(*id);
+}
+
+/// Scrolls the list under the pointer, from the bevy scroll example.
+fn scroll_browser(
+    mut wheel: EventReader<MouseWheel>,
+    hover_map: Res<HoverMap>,
+    mut lists: Query<&mut ScrollPosition, With<TileList>>,
+) {
+    for event in wheel.read() {
+        let dy = match event.unit {
+            MouseScrollUnit::Line => event.y * LINE_HEIGHT,
+            MouseScrollUnit::Pixel => event.y,
+        };
+
+        for hits in hover_map.values() {
+            for entity in hits.keys() {
+                if let Ok(mut scroll) = lists.get_mut(*entity) {
+                    scroll.offset_y -= dy;
+                }
+            }
+        }
+    }
+}
+
+/// Handles the search field keys before any other system sees them.
+fn search_input(
+    mut browser: ResMut<TileBrowser>,
+    mut keyboard: EventReader<KeyboardInput>,
+    mut keys: ResMut<ButtonInput<KeyCode>>,
+) {
+    if !browser.searching {
+        keyboard.clear();
+
+        if browser.open && keys.just_pressed(SEARCH_KEY) {
+            browser.searching = true;
+            keys.reset_all();
+        }
+        return;
+    }
+
+    for event in keyboard.read() {
+        if !event.state.is_pressed() {
+            continue;
+        }
+
+        match &event.logical_key {
+            Key::Escape => {
+                browser.search.clear();
+                browser.searching = false;
+            }
+            Key::Enter => browser.searching = false,
+            Key::Backspace => {
+                browser.search.pop();
+            }
+            Key::Character(text) => {
+                let digits = text.chars().filter(char::is_ascii_digit);
+                browser.search.extend(digits);
+            }
+            _ => {}
+        }
+    }
+
+    keys.reset_all();
+}
+
+fn update_uses_texts(stats: Res<TileUsageStats>, mut texts: Query<(&mut Text, &TileUses)>) {
+    for (mut text, TileUses(id)) in &mut texts {
+        let counts = stats.0.counts.get(id).copied().unwrap_or_default();
+        **text = format!("L {} S {}", counts.lid, counts.side);
+    }
+}
+
+fn filter_tiles(
+    browser: Res<TileBrowser>,
+    stats: Option<Res<TileUsageStats>>,
+    mut panels: Query<&mut Visibility, With<BrowserPanel>>,
+    mut cells: Query<(&TileCell, &mut Node, &mut BackgroundColor)>,
+    mut lists: Query<&mut ScrollPosition, With<TileList>>,
+) {
+    for mut visibility in &mut panels {
+        *visibility = if browser.open {
+            Visibility::Visible
+        } else {
+            Visibility::Hidden
+        };
+    }
+
+    for (TileCell(id), mut node, mut background) in &mut cells {
+        let unused = stats
+            .as_ref()
+            .is_some_and(|stats| stats.0.unused.binary_search(id).is_ok());
+
+        let display =
+            if id.to_string().starts_with(&browser.search) && (unused || !browser.unused_only) {
+                Display::Flex
+            } else {
+                Display::None
+            };
+        if node.display != display {
+            node.display = display;
+        }
+
+        let color = if browser.highlighted == Some(*id) {
+            GOLD.with_alpha(0.6).into()
+        } else {
+            Color::NONE
+        };
+        background.set_if_neq(BackgroundColor(color));
+    }
+
+    if browser.searching {
+        for mut scroll in &mut lists {
+            scroll.offset_y = 0.0;
+        }
+    }
+}
+
+fn update_header(
+    browser: Res<TileBrowser>,
+    highlighted: Res<HighlightedFaces>,
+    stats: Option<Res<TileUsageStats>>,
+    mut headers: Query<&mut Text, With<BrowserHeader>>,
+) {
+    let search = if browser.searching {
+        format!("Search id: {}_", browser.search)
+    } else if browser.search.is_empty() {
+        "/ to search by id".to_string()
+    } else {
+        format!("Search id: {}", browser.search)
+    };
+
+    let highlight = match browser.highlighted {
+        Some(id) => format!("Highlighting tile {id} on {} faces", highlighted.0.len()),
+        None => "Click a tile to highlight it in the map".to_string(),
+    };
+
+    let usage = match stats {
+        Some(stats) => {
+            let usage = &stats.0;
+            let mut text = format!(
+                "{} of {} tiles used, L lids S sides",
+                usage.counts.len() - usage.unused.len(),
+                usage.counts.len()
+            );
+            if !usage.missing.is_empty() {
+                text += &format!(
+                    "\nIds missing in the style: {}",
+                    tile_usage::id_ranges(usage.missing.keys().copied())
+                );
+            }
+            text
+        }
+        None => "Counting tile usage...".to_string(),
+    };
+    let filter = if browser.unused_only {
+        "U to show all tiles"
+    } else {
+        "U to only show unused tiles"
+    };
+
+    for mut text in &mut headers {
+        **text = format!("Tiles\n{usage}\n{search}, {filter}\n{highlight}");
+    }
+}
+
+fn collect_highlighted_faces(
+    browser: Res<TileBrowser>,
+    mut highlighted: ResMut<HighlightedFaces>,
+    faces: Query<(Entity, &FaceInfo)>,
+    added: Query<(), Added<FaceInfo>>,
+) {
+    // faces are spawned again when blocks are edited
+    if !browser.is_changed() && added.is_empty() {
+        return;
+    }
+
+    highlighted.0 = match browser.highlighted {
+        Some(id) => faces
+            .iter()
+            .filter(|(_, face)| face.tile_id == id)
+            .map(|(entity, _)| entity)
+            .collect(),
+        None => Vec::new(),
+    };
+}
+
+fn draw_highlighted_faces(
+    highlighted: Res<HighlightedFaces>,
+    faces: Query<(&GlobalTransform, &Aabb, &InheritedVisibility)>,
+    mut gizmos: Gizmos,
+) {
+    for &entity in &highlighted.0 {
+        let Ok((transform, aabb, visibility)) = faces.get(entity) else {
+            continue;
+        };
+        if !visibility.get() {
+            continue;
+        }
+
+        let bounds = Transform::from_translation(aabb.center.into())
+            .with_scale((aabb.half_extents * 2.0).into());
+        gizmos.cuboid(transform.mul_transform(bounds), LIME);
+    }
+}
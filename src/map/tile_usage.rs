@@ -0,0 +1,178 @@
+//! Which tiles of a style the faces of a map use.
+
+use std::collections::BTreeMap;
+
+use super::file::{BlockInfo, SlopeType, UncompressedMap};
+use crate::StyleFile;
+
+/// Number of faces using a tile.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FaceCounts {
+    pub lid: usize,
+    /// Left, right, top and bottom faces.
+    pub side: usize,
+}
+
+impl FaceCounts {
+    pub fn total(&self) -> usize {
+        self.lid + self.side
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct TileUsage {
+    /// Uses of every tile id of the style, including unused ones.
+    pub counts: BTreeMap<usize, FaceCounts>,
+    /// Tile ids of the style no face uses.
+    pub unused: Vec<usize>,
+    /// Tile ids used by faces which are not in the style.
+    pub missing: BTreeMap<usize, FaceCounts>,
+}
+
+impl TileUsage {
+    /// Counts the tiles of all faces of `map`. Tile 0 means there is no face
+    /// and is neither counted nor reported as unused, nor is the lid marking
+    /// three-sided diagonal slopes.
+    pub fn new(map: &UncompressedMap, style: &StyleFile) -> Self {
+        let tiles_count = style.tiles.len();
+        let mut counts: BTreeMap<_, _> = (1..tiles_count)
+            .map(|id| (id, FaceCounts::default()))
+            .collect();
+        let mut missing = BTreeMap::new();
+
+        for block in &map.0 {
+            for (tile_id, lid) in face_tiles(block) {
+                let face_counts = if tile_id < tiles_count {
+                    counts.entry(tile_id).or_default()
+                } else {
+                    missing.entry(tile_id).or_default()
+                };
+
+                if lid {
+                    face_counts.lid += 1;
+                } else {
+                    face_counts.side += 1;
+                }
+            }
+        }
+
+        let unused = counts
+            .iter()
+            .filter(|(_, counts)| counts.total() == 0)
+            .map(|(id, _)| *id)
+            .collect();
+
+        Self {
+            counts,
+            unused,
+            missing,
+        }
+    }
+}
+
+/// Tile ids of the faces of `block`, and whether they are lids.
+fn face_tiles(block: &BlockInfo) -> impl Iterator<Item = (usize, bool)> + '_ {
+    let marker_lid = matches!(block.slope_type, SlopeType::ThreeSidedDiagonal(_));
+    let lid = (!marker_lid).then_some((block.lid.tile_id, true));
+
+    [&block.left, &block.right, &block.top, &block.bottom]
+        .into_iter()
+        .map(|face| (face.tile_id, false))
+        .chain(lid)
+        .filter(|(tile_id, _)| *tile_id != 0)
+}
+
+/// Formats sorted ids compactly, like `1-4, 7, 9-10`.
+pub fn id_ranges(ids: impl IntoIterator<Item = usize>) -> String {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for id in ids {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == id => *end = id,
+            _ => ranges.push((id, id)),
+        }
+    }
+
+    ranges
+        .iter()
+        .map(|&(start, end)| {
+            if start == end {
+                start.to_string()
+            } else {
+                format!("{start}-{end}")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        map::file::{DiagonalType, THREE_SIDED_LID_TILE_ID},
+        style::{tests::test_style_with_tiles, tile::Tile},
+    };
+
+    fn map(blocks: Vec<BlockInfo>) -> UncompressedMap {
+        UncompressedMap(blocks)
+    }
+
+    /// Style with the tile ids `0..count`.
+    fn style(count: usize) -> StyleFile {
+        test_style_with_tiles((0..count).map(|_| Tile(Vec::new())).collect())
+    }
+
+    #[test]
+    fn lids_and_sides_are_counted_apart() {
+        let mut block = BlockInfo::default();
+        block.lid.tile_id = 1;
+        block.left.tile_id = 1;
+        block.top.tile_id = 2;
+        block.bottom.tile_id = 2;
+
+        let usage = TileUsage::new(&map(vec![block.clone(), block]), &style(4));
+
+        assert_eq!(usage.counts[&1], FaceCounts { lid: 2, side: 2 });
+        assert_eq!(usage.counts[&2], FaceCounts { lid: 0, side: 4 });
+        assert_eq!(usage.unused, [3]);
+        assert!(!usage.counts.contains_key(&0));
+    }
+
+    #[test]
+    fn three_sided_marker_lids_are_not_counted() {
+        let mut block = BlockInfo {
+            slope_type: SlopeType::ThreeSidedDiagonal(DiagonalType::UpLeft),
+            ..Default::default()
+        };
+        block.lid.tile_id = THREE_SIDED_LID_TILE_ID;
+        block.right.tile_id = 1;
+
+        let usage = TileUsage::new(&map(vec![block]), &style(THREE_SIDED_LID_TILE_ID + 1));
+
+        assert_eq!(usage.counts[&1], FaceCounts { lid: 0, side: 1 });
+        assert_eq!(usage.counts[&THREE_SIDED_LID_TILE_ID].total(), 0);
+        assert!(usage.unused.contains(&THREE_SIDED_LID_TILE_ID));
+    }
+
+    #[test]
+    fn tiles_past_the_style_are_missing() {
+        let mut block = BlockInfo::default();
+        block.lid.tile_id = 5;
+        block.left.tile_id = 7;
+        block.right.tile_id = 1;
+
+        let usage = TileUsage::new(&map(vec![block]), &style(2));
+
+        assert_eq!(usage.missing.keys().copied().collect::<Vec<_>>(), [5, 7]);
+        assert_eq!(usage.missing[&5], FaceCounts { lid: 1, side: 0 });
+        assert_eq!(usage.missing[&7], FaceCounts { lid: 0, side: 1 });
+        assert!(usage.unused.is_empty());
+    }
+
+    #[test]
+    fn ids_are_joined_into_ranges() {
+        assert_eq!(id_ranges([1, 2, 3, 4, 7, 9, 10]), "1-4, 7, 9-10");
+        assert_eq!(id_ranges([5]), "5");
+        assert_eq!(id_ranges([]), "");
+    }
+}
@@ -0,0 +1,671 @@
+//! Lint pass over a map, finding what the game would choke on or what looks
+//! like a mistake of the map author.
+//!
+//! Locations are the world coordinates the viewer shows, printed like the
+//! links of the go-to dialog.
+
+use std::{cmp::Reverse, collections::HashSet, fmt};
+
+use bevy::math::IVec3;
+
+use super::{
+    bookmarks::MapLink,
+    file::{
+        BlockInfo, Face, GroundType, Map, SlopeDirection, SlopeLevel, SlopeType, UncompressedMap,
+    },
+};
+use crate::StyleFile;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Probably unintended, but the map still loads.
+    Warning,
+    /// The map is broken.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// Cell or block the issue was found at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub x: i32,
+    pub y: i32,
+    /// `None` for issues of a whole column.
+    pub z: Option<i32>,
+}
+
+impl Location {
+    /// Location of column `x` and `row` of the map file.
+    fn from_file(x: usize, row: usize, z: Option<usize>) -> Self {
+        Self {
+            x: x as i32,
+            y: UncompressedMap::world_y(row as i32),
+            z: z.map(|z| z as i32),
+        }
+    }
+
+    /// Link for the go-to dialog, columns link to their lowest level.
+    pub fn link(&self) -> MapLink {
+        MapLink {
+            map: None,
+            cell: IVec3::new(self.x, self.y, self.z.unwrap_or(0)),
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.link())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Issue {
+    pub severity: Severity,
+    /// `None` for issues of the whole map.
+    pub location: Option<Location>,
+    pub kind: IssueKind,
+}
+
+impl Issue {
+    fn new(location: Option<Location>, kind: IssueKind) -> Self {
+        Self {
+            severity: kind.severity(),
+            location,
+            kind,
+        }
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.location {
+            Some(location @ Location { z: None, .. }) => {
+                write!(f, "{} in column {location}: {}", self.severity, self.kind)
+            }
+            Some(location) => write!(f, "{} at {location}: {}", self.severity, self.kind),
+            None => write!(f, "{}: {}", self.severity, self.kind),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum IssueKind {
+    /// The base array does not have an entry for every cell.
+    BaseSize {
+        len: usize,
+    },
+    /// A base entry points past the column words, or the column is cut off.
+    ColumnOutOfRange {
+        index: usize,
+    },
+    /// A column word with an offset above its height or a height above the
+    /// top level.
+    BadColumn {
+        height: usize,
+        offset: usize,
+    },
+    BlockInfoOutOfRange {
+        index: usize,
+    },
+    TileOutOfRange {
+        face: &'static str,
+        tile_id: usize,
+        tiles: usize,
+    },
+    /// A 7° or 26° slope piece without the piece continuing it uphill or
+    /// downhill.
+    SlopeNeighbourMismatch {
+        slope: SlopeType,
+        uphill: bool,
+        found: SlopeType,
+    },
+    /// Slope code 62, which the game does not use.
+    IllegalSlopeCode {
+        code: u8,
+    },
+    /// A solid block without a lid and nothing on top of it.
+    MissingLid,
+    /// A road arrow pointing into a solid block or a wall.
+    ArrowIntoWall {
+        direction: SlopeDirection,
+    },
+}
+
+impl IssueKind {
+    pub fn severity(&self) -> Severity {
+        match self {
+            IssueKind::BaseSize { .. }
+            | IssueKind::ColumnOutOfRange { .. }
+            | IssueKind::BadColumn { .. }
+            | IssueKind::BlockInfoOutOfRange { .. }
+            | IssueKind::TileOutOfRange { .. }
+            | IssueKind::IllegalSlopeCode { .. } => Severity::Error,
+            IssueKind::SlopeNeighbourMismatch { .. }
+            | IssueKind::MissingLid
+            | IssueKind::ArrowIntoWall { .. } => Severity::Warning,
+        }
+    }
+}
+
+impl fmt::Display for IssueKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IssueKind::BaseSize { len } => write!(
+                f,
+                "base has {len} entries instead of {}",
+                UncompressedMap::X * UncompressedMap::Y
+            ),
+            IssueKind::ColumnOutOfRange { index } => {
+                write!(f, "column {index} is out of range")
+            }
+            IssueKind::BadColumn { height, offset } => {
+                write!(f, "column with offset {offset} and height {height}")
+            }
+            IssueKind::BlockInfoOutOfRange { index } => {
+                write!(f, "block info {index} is out of range")
+            }
+            IssueKind::TileOutOfRange {
+                face,
+                tile_id,
+                tiles,
+            } => write!(
+                f,
+                "{face} face uses tile {tile_id}, the style has {tiles} tiles"
+            ),
+            IssueKind::SlopeNeighbourMismatch {
+                slope,
+                uphill,
+                found,
+            } => write!(
+                f,
+                "{slope:?} continues {} into {found:?}",
+                if *uphill { "uphill" } else { "downhill" }
+            ),
+            IssueKind::IllegalSlopeCode { code } => write!(f, "illegal slope code {code}"),
+            IssueKind::MissingLid => write!(f, "solid block without lid"),
+            IssueKind::ArrowIntoWall { direction } => {
+                write!(f, "arrow {direction:?} points into a wall")
+            }
+        }
+    }
+}
+
+/// Finds the issues of `map`, errors first. Tiles are checked against
+/// `style`.
+pub fn validate(map: &Map, style: &StyleFile) -> Vec<Issue> {
+    let mut issues = check_indices(map);
+
+    if let Some(blocks) = &map.uncompressed_map {
+        for z in 0..UncompressedMap::Z {
+            for y in 0..UncompressedMap::Y {
+                for x in 0..UncompressedMap::X {
+                    let Some(block) = blocks.get(x, y, z) else {
+                        continue;
+                    };
+
+                    let location = Some(Location::from_file(x, y, Some(z)));
+                    issues.extend(
+                        check_block(blocks, style, block, (x, y, z))
+                            .into_iter()
+                            .map(|kind| Issue::new(location, kind)),
+                    );
+                }
+            }
+        }
+    }
+
+    issues.sort_by_key(|issue| Reverse(issue.severity));
+    issues
+}
+
+/// Indices of the compressed map. Columns shared by several cells are
+/// reported once.
+fn check_indices(map: &Map) -> Vec<Issue> {
+    let compressed = &map.compressed_map_32bit;
+    let mut issues = Vec::new();
+
+    let len = compressed.base().len();
+    if len != UncompressedMap::X * UncompressedMap::Y {
+        issues.push(Issue::new(None, IssueKind::BaseSize { len }));
+    }
+
+    let mut checked = HashSet::new();
+    for y in 0..UncompressedMap::Y {
+        for x in 0..UncompressedMap::X {
+            let Some(index) = compressed.column_index(x, y) else {
+                continue;
+            };
+            if !checked.insert(index) {
+                continue;
+            }
+
+            let location = Location::from_file(x, y, None);
+            let Some(&word) = compressed.column_infos().get(index) else {
+                issues.push(Issue::new(
+                    Some(location),
+                    IssueKind::ColumnOutOfRange { index },
+                ));
+                continue;
+            };
+
+            let height = (word & 0xff) as usize;
+            let offset = ((word & 0xff00) >> 8) as usize;
+            if offset > height || height > UncompressedMap::Z {
+                issues.push(Issue::new(
+                    Some(location),
+                    IssueKind::BadColumn { height, offset },
+                ));
+                continue;
+            }
+
+            if index + 1 + height - offset > compressed.column_infos().len() {
+                issues.push(Issue::new(
+                    Some(location),
+                    IssueKind::ColumnOutOfRange { index },
+                ));
+                continue;
+            }
+
+            for z in offset..height {
+                let Some(block_index) = compressed.block_info_index(x, y, z) else {
+                    continue;
+                };
+                if block_index >= compressed.block_infos().len() {
+                    issues.push(Issue::new(
+                        Some(Location::from_file(x, y, Some(z))),
+                        IssueKind::BlockInfoOutOfRange { index: block_index },
+                    ));
+                }
+            }
+        }
+    }
+
+    issues
+}
+
+fn check_block(
+    map: &UncompressedMap,
+    style: &StyleFile,
+    block: &BlockInfo,
+    (x, y, z): (usize, usize, usize),
+) -> Vec<IssueKind> {
+    let mut issues = Vec::new();
+
+    let tiles = style.tiles.len();
+    for (face, tile_id) in face_tiles(block) {
+        if tile_id >= tiles {
+            issues.push(IssueKind::TileOutOfRange {
+                face,
+                tile_id,
+                tiles,
+            });
+        }
+    }
+
+    match &block.slope_type {
+        SlopeType::Ignore(code) => issues.push(IssueKind::IllegalSlopeCode { code: *code }),
+        SlopeType::Degree26 { direction, level } => {
+            let uphill = *level == SlopeLevel::Low;
+            let expected = |found: &SlopeType| {
+                matches!(found, SlopeType::Degree26 { direction: d, level: l }
+                    if d == direction && *l != *level)
+            };
+            issues.extend(check_neighbour(
+                map,
+                block,
+                (x, y, z),
+                *direction,
+                uphill,
+                expected,
+            ));
+        }
+        SlopeType::Degree7 { direction, index } => {
+            for (uphill, next) in [(true, index.checked_add(1)), (false, index.checked_sub(1))] {
+                let Some(next) = next.filter(|next| *next < 8) else {
+                    continue;
+                };
+                let expected = |found: &SlopeType| {
+                    matches!(found, SlopeType::Degree7 { direction: d, index: i }
+                        if d == direction && *i == next)
+                };
+                issues.extend(check_neighbour(
+                    map,
+                    block,
+                    (x, y, z),
+                    *direction,
+                    uphill,
+                    expected,
+                ));
+            }
+        }
+        _ => {}
+    }
+
+    if block.ground_type != GroundType::Air
+        && block.lid.tile_id == 0
+        && !map
+            .get(x, y, z + 1)
+            .is_some_and(|above| above.ground_type != GroundType::Air)
+    {
+        issues.push(IssueKind::MissingLid);
+    }
+
+    for direction in block.arrows.all() {
+        if arrow_into_wall(map, (x, y, z), direction) {
+            issues.push(IssueKind::ArrowIntoWall { direction });
+        }
+    }
+
+    issues
+}
+
+/// Named faces with a tile, leaving out the lid marking three-sided
+/// diagonals.
+fn face_tiles(block: &BlockInfo) -> impl Iterator<Item = (&'static str, usize)> + '_ {
+    let marker_lid = matches!(block.slope_type, SlopeType::ThreeSidedDiagonal(_));
+    let lid = (!marker_lid).then_some(("lid", &block.lid));
+
+    [
+        ("left", &block.left),
+        ("right", &block.right),
+        ("top", &block.top),
+        ("bottom", &block.bottom),
+    ]
+    .into_iter()
+    .chain(lid)
+    .map(|(name, face): (&'static str, &Face)| (name, face.tile_id))
+    .filter(|(_, tile_id)| *tile_id != 0)
+}
+
+/// Checks the neighbour uphill or downhill of a slope piece, nothing is
+/// expected beyond the map edge.
+fn check_neighbour(
+    map: &UncompressedMap,
+    block: &BlockInfo,
+    (x, y, z): (usize, usize, usize),
+    direction: SlopeDirection,
+    uphill: bool,
+    expected: impl Fn(&SlopeType) -> bool,
+) -> Option<IssueKind> {
+    let (dx, dy) = step(direction);
+    let (dx, dy) = if uphill { (dx, dy) } else { (-dx, -dy) };
+    let neighbour = map.get(x.checked_add_signed(dx)?, y.checked_add_signed(dy)?, z)?;
+
+    (!expected(&neighbour.slope_type)).then(|| IssueKind::SlopeNeighbourMismatch {
+        slope: block.slope_type.clone(),
+        uphill,
+        found: neighbour.slope_type.clone(),
+    })
+}
+
+/// Step in file coordinates towards `direction`, rows count downwards.
+fn step(direction: SlopeDirection) -> (isize, isize) {
+    match direction {
+        SlopeDirection::Up => (0, -1),
+        SlopeDirection::Down => (0, 1),
+        SlopeDirection::Left => (-1, 0),
+        SlopeDirection::Right => (1, 0),
+    }
+}
+
+/// Whether the way along an arrow on the block at `x`, `y`, `z` is blocked
+/// one level up, where traffic drives on top of the block.
+fn arrow_into_wall(
+    map: &UncompressedMap,
+    (x, y, z): (usize, usize, usize),
+    direction: SlopeDirection,
+) -> bool {
+    let (dx, dy) = step(direction);
+    let (Some(nx), Some(ny)) = (x.checked_add_signed(dx), y.checked_add_signed(dy)) else {
+        return false;
+    };
+    let level = z + 1;
+
+    let solid = map.get(nx, ny, level).is_some_and(|block| {
+        block.ground_type != GroundType::Air && matches!(block.slope_type, SlopeType::None)
+    });
+
+    let side = |block: &BlockInfo, direction| match direction {
+        SlopeDirection::Left => block.left.wall,
+        SlopeDirection::Right => block.right.wall,
+        SlopeDirection::Up => block.top.wall,
+        SlopeDirection::Down => block.bottom.wall,
+    };
+    let opposite = match direction {
+        SlopeDirection::Left => SlopeDirection::Right,
+        SlopeDirection::Right => SlopeDirection::Left,
+        SlopeDirection::Up => SlopeDirection::Down,
+        SlopeDirection::Down => SlopeDirection::Up,
+    };
+    let wall = map
+        .get(x, y, level)
+        .is_some_and(|block| side(block, direction))
+        || map
+            .get(nx, ny, level)
+            .is_some_and(|block| side(block, opposite));
+
+    solid || wall
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        map::file::{tests::test_map_with_blocks, Arrows, CompressedMap32},
+        style::{tests::test_style_with_tiles, tile::Tile},
+    };
+
+    type Blocks<'a> = &'a [((usize, usize, usize), BlockInfo)];
+
+    /// Map with `blocks` at file coordinates.
+    fn uncompressed(blocks: Blocks) -> UncompressedMap {
+        test_map_with_blocks(blocks.iter().map(|&((x, row, z), ref block)| {
+            let cell = IVec3::new(x as i32, UncompressedMap::world_y(row as i32), z as i32);
+            (cell, block.clone())
+        }))
+    }
+
+    fn test_map(blocks: Blocks) -> Map {
+        let uncompressed = uncompressed(blocks);
+
+        Map {
+            compressed_map_32bit: CompressedMap32::from(&uncompressed),
+            uncompressed_map: Some(uncompressed),
+            lights: Vec::new(),
+            zones: Vec::new(),
+        }
+    }
+
+    /// Map read from a GMP file whose base and column words are changed by
+    /// `patch`.
+    fn patched_map(blocks: Blocks, patch: impl FnOnce(&mut [u32], &mut [u32])) -> Map {
+        let compressed = CompressedMap32::from(&uncompressed(blocks));
+        let mut base = compressed.base().to_vec();
+        let mut columns = compressed.column_infos().to_vec();
+        patch(&mut base, &mut columns);
+
+        let mut data: Vec<u8> = base.iter().flat_map(|word| word.to_ne_bytes()).collect();
+        data.extend((columns.len() as u32).to_ne_bytes());
+        data.extend(columns.iter().flat_map(|word| word.to_ne_bytes()));
+        data.extend((compressed.block_infos().len() as u32).to_ne_bytes());
+        data.extend(
+            compressed
+                .block_infos()
+                .iter()
+                .flat_map(BlockInfo::to_bytes),
+        );
+
+        let mut bytes = b"GBMP".to_vec();
+        bytes.extend(500u16.to_ne_bytes());
+        bytes.extend(b"DMAP");
+        bytes.extend((data.len() as u32).to_ne_bytes());
+        bytes.extend(data);
+
+        Map::from_bytes(bytes).unwrap()
+    }
+
+    /// Style with the tile ids `0..count`.
+    fn style(count: usize) -> StyleFile {
+        test_style_with_tiles((0..count).map(|_| Tile(Vec::new())).collect())
+    }
+
+    /// Solid block with a lid.
+    fn solid() -> BlockInfo {
+        let mut block = BlockInfo {
+            ground_type: GroundType::Pavement,
+            ..Default::default()
+        };
+        block.lid.tile_id = 1;
+        block
+    }
+
+    /// The only issue of `map`.
+    fn single_issue(map: &Map) -> Issue {
+        let issues = validate(map, &style(2));
+        assert_eq!(issues.len(), 1, "{issues:?}");
+        issues.into_iter().next().unwrap()
+    }
+
+    #[test]
+    fn column_index_out_of_range_is_an_error() {
+        let map = patched_map(&[], |base, columns| base[0] = columns.len() as u32 + 10);
+
+        let issue = single_issue(&map);
+        assert!(matches!(issue.kind, IssueKind::ColumnOutOfRange { .. }));
+        assert_eq!(issue.severity, Severity::Error);
+        assert_eq!(
+            issue.location,
+            Some(Location {
+                x: 0,
+                y: 256,
+                z: None
+            })
+        );
+    }
+
+    #[test]
+    fn block_info_index_out_of_range_is_an_error() {
+        let map = patched_map(&[((1, 2, 0), solid())], |base, columns| {
+            let column = base[2 * UncompressedMap::X + 1] as usize;
+            columns[column + 1] = 999;
+        });
+
+        let issue = single_issue(&map);
+        assert!(matches!(
+            issue.kind,
+            IssueKind::BlockInfoOutOfRange { index: 999 }
+        ));
+        assert_eq!(issue.severity, Severity::Error);
+        assert_eq!(
+            issue.location,
+            Some(Location {
+                x: 1,
+                y: 254,
+                z: Some(0)
+            })
+        );
+    }
+
+    #[test]
+    fn locations_are_go_to_links() {
+        let block = Location::from_file(1, 2, Some(3));
+        let column = Location::from_file(1, 2, None);
+
+        assert_eq!(block.to_string(), "1,254,3");
+        assert_eq!(block.to_string().parse::<MapLink>().unwrap(), block.link());
+        assert_eq!(column.link().cell, IVec3::new(1, 254, 0));
+    }
+
+    #[test]
+    fn tile_past_the_style_is_an_error() {
+        let mut block = BlockInfo::default();
+        block.left.tile_id = 5;
+
+        let issue = single_issue(&test_map(&[((3, 3, 0), block)]));
+        assert!(matches!(
+            issue.kind,
+            IssueKind::TileOutOfRange {
+                face: "left",
+                tile_id: 5,
+                tiles: 2
+            }
+        ));
+        assert_eq!(issue.severity, Severity::Error);
+    }
+
+    #[test]
+    fn low_26_degree_piece_without_high_piece_is_a_warning() {
+        let block = BlockInfo {
+            slope_type: SlopeType::Degree26 {
+                direction: SlopeDirection::Up,
+                level: SlopeLevel::Low,
+            },
+            ..solid()
+        };
+
+        let issue = single_issue(&test_map(&[((10, 10, 0), block)]));
+        assert!(matches!(
+            issue.kind,
+            IssueKind::SlopeNeighbourMismatch {
+                uphill: true,
+                found: SlopeType::None,
+                ..
+            }
+        ));
+        assert_eq!(issue.severity, Severity::Warning);
+    }
+
+    #[test]
+    fn slope_code_62_is_an_error() {
+        let block = BlockInfo {
+            slope_type: SlopeType::from(62 << 2),
+            ..Default::default()
+        };
+
+        let issue = single_issue(&test_map(&[((4, 5, 1), block)]));
+        assert!(matches!(
+            issue.kind,
+            IssueKind::IllegalSlopeCode { code: 62 }
+        ));
+        assert_eq!(issue.severity, Severity::Error);
+    }
+
+    #[test]
+    fn solid_block_without_lid_is_a_warning() {
+        let mut block = solid();
+        block.lid.tile_id = 0;
+
+        let issue = single_issue(&test_map(&[((6, 7, 2), block)]));
+        assert!(matches!(issue.kind, IssueKind::MissingLid));
+        assert_eq!(issue.severity, Severity::Warning);
+    }
+
+    #[test]
+    fn arrow_into_a_wall_is_a_warning() {
+        let road = BlockInfo {
+            ground_type: GroundType::Road,
+            // green arrow to the right
+            arrows: Arrows(0b10),
+            ..solid()
+        };
+
+        let issue = single_issue(&test_map(&[((10, 10, 0), road), ((11, 10, 1), solid())]));
+        assert!(matches!(
+            issue.kind,
+            IssueKind::ArrowIntoWall {
+                direction: SlopeDirection::Right
+            }
+        ));
+        assert_eq!(issue.severity, Severity::Warning);
+    }
+}
@@ -0,0 +1,215 @@
+use std::io::{self, Cursor, Write};
+
+use byteorder::{NativeEndian, WriteBytesExt};
+
+use super::file::{CompressedMap32, UncompressedMap};
+use crate::chunk::{self, HEADER_SIZE};
+
+/// Writes `map` as a GMP file. The header and every chunk except the maps
+/// are copied unchanged from `original`, the file `map` was read from.
+/// DMAP and, if present, UMAP are written from `map`, the 16 bit CMAP is left
+/// out as it can not hold every map.
+pub fn write_map<W: Write>(
+    map: &UncompressedMap,
+    original: &[u8],
+    writer: &mut W,
+) -> io::Result<()> {
+    let (_header, chunks) = chunk::read_layout(&mut Cursor::new(original))?;
+
+    writer.write_all(&original[..HEADER_SIZE as usize])?;
+
+    for chunk in chunks {
+        let data = match chunk.tag.as_str() {
+            "DMAP" => compressed_map_chunk(&CompressedMap32::from(map)),
+            "UMAP" => uncompressed_map_chunk(map),
+            "CMAP" => continue,
+            _ => {
+                let start = chunk.offset as usize;
+                let end = (start + chunk.size as usize).min(original.len());
+                original[start..end].to_vec()
+            }
+        };
+
+        writer.write_all(chunk.tag.as_bytes())?;
+        writer.write_u32::<NativeEndian>(data.len() as u32)?;
+        writer.write_all(&data)?;
+    }
+
+    Ok(())
+}
+
+fn compressed_map_chunk(map: &CompressedMap32) -> Vec<u8> {
+    let mut data = Vec::new();
+
+    for index in map.base() {
+        data.write_u32::<NativeEndian>(*index).unwrap();
+    }
+
+    data.write_u32::<NativeEndian>(map.column_infos().len() as u32)
+        .unwrap();
+    for word in map.column_infos() {
+        data.write_u32::<NativeEndian>(*word).unwrap();
+    }
+
+    data.write_u32::<NativeEndian>(map.block_infos().len() as u32)
+        .unwrap();
+    for block in map.block_infos() {
+        data.extend_from_slice(&block.to_bytes());
+    }
+
+    data
+}
+
+fn uncompressed_map_chunk(map: &UncompressedMap) -> Vec<u8> {
+    map.0.iter().flat_map(|block| block.to_bytes()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bevy::utils::default;
+
+    use crate::map::file::{
+        Arrows, BlockInfo, GroundType, Map, Rotate, SlopeType, THREE_SIDED_LID_TILE_ID,
+    };
+
+    fn chunk(tag: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut bytes = tag.to_vec();
+        bytes.extend_from_slice(&(data.len() as u32).to_ne_bytes());
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    /// Empty map with one block with every field set and a ZONE chunk.
+    fn test_map_bytes() -> Vec<u8> {
+        let blocks_count = UncompressedMap::X * UncompressedMap::Y * UncompressedMap::Z;
+        let mut map = UncompressedMap(vec![BlockInfo::default(); blocks_count]);
+        *map.get_mut(3, 4, 2).unwrap() = test_block();
+
+        let mut bytes = b"GBMP".to_vec();
+        bytes.extend_from_slice(&500u16.to_ne_bytes());
+        bytes.extend(chunk(
+            b"DMAP",
+            &compressed_map_chunk(&CompressedMap32::from(&map)),
+        ));
+        bytes.extend(chunk(b"ZONE", &[1, 2, 3, 4, 5]));
+        bytes
+    }
+
+    fn test_block() -> BlockInfo {
+        let mut block = BlockInfo {
+            arrows: Arrows(0b1000_0001),
+            ground_type: GroundType::Pavement,
+            slope_type: SlopeType::Ignore(62),
+            ..default()
+        };
+        block.left.tile_id = 7;
+        block.left.wall = true;
+        block.right.bullet_wall = true;
+        block.top.flat = true;
+        block.bottom.flip = true;
+        block.lid.tile_id = 1023;
+        block.lid.lighting = 2;
+        block.lid.rotate = Rotate::Degree270;
+        block
+    }
+
+    fn write(map: &UncompressedMap, original: &[u8]) -> Vec<u8> {
+        let mut written = Vec::new();
+        write_map(map, original, &mut written).unwrap();
+        written
+    }
+
+    #[test]
+    fn unchanged_map_round_trips_byte_for_byte() {
+        let bytes = test_map_bytes();
+        let map = Map::from_bytes(bytes.clone()).unwrap();
+
+        assert_eq!(write(map.uncompressed_map.as_ref().unwrap(), &bytes), bytes);
+    }
+
+    #[test]
+    fn edited_blocks_are_read_back() {
+        let bytes = test_map_bytes();
+        let mut map = Map::from_bytes(bytes.clone())
+            .unwrap()
+            .uncompressed_map
+            .unwrap();
+        *map.get_mut(3, 4, 2).unwrap() = BlockInfo::default();
+        *map.get_mut(255, 255, 7).unwrap() = test_block();
+
+        let written = Map::from_bytes(write(&map, &bytes)).unwrap();
+        let written = written.uncompressed_map.as_ref().unwrap();
+
+        assert_eq!(written.0.len(), map.0.len());
+        assert_eq!(
+            written.get(3, 4, 2).unwrap().to_bytes(),
+            BlockInfo::default().to_bytes()
+        );
+        assert_eq!(
+            written.get(255, 255, 7).unwrap().to_bytes(),
+            test_block().to_bytes()
+        );
+    }
+
+    #[test]
+    fn slope_codes_survive_decoding() {
+        for id in 0..64u8 {
+            for ground in 0..4u8 {
+                let block = BlockInfo {
+                    slope_type: SlopeType::from(id << 2),
+                    ground_type: ground.into(),
+                    ..default()
+                };
+
+                assert_eq!(block.to_bytes()[11], (id << 2) | ground);
+            }
+        }
+    }
+
+    #[test]
+    fn diagonals_are_three_sided_by_their_lid() {
+        let three_sided = SlopeType::decode(49 << 2, THREE_SIDED_LID_TILE_ID);
+        let four_sided = SlopeType::decode(49 << 2, 7);
+        assert!(matches!(three_sided, SlopeType::ThreeSidedDiagonal(_)));
+        assert!(matches!(four_sided, SlopeType::FourSidedDiagonal(_)));
+
+        let block = BlockInfo {
+            slope_type: three_sided,
+            ..default()
+        };
+        let lid = u16::from_ne_bytes([block.to_bytes()[8], block.to_bytes()[9]]);
+        assert_eq!(usize::from(lid & 0x3ff), THREE_SIDED_LID_TILE_ID);
+    }
+
+    #[test]
+    fn truncated_maps_are_errors() {
+        let bytes = test_map_bytes();
+
+        for len in [3, 8, 20, bytes.len() - 6] {
+            assert!(
+                Map::from_bytes(bytes[..len].to_vec()).is_err(),
+                "{len} bytes"
+            );
+        }
+        // a header without any chunk lacks the DMAP chunk
+        assert!(Map::from_bytes(bytes[..6].to_vec()).is_err());
+    }
+
+    #[test]
+    fn other_chunks_are_copied_through() {
+        let bytes = test_map_bytes();
+        let map = Map::from_bytes(bytes.clone()).unwrap();
+        let written = write(map.uncompressed_map.as_ref().unwrap(), &bytes);
+
+        let (_, chunks) = chunk::read_layout(&mut Cursor::new(&written)).unwrap();
+        let zone = chunks.iter().find(|chunk| chunk.tag == "ZONE").unwrap();
+        let start = zone.offset as usize;
+
+        assert_eq!(
+            &written[start..start + zone.size as usize],
+            &[1, 2, 3, 4, 5]
+        );
+    }
+}
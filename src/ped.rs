@@ -0,0 +1,556 @@
+//! Pedestrians walking the pavements of the map. They follow the pavement
+//! cell by cell, turn at its corners and cross the road at junctions, where
+//! the pavement ends at a road with pavement on the other side.
+
+use bevy::{input::common_conditions::input_just_pressed, prelude::*, utils::HashMap};
+
+use crate::{
+    camera::{CameraMode, CameraTarget},
+    car::{sprite_image, MAX_DELTA, SPRITE_LIFT},
+    loader::StyleFileAsset,
+    map::{
+        collision::{CollisionMap, MAX_CLIMB, STEP_BODY_SIZE},
+        file::{GroundType, UncompressedMap},
+        ground::{self, Ground},
+        Map, MapFileAsset, Style,
+    },
+    rng::Rng,
+    style::Remap,
+    StyleFile,
+};
+
+pub fn plugin(app: &mut App) {
+    app.init_resource::<PedSprites>()
+        .add_systems(
+            Update,
+            toggle_peds
+                .run_if(input_just_pressed(PEDS_KEY))
+                .run_if(in_state(CameraMode::Game)),
+        )
+        .add_systems(Update, (walk_peds, animate_peds).chain());
+}
+
+const PEDS_KEY: KeyCode = KeyCode::KeyQ;
+
+/// Peds spawned at once.
+const PED_COUNT: usize = 40;
+/// Distance in cells around the camera target peds are spawned in.
+const SPAWN_RADIUS: i32 = 12;
+/// Blocks per second.
+const WALK_SPEED: f32 = 0.8;
+/// Longest road crossed, in cells.
+const MAX_CROSSING: i32 = 6;
+/// Chance to cross the road at a junction instead of turning with the
+/// pavement.
+const CROSS_CHANCE: f32 = 0.5;
+/// Chance to turn into a side pavement instead of walking on.
+const TURN_CHANCE: f32 = 0.1;
+
+// NOTE: the first ped sprites are the walking animation
+const WALK_FRAMES: usize = 8;
+const FRAME_TIME: f32 = 0.1;
+/// Size of peds without sprites, in blocks.
+const DEFAULT_SIZE: Vec2 = Vec2::new(0.25, 0.25);
+const DEFAULT_COLOR: Color = Color::srgb(0.9, 0.8, 0.1);
+
+/// Ped walking from cell to cell, its transform is on the floor.
+#[derive(Component, Debug)]
+pub struct Ped {
+    /// Cell walked to and its floor.
+    target: IVec2,
+    ground: Ground,
+    direction: IVec2,
+    /// Whether the ped is on the road to the pavement on the other side.
+    crossing: bool,
+    rng: Rng,
+    remap: usize,
+    walked: f32,
+}
+
+/// Mesh and material of a sprite.
+type Frame = (Handle<Mesh>, Handle<StandardMaterial>);
+
+/// Walking animation of each ped remap.
+#[derive(Resource, Debug, Default)]
+struct PedSprites {
+    frames: HashMap<usize, Vec<Frame>>,
+}
+
+/// Floor of the neighbouring cell `to` a ped on `ground` at `from` can
+/// step on, of any ground type but air.
+fn step(
+    map: &UncompressedMap,
+    collision: &CollisionMap,
+    from: IVec2,
+    ground: &Ground,
+    to: IVec2,
+) -> Option<Ground> {
+    collision.step(map, from, ground, to, STEP_BODY_SIZE, MAX_CLIMB)
+}
+
+/// Whether the road straight ahead of `cell` leads to pavement within
+/// [`MAX_CROSSING`] cells.
+fn crossing(
+    map: &UncompressedMap,
+    collision: &CollisionMap,
+    cell: IVec2,
+    ground: &Ground,
+    direction: IVec2,
+) -> bool {
+    let (mut cell, mut ground) = (cell, *ground);
+
+    for _ in 0..=MAX_CROSSING {
+        let Some(next) = step(map, collision, cell, &ground, cell + direction) else {
+            return false;
+        };
+        match next.ground_type {
+            GroundType::Pavement => return ground.ground_type == GroundType::Road,
+            GroundType::Road => (cell, ground) = (cell + direction, next),
+            _ => return false,
+        }
+    }
+
+    false
+}
+
+/// Direction a ped on `ground` at `cell` walks on to, `None` if it is stuck.
+/// The second value is whether it crosses a road.
+fn next_direction(
+    map: &UncompressedMap,
+    collision: &CollisionMap,
+    cell: IVec2,
+    ground: &Ground,
+    direction: IVec2,
+    crossing_road: bool,
+    rng: &mut Rng,
+) -> Option<(IVec2, bool)> {
+    let ground_type =
+        |to: IVec2| step(map, collision, cell, ground, cell + to).map(|next| next.ground_type);
+    let pavement = |to: IVec2| ground_type(to) == Some(GroundType::Pavement);
+    let left = direction.perp();
+    let sides: Vec<_> = [left, -left]
+        .into_iter()
+        .filter(|to| pavement(*to))
+        .collect();
+
+    // keep going until the pavement on the other side
+    if crossing_road {
+        match ground_type(direction) {
+            Some(GroundType::Road) => return Some((direction, true)),
+            Some(GroundType::Pavement) => return Some((direction, false)),
+            _ => {}
+        }
+    }
+
+    if pavement(direction) {
+        if rng.chance(TURN_CHANCE) {
+            if let Some(side) = rng.pick(&sides) {
+                return Some((side, false));
+            }
+        }
+        return Some((direction, false));
+    }
+
+    // the pavement turns or ends, at junctions the road can be crossed
+    let cross = crossing(map, collision, cell, ground, direction);
+    if cross && (sides.is_empty() || rng.chance(CROSS_CHANCE)) {
+        return Some((direction, true));
+    }
+    if let Some(side) = rng.pick(&sides) {
+        return Some((side, false));
+    }
+
+    // dead end, or stranded on the road after the pavement ahead went away
+    if pavement(-direction) {
+        return Some((-direction, false));
+    }
+    let roads: Vec<_> = [left, -left, -direction]
+        .into_iter()
+        .filter(|to| crossing_road && ground_type(*to) == Some(GroundType::Road))
+        .collect();
+    rng.pick(&roads).map(|to| (to, true))
+}
+
+impl Ped {
+    /// Walks the ped by `dt` seconds, `transform` is its position on the
+    /// floor.
+    fn walk(
+        &mut self,
+        transform: &mut Transform,
+        map: &UncompressedMap,
+        collision: &CollisionMap,
+        dt: f32,
+    ) {
+        let mut distance = WALK_SPEED * dt;
+        let mut position = transform.translation.truncate();
+
+        while distance > 0.0 {
+            let to_target = self.target.as_vec2() - position;
+            if to_target.length() > distance {
+                position += to_target.normalize() * distance;
+                self.walked += distance;
+                break;
+            }
+
+            // arrived, choose the next cell
+            position = self.target.as_vec2();
+            distance -= to_target.length();
+            self.walked += to_target.length();
+
+            let Some((direction, crossing)) = next_direction(
+                map,
+                collision,
+                self.target,
+                &self.ground,
+                self.direction,
+                self.crossing,
+                &mut self.rng,
+            ) else {
+                break;
+            };
+            let Some(ground) = step(
+                map,
+                collision,
+                self.target,
+                &self.ground,
+                self.target + direction,
+            ) else {
+                break;
+            };
+
+            self.target += direction;
+            self.ground = ground;
+            self.direction = direction;
+            self.crossing = crossing;
+        }
+
+        let height = collision
+            .height_at(position, self.ground.height + MAX_CLIMB)
+            .unwrap_or(self.ground.height);
+        transform.translation = position.extend(height + SPRITE_LIFT);
+        // NOTE: sprites face up, along +Y
+        transform.rotation = Quat::from_rotation_z(Vec2::Y.angle_to(self.direction.as_vec2()));
+    }
+}
+
+/// Removes the peds, or spawns them on the pavement around the followed
+/// target.
+#[allow(clippy::too_many_arguments)]
+fn toggle_peds(
+    mut commands: Commands,
+    mut seed: Local<u32>,
+    peds: Query<Entity, With<Ped>>,
+    targets: Query<&GlobalTransform, With<CameraTarget>>,
+    map: Option<Res<Map>>,
+    map_assets: Res<Assets<MapFileAsset>>,
+    style: Option<Res<Style>>,
+    style_assets: Res<Assets<StyleFileAsset>>,
+    mut sprites: ResMut<PedSprites>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    if !peds.is_empty() {
+        for ped in &peds {
+            commands.entity(ped).despawn();
+        }
+        return;
+    }
+
+    let Some(uncompressed) = map
+        .and_then(|map| map_assets.get(&map.asset))
+        .and_then(|map| map.0.uncompressed_map.as_ref())
+    else {
+        warn!("No map loaded to walk on");
+        return;
+    };
+    let Some(target) = targets.iter().next() else {
+        return;
+    };
+
+    let style = style
+        .and_then(|style| style_assets.get(&style.asset))
+        .map(|style| &style.0);
+    let remaps = style.map_or(0, |style| usize::from(style.palette_base.ped_remap));
+
+    // the highest floor of a cell is where peds walk
+    let center = target.translation().truncate().round().as_ivec2();
+    let mut pavements: Vec<_> = (-SPAWN_RADIUS..=SPAWN_RADIUS)
+        .flat_map(|x| (-SPAWN_RADIUS..=SPAWN_RADIUS).map(move |y| center + IVec2::new(x, y)))
+        .filter_map(|cell| {
+            let ground = ground::grounds(uncompressed, cell.as_vec2()).next()?;
+            (ground.ground_type == GroundType::Pavement).then_some((cell, ground))
+        })
+        .collect();
+    if pavements.is_empty() {
+        warn!("No pavement around {center}");
+        return;
+    }
+
+    let mut rng = Rng::new(*seed);
+    *seed = seed.wrapping_add(1);
+    for _ in 0..PED_COUNT.min(pavements.len()) {
+        let (cell, ground) = pavements.swap_remove(rng.below(pavements.len()));
+        let remap = rng.below(remaps);
+        let direction = rng
+            .pick(&[IVec2::X, IVec2::Y, IVec2::NEG_X, IVec2::NEG_Y])
+            .unwrap_or(IVec2::Y);
+
+        let frames = sprites.frames.entry(remap).or_insert_with(|| {
+            walk_frames(
+                style,
+                remap,
+                remaps > 0,
+                &mut meshes,
+                &mut materials,
+                &mut images,
+            )
+        });
+        let (mesh, material) = frames[0].clone();
+
+        commands.spawn((
+            Ped {
+                target: cell,
+                ground,
+                direction,
+                crossing: false,
+                rng: Rng::new(rng.next()),
+                remap,
+                walked: 0.0,
+            },
+            Mesh3d(mesh),
+            MeshMaterial3d(material),
+            Transform::from_translation(cell.as_vec2().extend(ground.height + SPRITE_LIFT)),
+        ));
+    }
+}
+
+/// Meshes and materials of the walking animation in the ped `remap`, a plain
+/// square if the style has no ped sprites.
+fn walk_frames(
+    style: Option<&StyleFile>,
+    remap: usize,
+    remapped: bool,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    images: &mut Assets<Image>,
+) -> Vec<Frame> {
+    let remap = if remapped {
+        Remap::Ped(remap)
+    } else {
+        Remap::None
+    };
+
+    let frames: Vec<_> = style
+        .into_iter()
+        .flat_map(|style| {
+            let first = style.sprite_bases.ped_start();
+            (first..first + WALK_FRAMES).map(move |id| (style, id))
+        })
+        .filter_map(|(style, id)| sprite_image(style, id, remap, images))
+        .map(|(image, size)| {
+            (
+                meshes.add(Rectangle::from_size(size)),
+                materials.add(StandardMaterial {
+                    base_color_texture: Some(image),
+                    alpha_mode: AlphaMode::Mask(0.5),
+                    ..default()
+                }),
+            )
+        })
+        .collect();
+
+    if frames.is_empty() {
+        return vec![(
+            meshes.add(Rectangle::from_size(DEFAULT_SIZE)),
+            materials.add(DEFAULT_COLOR),
+        )];
+    }
+
+    frames
+}
+
+fn walk_peds(
+    time: Res<Time>,
+    map: Option<Res<Map>>,
+    map_assets: Res<Assets<MapFileAsset>>,
+    collision: Option<Res<CollisionMap>>,
+    mut peds: Query<(&mut Ped, &mut Transform)>,
+) {
+    let Some(uncompressed) = map
+        .and_then(|map| map_assets.get(&map.asset))
+        .and_then(|map| map.0.uncompressed_map.as_ref())
+    else {
+        return;
+    };
+    let Some(collision) = collision else {
+        return;
+    };
+
+    let dt = time.delta_secs().min(MAX_DELTA);
+    for (mut ped, mut transform) in &mut peds {
+        ped.walk(&mut transform, uncompressed, &collision, dt);
+    }
+}
+
+fn animate_peds(
+    sprites: Res<PedSprites>,
+    mut peds: Query<(&Ped, &mut Mesh3d, &mut MeshMaterial3d<StandardMaterial>)>,
+) {
+    for (ped, mut mesh, mut material) in &mut peds {
+        let Some(frames) = sprites.frames.get(&ped.remap) else {
+            continue;
+        };
+
+        let frame = (ped.walked / WALK_SPEED / FRAME_TIME) as usize % frames.len();
+        let (frame_mesh, frame_material) = &frames[frame];
+        if mesh.0 != *frame_mesh {
+            mesh.0 = frame_mesh.clone();
+            material.0 = frame_material.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bevy::utils::default;
+
+    use crate::map::file::{tests::test_map_with_blocks, BlockInfo, Face};
+
+    /// Map with level 0 blocks of `ground_type` at the cells given as rows
+    /// of `P` for pavement and `R` for road, from the top row at y 110 down.
+    fn map(rows: &[&str]) -> (UncompressedMap, CollisionMap) {
+        let blocks = rows.iter().enumerate().flat_map(|(i, row)| {
+            row.chars().enumerate().filter_map(move |(x, c)| {
+                let ground_type = match c {
+                    'P' => GroundType::Pavement,
+                    'R' => GroundType::Road,
+                    _ => return None,
+                };
+                let block = BlockInfo {
+                    ground_type,
+                    ..default()
+                };
+                Some((IVec3::new(100 + x as i32, 110 - i as i32, 0), block))
+            })
+        });
+
+        let map = test_map_with_blocks(blocks);
+        let collision = CollisionMap::from(&map);
+        (map, collision)
+    }
+
+    /// Cells visited by a ped starting at `cell` in `direction`.
+    fn walk(
+        (map, collision): &(UncompressedMap, CollisionMap),
+        cell: IVec2,
+        direction: IVec2,
+        steps: usize,
+        seed: u32,
+    ) -> Vec<IVec2> {
+        let mut ground = ground::ground_below(map, cell.as_vec2(), 10.0).unwrap();
+        let (mut cell, mut direction, mut crossing) = (cell, direction, false);
+        let mut rng = Rng::new(seed);
+        let mut cells = vec![cell];
+
+        for _ in 0..steps {
+            let Some(next) =
+                next_direction(map, collision, cell, &ground, direction, crossing, &mut rng)
+            else {
+                break;
+            };
+            (direction, crossing) = next;
+            ground = step(map, collision, cell, &ground, cell + direction).unwrap();
+            cell += direction;
+            cells.push(cell);
+        }
+
+        cells
+    }
+
+    fn ground_type(map: &UncompressedMap, cell: IVec2) -> GroundType {
+        ground::grounds(map, cell.as_vec2())
+            .next()
+            .unwrap()
+            .ground_type
+    }
+
+    #[test]
+    fn peds_turn_at_corners() {
+        let map = map(&["PPPPP", "RRRRP", "RRRRP", "RRRRP"]);
+
+        let cells = walk(&map, IVec2::new(100, 110), IVec2::X, 7, 1);
+        assert_eq!(cells[4], IVec2::new(104, 110));
+        assert_eq!(cells[7], IVec2::new(104, 107));
+    }
+
+    #[test]
+    fn peds_stay_on_pavement() {
+        let map = map(&["PPPPPPPP", "RRRRRRRR", "RRRRRRRR"]);
+
+        for seed in 0..20 {
+            for cell in walk(&map, IVec2::new(103, 110), IVec2::X, 50, seed) {
+                assert_eq!(ground_type(&map.0, cell), GroundType::Pavement);
+            }
+        }
+    }
+
+    #[test]
+    fn peds_stop_at_walls_and_buildings() {
+        let mut blocks = map(&["PPPP"]);
+        let fence = BlockInfo {
+            left: Face {
+                wall: true,
+                ..BlockInfo::default().left
+            },
+            ..default()
+        };
+        let building = BlockInfo {
+            ground_type: GroundType::Pavement,
+            ..default()
+        };
+        let row = UncompressedMap::row(110) as usize;
+        *blocks.0.get_mut(102, row, 1).unwrap() = fence;
+        *blocks.0.get_mut(100, row, 1).unwrap() = building;
+        blocks.1 = CollisionMap::from(&blocks.0);
+
+        let cells = walk(&blocks, IVec2::new(101, 110), IVec2::X, 4, 1);
+        assert!(cells.iter().all(|cell| *cell == IVec2::new(101, 110)));
+    }
+
+    #[test]
+    fn peds_cross_roads_at_junctions() {
+        let straight = map(&["P", "R", "R", "P"]);
+
+        // the pavement ends at the road, with pavement on the other side
+        let cells = walk(&straight, IVec2::new(100, 110), IVec2::NEG_Y, 3, 1);
+        assert_eq!(
+            cells,
+            [
+                IVec2::new(100, 110),
+                IVec2::new(100, 109),
+                IVec2::new(100, 108),
+                IVec2::new(100, 107),
+            ]
+        );
+
+        // at corners peds either turn or cross
+        let corner = map(&["PP", "RR", "RR", "PP"]);
+        let crossed = (0..20)
+            .filter(|seed| walk(&corner, IVec2::new(101, 110), IVec2::NEG_Y, 1, *seed)[1].y == 109)
+            .count();
+        assert!(0 < crossed && crossed < 20, "crossed {crossed} times");
+    }
+
+    #[test]
+    fn peds_do_not_cross_into_nowhere() {
+        let map = map(&["P", "P", "R", "R"]);
+
+        let cells = walk(&map, IVec2::new(100, 110), IVec2::NEG_Y, 4, 1);
+        assert!(cells
+            .iter()
+            .all(|cell| ground_type(&map.0, *cell) == GroundType::Pavement));
+    }
+}
@@ -0,0 +1,32 @@
+//! Small random number generator for the variety of peds and traffic.
+
+/// Xorshift generator, good enough for decisions of peds and drivers.
+#[derive(Debug, Clone)]
+pub(crate) struct Rng(u32);
+
+impl Rng {
+    pub(crate) fn new(seed: u32) -> Self {
+        // NOTE: xorshift gets stuck at 0
+        Self(seed.wrapping_mul(0x9e37_79b9) | 1)
+    }
+
+    pub(crate) fn next(&mut self) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0
+    }
+
+    /// Whether an event with `probability` happens.
+    pub(crate) fn chance(&mut self, probability: f32) -> bool {
+        (self.next() as f32 / u32::MAX as f32) < probability
+    }
+
+    pub(crate) fn below(&mut self, max: usize) -> usize {
+        self.next() as usize % max.max(1)
+    }
+
+    pub(crate) fn pick<T: Copy>(&mut self, items: &[T]) -> Option<T> {
+        (!items.is_empty()).then(|| items[self.below(items.len())])
+    }
+}
@@ -34,7 +34,7 @@ impl AssetLoader for StyleFileAssetLoader {
         let mut bytes = Vec::new();
         reader.read_to_end(&mut bytes).await?;
 
-        let style = StyleFile::from_bytes(bytes);
+        let style = StyleFile::from_bytes(bytes)?;
 
         Ok(StyleFileAsset(style))
     }
@@ -1,6 +1,10 @@
 pub mod loader;
-mod tile;
+pub mod sprite;
+pub(crate) mod tile;
+pub mod tile_sheet;
+pub mod writer;
 
+pub use sprite::{CarInfo, Remap, SpriteBases, SpriteEntry, SpriteImage};
 pub use tile::Tile;
 
 use byteorder::{NativeEndian, ReadBytesExt};
@@ -16,7 +20,7 @@ use std::{
 };
 
 // FIXME make configurable or associated constants?
-const PAGE_SIZE: usize = 256;
+pub(crate) const PAGE_SIZE: usize = 256;
 
 #[derive(Debug)]
 pub struct StyleFileHeader {
@@ -32,45 +36,99 @@ pub struct StyleFile {
     pub palette_index: PaletteIndex,
     pub palette_base: PaletteBase,
     pub physical_palette: Vec<PhysicalPalette>,
+    /// Sprite pages, empty if the style has no sprites.
+    pub sprite_graphics: Vec<u8>,
+    pub sprite_index: Vec<SpriteEntry>,
+    pub sprite_bases: SpriteBases,
+    pub car_infos: Vec<CarInfo>,
     //pub palette_base: PaletteBase,
     // TODO maybe use a HashMap for palette index and physical palettes
 }
 
 impl StyleFile {
-    pub fn from_file(file: &File) -> StyleFile {
-        let mut buf_reader = BufReader::new(file);
+    pub fn from_file(file: &File) -> io::Result<StyleFile> {
+        Self::read(&mut BufReader::new(file))
+    }
 
-        let header = read_header(&mut buf_reader).unwrap();
-        let chunks = match read_chunks(&mut buf_reader) {
-            Some(c) => c,
-            None => panic!("Error while reading chunks."),
-        };
+    pub fn from_bytes(bytes: Vec<u8>) -> io::Result<Self> {
+        Self::read(&mut Cursor::new(bytes))
+    }
 
-        StyleFile {
+    fn read<T: Read + Seek>(buf_reader: &mut T) -> io::Result<Self> {
+        let header = read_header(buf_reader)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        let chunks = read_chunks(buf_reader)?;
+
+        Ok(StyleFile {
             header,
             tiles: chunks.tiles,
             palette_index: chunks.palette_index,
             palette_base: chunks.palette_base,
             physical_palette: chunks.physical_palettes,
-        }
+            sprite_graphics: chunks.sprite_graphics,
+            sprite_index: chunks.sprite_index,
+            sprite_bases: chunks.sprite_bases,
+            car_infos: chunks.car_infos,
+        })
     }
+}
 
-    fn from_bytes(bytes: Vec<u8>) -> Self {
-        let mut buf_reader = Cursor::new(bytes);
+impl StyleFile {
+    /// Resolves a tile through its physical palette into 64x64 BGRA pixels.
+    /// Palette index 0 is fully transparent, all other colours are opaque,
+    /// black included.
+    pub fn tile_bgra(&self, id: usize) -> Option<Vec<u8>> {
+        let tile = self.tiles.get(id)?;
+        let palette_index = self.palette_index.physical_index.get(id)?;
+        let phys_palette = self.physical_palette.get(*palette_index as usize)?;
+
+        let mut pixels = Vec::with_capacity(tile.0.len() * 4);
+        for p in &tile.0 {
+            if *p == 0 {
+                pixels.extend_from_slice(&[0, 0, 0, 0]);
+            } else {
+                let c = phys_palette.colors.get(*p as usize)?.to_ne_bytes();
+                pixels.extend_from_slice(&[c[0], c[1], c[2], 255]);
+            }
+        }
 
-        let header = read_header(&mut buf_reader).unwrap();
-        let chunks = match read_chunks(&mut buf_reader) {
-            Some(c) => c,
-            None => panic!("Error while reading chunks."),
-        };
+        Some(pixels)
+    }
 
-        StyleFile {
-            header,
-            tiles: chunks.tiles,
-            palette_index: chunks.palette_index,
-            palette_base: chunks.palette_base,
-            physical_palette: chunks.physical_palettes,
+    /// Resolves sprite `id` through its own or a remap palette into BGRA
+    /// pixels. Palette index 0 is fully transparent, all other colours are
+    /// opaque.
+    pub fn sprite_bgra(&self, id: usize, remap: Remap) -> Option<SpriteImage> {
+        let entry = self.sprite_index.get(id)?;
+        let pixels = sprite::sprite_pixels(&self.sprite_graphics, entry)?;
+
+        // NOTE: the palette index holds the palettes of each kind one after
+        // the other, in the order of the palette base
+        let base = &self.palette_base;
+        let remaps = usize::from(base.tile) + usize::from(base.sprite);
+        let virtual_palette = match remap {
+            Remap::None => usize::from(base.tile) + id,
+            Remap::Car(remap) => remaps + remap,
+            Remap::Ped(remap) => remaps + usize::from(base.car_remap) + remap,
+        };
+        let palette_index = self.palette_index.physical_index.get(virtual_palette)?;
+        let phys_palette = self.physical_palette.get(*palette_index as usize)?;
+
+        let mut bgra = Vec::with_capacity(pixels.len() * 4);
+        for p in pixels {
+            if p == 0 {
+                bgra.extend_from_slice(&[0, 0, 0, 0]);
+            } else {
+                let c = phys_palette.colors.get(usize::from(p))?.to_ne_bytes();
+                bgra.extend_from_slice(&[c[0], c[1], c[2], 255]);
+            }
         }
+
+        Some(SpriteImage {
+            width: usize::from(entry.width),
+            height: usize::from(entry.height),
+            bgra,
+        })
     }
 }
 
@@ -122,11 +180,11 @@ fn read_header<T: Read>(buf_reader: &mut T) -> Result<StyleFileHeader, ParseErro
     let file_type = String::from_utf8(buffer.to_vec())?;
     let version = buf_reader.read_u16::<NativeEndian>()?;
 
-    dbg!(&file_type, &version);
-
     Ok(StyleFileHeader { file_type, version })
 }
 
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug)]
 enum ChunkBuilderError {
     MissingTilesChunkError,
     MissingPaletteIndexChunkError,
@@ -134,11 +192,29 @@ enum ChunkBuilderError {
     MissingPaletteBase,
 }
 
+impl fmt::Display for ChunkBuilderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let chunk = match self {
+            ChunkBuilderError::MissingTilesChunkError => "TILE",
+            ChunkBuilderError::MissingPaletteIndexChunkError => "PALX",
+            ChunkBuilderError::MissingPhysicalPalettesChunk => "PPAL",
+            ChunkBuilderError::MissingPaletteBase => "PALB",
+        };
+        write!(f, "Missing {} chunk", chunk)
+    }
+}
+
+impl Error for ChunkBuilderError {}
+
 struct ChunkBuilder {
     tiles: Option<Vec<Tile>>,
     palette_index: Option<PaletteIndex>,
     palette_base: Option<PaletteBase>,
     physical_palette: Option<Vec<PhysicalPalette>>,
+    sprite_graphics: Vec<u8>,
+    sprite_index: Vec<SpriteEntry>,
+    sprite_bases: SpriteBases,
+    car_infos: Vec<CarInfo>,
 }
 
 impl ChunkBuilder {
@@ -148,6 +224,10 @@ impl ChunkBuilder {
             palette_index: None,
             palette_base: None,
             physical_palette: None,
+            sprite_graphics: Vec::new(),
+            sprite_index: Vec::new(),
+            sprite_bases: SpriteBases::default(),
+            car_infos: Vec::new(),
         }
     }
 
@@ -156,19 +236,37 @@ impl ChunkBuilder {
         chunk_type: ChunkTypes,
         size: u32,
         buf_reader: &mut T,
-    ) -> &mut ChunkBuilder {
-        match chunk_type {
-            ChunkTypes::Tiles => self.tiles(load_tiles(size, buf_reader)),
+    ) -> io::Result<&mut ChunkBuilder> {
+        Ok(match chunk_type {
+            ChunkTypes::Tiles => self.tiles(load_tiles(size, buf_reader)?),
             ChunkTypes::PhysicalPalettes => {
-                self.physical_palettes(load_physical_palettes(size, buf_reader))
+                self.physical_palettes(load_physical_palettes(size, buf_reader)?)
+            }
+            ChunkTypes::PaletteBase => self.palette_base(load_palette_base(size, buf_reader)?),
+            ChunkTypes::PaletteIndex => self.palette_index(load_palette_index(size, buf_reader)?),
+            // NOTE: sprites and cars are optional, styles without them still
+            // show the map
+            ChunkTypes::SpriteGraphics => {
+                self.sprite_graphics = sprite::load_sprite_graphics(size, buf_reader)?;
+                self
+            }
+            ChunkTypes::SpriteIndex => {
+                self.sprite_index = sprite::load_sprite_index(size, buf_reader)?;
+                self
+            }
+            ChunkTypes::SpritesBases => {
+                self.sprite_bases = sprite::load_sprite_bases(size, buf_reader)?;
+                self
+            }
+            ChunkTypes::CarInfo => {
+                self.car_infos = sprite::load_car_infos(size, buf_reader)?;
+                self
             }
-            ChunkTypes::PaletteBase => self.palette_base(load_palette_base(size, buf_reader)),
-            ChunkTypes::PaletteIndex => self.palette_index(load_palette_index(size, buf_reader)),
             _ => {
-                buf_reader.seek(SeekFrom::Current(size as i64)).unwrap();
+                buf_reader.seek(SeekFrom::Current(size as i64))?;
                 self
             }
-        }
+        })
     }
 
     pub fn tiles(&mut self, tiles: Vec<Tile>) -> &mut ChunkBuilder {
@@ -211,6 +309,10 @@ impl ChunkBuilder {
             palette_base,
             palette_index,
             physical_palettes,
+            sprite_graphics: self.sprite_graphics,
+            sprite_index: self.sprite_index,
+            sprite_bases: self.sprite_bases,
+            car_infos: self.car_infos,
         };
 
         Ok(chunks)
@@ -228,6 +330,10 @@ struct StyleFileChunks {
     palette_index: PaletteIndex,
     palette_base: PaletteBase,
     physical_palettes: Vec<PhysicalPalette>,
+    sprite_graphics: Vec<u8>,
+    sprite_index: Vec<SpriteEntry>,
+    sprite_bases: SpriteBases,
+    car_infos: Vec<CarInfo>,
 }
 
 #[derive(Debug)]
@@ -293,56 +399,63 @@ impl FromStr for ChunkTypes {
     }
 }
 
-fn read_chunks<T: Read + Seek>(mut buf_reader: &mut T) -> Option<StyleFileChunks> {
+fn read_chunks<T: Read + Seek>(buf_reader: &mut T) -> io::Result<StyleFileChunks> {
     let mut buffer = [0; 4];
     let mut chunk_builder = ChunkBuilder::new();
 
     loop {
-        buf_reader.read_exact(&mut buffer).unwrap();
+        if buf_reader.read_exact(&mut buffer).is_err() {
+            break;
+        }
 
         let chunk_type = match String::from_utf8(buffer.to_vec()) {
             Ok(s) => s,
             Err(_) => break,
         };
 
-        let size = match buf_reader.read_u32::<NativeEndian>() {
-            Ok(s) => s,
-            Err(_) => unimplemented!(),
-        };
-
-        println!("{}: {} bytes", chunk_type, size);
+        let size = buf_reader.read_u32::<NativeEndian>()?;
 
         // let chunk_type = ChunkTypes::from_str(&chunk_type).unwrap_or_else(|| break);
+        // unknown chunks are skipped, chunks after them are still read
         let chunk_type = match ChunkTypes::from_str(&chunk_type) {
             Ok(c) => c,
-            Err(_) => break,
+            Err(_) => {
+                if buf_reader.seek(SeekFrom::Current(size as i64)).is_err() {
+                    break;
+                }
+                continue;
+            }
         };
 
-        chunk_builder.load_chunk(chunk_type, size, &mut buf_reader);
+        // NOTE: chunks are read from their own data, so broken lengths inside
+        // a chunk fail instead of reading into the next one
+        let data = crate::chunk::read_data(buf_reader, size)?;
+        chunk_builder.load_chunk(chunk_type, size, &mut Cursor::new(data))?;
     }
 
-    match chunk_builder.build() {
-        Ok(chunk) => Some(chunk),
-        Err(_) => None,
-    }
+    chunk_builder
+        .build()
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
 }
 
 const TILES_PER_PAGE: usize = 16;
 
-fn load_tiles<T: Read + Seek>(size: u32, buf_reader: &mut T) -> Vec<Tile> {
+fn load_tiles<T: Read + Seek>(size: u32, buf_reader: &mut T) -> io::Result<Vec<Tile>> {
     let pages_count = size / (PAGE_SIZE * PAGE_SIZE) as u32;
     let mut tiles: Vec<Tile> = Vec::with_capacity(pages_count as usize * TILES_PER_PAGE);
 
     for _ in 0..pages_count {
-        load_tiles_from_page(&mut tiles, buf_reader);
+        load_tiles_from_page(&mut tiles, buf_reader)?;
     }
 
-    dbg!(tiles.len());
-    tiles
+    Ok(tiles)
 }
 
-fn load_tiles_from_page<T: Read + Seek>(tiles: &mut Vec<Tile>, buf_reader: &mut T) {
-    let page = load_page(buf_reader);
+fn load_tiles_from_page<T: Read + Seek>(
+    tiles: &mut Vec<Tile>,
+    buf_reader: &mut T,
+) -> io::Result<()> {
+    let page = load_page(buf_reader)?;
 
     for id in 0..TILES_PER_PAGE {
         tiles.push(Tile::from_file(id, &page));
@@ -371,46 +484,49 @@ fn load_tiles_from_page<T: Read + Seek>(tiles: &mut Vec<Tile>, buf_reader: &mut
     //        Tile(tile4),
     //    ]);
     //}
-}
 
-fn load_page<T: Read + Seek>(buf_reader: &mut T) -> Vec<u8> {
-    let mut page = [0; PAGE_SIZE * PAGE_SIZE];
+    Ok(())
+}
 
-    for v in page.iter_mut() {
-        *v = buf_reader.read_u8().unwrap();
-    }
+fn load_page<T: Read + Seek>(buf_reader: &mut T) -> io::Result<Vec<u8>> {
+    let mut page = vec![0; PAGE_SIZE * PAGE_SIZE];
+    buf_reader.read_exact(&mut page)?;
 
-    page.to_vec()
+    Ok(page)
 }
 
-fn load_palette_index<T: Read + Seek>(size: u32, buf_reader: &mut T) -> PaletteIndex {
+fn load_palette_index<T: Read + Seek>(size: u32, buf_reader: &mut T) -> io::Result<PaletteIndex> {
     let size = (size / 2) as usize;
 
     let mut physical_palettes = Vec::with_capacity(size);
 
     for _ in 0..size {
-        physical_palettes.push(buf_reader.read_u16::<NativeEndian>().unwrap());
+        physical_palettes.push(buf_reader.read_u16::<NativeEndian>()?);
     }
 
-    PaletteIndex {
+    Ok(PaletteIndex {
         physical_index: physical_palettes,
-    }
+    })
 }
 
-fn load_physical_palettes<T: Read + Seek>(size: u32, buf_reader: &mut T) -> Vec<PhysicalPalette> {
-    const PALETTES_PER_PAGE: usize = 64;
+const PALETTES_PER_PAGE: usize = 64;
+
+fn load_physical_palettes<T: Read + Seek>(
+    size: u32,
+    buf_reader: &mut T,
+) -> io::Result<Vec<PhysicalPalette>> {
     let pages_count = size / (PAGE_SIZE * PAGE_SIZE) as u32;
     let mut palettes: Vec<PhysicalPalette> =
         Vec::with_capacity(pages_count as usize * PALETTES_PER_PAGE);
 
     for _ in 0..pages_count {
-        let page = load_page(buf_reader);
+        let page = load_page(buf_reader)?;
         for id in 0..PALETTES_PER_PAGE {
             palettes.push(load_phys_palette_from_page(id, &page));
         }
     }
 
-    palettes
+    Ok(palettes)
 }
 
 fn load_phys_palette_from_page(id: usize, page: &[u8]) -> PhysicalPalette {
@@ -426,18 +542,86 @@ fn load_phys_palette_from_page(id: usize, page: &[u8]) -> PhysicalPalette {
     PhysicalPalette { colors }
 }
 
-fn load_palette_base<T: Read + Seek>(size: u32, buf_reader: &mut T) -> PaletteBase {
+fn load_palette_base<T: Read + Seek>(size: u32, buf_reader: &mut T) -> io::Result<PaletteBase> {
     const PALETTE_BASE_SIZE: u32 = 16;
-    assert!(size == PALETTE_BASE_SIZE);
-
-    PaletteBase {
-        tile: buf_reader.read_u16::<NativeEndian>().unwrap(),
-        sprite: buf_reader.read_u16::<NativeEndian>().unwrap(),
-        car_remap: buf_reader.read_u16::<NativeEndian>().unwrap(),
-        ped_remap: buf_reader.read_u16::<NativeEndian>().unwrap(),
-        code_obj_remap: buf_reader.read_u16::<NativeEndian>().unwrap(),
-        map_opj_remap: buf_reader.read_u16::<NativeEndian>().unwrap(),
-        user_remap: buf_reader.read_u16::<NativeEndian>().unwrap(),
-        font_remap: buf_reader.read_u16::<NativeEndian>().unwrap(),
+    if size != PALETTE_BASE_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("PALB chunk has {size} bytes instead of {PALETTE_BASE_SIZE}"),
+        ));
+    }
+
+    Ok(PaletteBase {
+        tile: buf_reader.read_u16::<NativeEndian>()?,
+        sprite: buf_reader.read_u16::<NativeEndian>()?,
+        car_remap: buf_reader.read_u16::<NativeEndian>()?,
+        ped_remap: buf_reader.read_u16::<NativeEndian>()?,
+        code_obj_remap: buf_reader.read_u16::<NativeEndian>()?,
+        map_opj_remap: buf_reader.read_u16::<NativeEndian>()?,
+        user_remap: buf_reader.read_u16::<NativeEndian>()?,
+        font_remap: buf_reader.read_u16::<NativeEndian>()?,
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Style with one tile using palette 0, which has opaque black at index 1
+    /// and red at index 2.
+    fn test_style(pixels: Vec<u8>) -> StyleFile {
+        test_style_with_tiles(vec![Tile(pixels)])
+    }
+
+    /// Style with `tiles`, all using the palette of [`test_style`].
+    pub(crate) fn test_style_with_tiles(tiles: Vec<Tile>) -> StyleFile {
+        let mut colors = vec![0; 256];
+        colors[2] = u32::from_ne_bytes([0, 0, 255, 0]);
+
+        StyleFile {
+            header: StyleFileHeader {
+                file_type: "GBST".to_string(),
+                version: 700,
+            },
+            tiles,
+            palette_index: PaletteIndex {
+                physical_index: vec![0],
+            },
+            palette_base: PaletteBase {
+                tile: 1,
+                sprite: 0,
+                car_remap: 0,
+                ped_remap: 0,
+                code_obj_remap: 0,
+                map_opj_remap: 0,
+                user_remap: 0,
+                font_remap: 0,
+            },
+            physical_palette: vec![PhysicalPalette { colors }],
+            sprite_graphics: Vec::new(),
+            sprite_index: Vec::new(),
+            sprite_bases: SpriteBases::default(),
+            car_infos: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn only_palette_index_0_is_transparent() {
+        let style = test_style(vec![0, 1, 2]);
+
+        let bgra = style.tile_bgra(0).unwrap();
+        assert_eq!(bgra[0..4], [0, 0, 0, 0]);
+        // the palette entry is black with an alpha byte of 0 like index 0
+        assert_eq!(bgra[4..8], [0, 0, 0, 255]);
+        assert_eq!(bgra[8..12], [0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn unresolved_tiles_are_none() {
+        assert!(test_style(vec![0]).tile_bgra(1).is_none());
+
+        let mut style = test_style(vec![0]);
+        style.palette_index.physical_index[0] = 1;
+        assert!(style.tile_bgra(0).is_none());
     }
 }
@@ -0,0 +1,300 @@
+use std::io::{self, Read, Seek};
+
+use byteorder::{NativeEndian, ReadBytesExt};
+
+use super::PAGE_SIZE;
+
+/// Entry of the sprite index, the sprite is stored in the sprite pages at
+/// `offset` with rows a page wide.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpriteEntry {
+    pub offset: u32,
+    pub width: u8,
+    pub height: u8,
+}
+
+/// Number of sprites of each kind, stored one kind after the other.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SpriteBases {
+    pub car: u16,
+    pub ped: u16,
+    pub code_obj: u16,
+    pub map_obj: u16,
+    pub user: u16,
+    pub font: u16,
+}
+
+impl SpriteBases {
+    /// Sprite id of the first ped sprite.
+    pub fn ped_start(&self) -> usize {
+        usize::from(self.car)
+    }
+}
+
+/// Palette a sprite is drawn with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Remap {
+    /// The own palette of the sprite.
+    None,
+    /// Car remap palette, one of the remaps of its car info.
+    Car(usize),
+    /// Ped remap palette, for the clothes of peds.
+    Ped(usize),
+}
+
+/// Sprite resolved into BGRA pixels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpriteImage {
+    pub width: usize,
+    pub height: usize,
+    pub bgra: Vec<u8>,
+}
+
+/// Model of a car, the sizes are in pixels, 64 per block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CarInfo {
+    pub model: u8,
+    /// Car sprite, relative to the first car sprite.
+    pub sprite: u8,
+    pub width: u8,
+    pub height: u8,
+    pub remaps: Vec<u8>,
+    pub passengers: u8,
+    pub wreck: u8,
+    pub rating: u8,
+    pub front_wheel_offset: i8,
+    pub rear_wheel_offset: i8,
+    pub front_window_offset: i8,
+    pub rear_window_offset: i8,
+    pub info_flags: u8,
+    pub info_flags_2: u8,
+    /// Positions of the doors relative to the centre of the car.
+    pub doors: Vec<(i8, i8)>,
+}
+
+/// Pixels of the sprite `entry` in the sprite pages `graphics`, `None` if it
+/// is not inside them.
+pub(super) fn sprite_pixels(graphics: &[u8], entry: &SpriteEntry) -> Option<Vec<u8>> {
+    let (width, height) = (usize::from(entry.width), usize::from(entry.height));
+    let mut pixels = Vec::with_capacity(width * height);
+
+    for y in 0..height {
+        let start = entry.offset as usize + y * PAGE_SIZE;
+        pixels.extend_from_slice(graphics.get(start..start + width)?);
+    }
+
+    Some(pixels)
+}
+
+pub(super) fn load_sprite_graphics<T: Read + Seek>(
+    size: u32,
+    buf_reader: &mut T,
+) -> io::Result<Vec<u8>> {
+    crate::chunk::read_data(buf_reader, size)
+}
+
+pub(super) fn load_sprite_index<T: Read + Seek>(
+    size: u32,
+    buf_reader: &mut T,
+) -> io::Result<Vec<SpriteEntry>> {
+    const ENTRY_SIZE: u32 = 8;
+
+    (0..size / ENTRY_SIZE)
+        .map(|_| {
+            let entry = SpriteEntry {
+                offset: buf_reader.read_u32::<NativeEndian>()?,
+                width: buf_reader.read_u8()?,
+                height: buf_reader.read_u8()?,
+            };
+            // padding
+            buf_reader.read_u16::<NativeEndian>()?;
+            Ok(entry)
+        })
+        .collect()
+}
+
+pub(super) fn load_sprite_bases<T: Read + Seek>(
+    size: u32,
+    buf_reader: &mut T,
+) -> io::Result<SpriteBases> {
+    const SPRITE_BASES_SIZE: u32 = 12;
+    if size != SPRITE_BASES_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("SPRB chunk has {size} bytes instead of {SPRITE_BASES_SIZE}"),
+        ));
+    }
+
+    Ok(SpriteBases {
+        car: buf_reader.read_u16::<NativeEndian>()?,
+        ped: buf_reader.read_u16::<NativeEndian>()?,
+        code_obj: buf_reader.read_u16::<NativeEndian>()?,
+        map_obj: buf_reader.read_u16::<NativeEndian>()?,
+        user: buf_reader.read_u16::<NativeEndian>()?,
+        font: buf_reader.read_u16::<NativeEndian>()?,
+    })
+}
+
+pub(super) fn load_car_infos<T: Read + Seek>(
+    size: u32,
+    buf_reader: &mut T,
+) -> io::Result<Vec<CarInfo>> {
+    let data = crate::chunk::read_data(buf_reader, size)?;
+
+    let mut cars = Vec::new();
+    let mut reader = data.as_slice();
+    // NOTE: records have a variable length, a truncated last one is dropped
+    while !reader.is_empty() {
+        match read_car_info(&mut reader) {
+            Ok(car) => cars.push(car),
+            Err(_) => break,
+        }
+    }
+
+    Ok(cars)
+}
+
+fn read_car_info(reader: &mut &[u8]) -> io::Result<CarInfo> {
+    let model = reader.read_u8()?;
+    let sprite = reader.read_u8()?;
+    let width = reader.read_u8()?;
+    let height = reader.read_u8()?;
+    let remaps_count = reader.read_u8()?;
+    let passengers = reader.read_u8()?;
+    let wreck = reader.read_u8()?;
+    let rating = reader.read_u8()?;
+    let front_wheel_offset = reader.read_i8()?;
+    let rear_wheel_offset = reader.read_i8()?;
+    let front_window_offset = reader.read_i8()?;
+    let rear_window_offset = reader.read_i8()?;
+    let info_flags = reader.read_u8()?;
+    let info_flags_2 = reader.read_u8()?;
+
+    let mut remaps = vec![0; usize::from(remaps_count)];
+    reader.read_exact(&mut remaps)?;
+
+    let doors_count = reader.read_u8()?;
+    let doors = (0..doors_count)
+        .map(|_| Ok((reader.read_i8()?, reader.read_i8()?)))
+        .collect::<io::Result<_>>()?;
+
+    Ok(CarInfo {
+        model,
+        sprite,
+        width,
+        height,
+        remaps,
+        passengers,
+        wreck,
+        rating,
+        front_wheel_offset,
+        rear_wheel_offset,
+        front_window_offset,
+        rear_window_offset,
+        info_flags,
+        info_flags_2,
+        doors,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// Car info record with `remaps` and `doors`.
+    fn car_info_bytes(model: u8, remaps: &[u8], doors: &[(i8, i8)]) -> Vec<u8> {
+        let mut bytes = vec![model, 3, 40, 64, remaps.len() as u8, 1, 2, 3];
+        bytes.extend([10, -10, 5, -5].map(|offset: i8| offset as u8));
+        bytes.extend([0x81, 0x02]);
+        bytes.extend(remaps);
+        bytes.push(doors.len() as u8);
+        bytes.extend(doors.iter().flat_map(|&(x, y)| [x as u8, y as u8]));
+        bytes
+    }
+
+    fn load(bytes: &[u8]) -> Vec<CarInfo> {
+        load_car_infos(bytes.len() as u32, &mut Cursor::new(bytes)).unwrap()
+    }
+
+    #[test]
+    fn car_infos_have_remaps_and_doors() {
+        let cars = load(&car_info_bytes(7, &[4, 5, 6], &[(-12, 20), (12, -20)]));
+
+        assert_eq!(
+            cars,
+            [CarInfo {
+                model: 7,
+                sprite: 3,
+                width: 40,
+                height: 64,
+                remaps: vec![4, 5, 6],
+                passengers: 1,
+                wreck: 2,
+                rating: 3,
+                front_wheel_offset: 10,
+                rear_wheel_offset: -10,
+                front_window_offset: 5,
+                rear_window_offset: -5,
+                info_flags: 0x81,
+                info_flags_2: 0x02,
+                doors: vec![(-12, 20), (12, -20)],
+            }]
+        );
+    }
+
+    #[test]
+    fn truncated_last_car_info_is_dropped() {
+        let mut bytes = car_info_bytes(1, &[], &[]);
+        let second = car_info_bytes(2, &[9], &[(1, 2)]);
+        bytes.extend(&second[..second.len() - 1]);
+
+        let cars = load(&bytes);
+        assert_eq!(cars.len(), 1);
+        assert_eq!(cars[0].model, 1);
+        assert!(cars[0].remaps.is_empty() && cars[0].doors.is_empty());
+    }
+
+    #[test]
+    fn sprites_are_read_row_by_row_from_the_pages() {
+        let mut graphics = vec![0; PAGE_SIZE * 3];
+        graphics[PAGE_SIZE + 5..PAGE_SIZE + 7].copy_from_slice(&[1, 2]);
+        graphics[2 * PAGE_SIZE + 5..2 * PAGE_SIZE + 7].copy_from_slice(&[3, 4]);
+        let entry = SpriteEntry {
+            offset: PAGE_SIZE as u32 + 5,
+            width: 2,
+            height: 2,
+        };
+
+        assert_eq!(sprite_pixels(&graphics, &entry), Some(vec![1, 2, 3, 4]));
+    }
+
+    #[test]
+    fn sprites_crossing_the_end_of_the_graphics_are_none() {
+        let graphics = vec![0; PAGE_SIZE * 2];
+        let entry = |offset: usize, width: u8, height: u8| SpriteEntry {
+            offset: offset as u32,
+            width,
+            height,
+        };
+
+        // the last row fits exactly
+        assert!(sprite_pixels(&graphics, &entry(PAGE_SIZE - 4, 4, 2)).is_some());
+        // the second row is past the end
+        assert_eq!(sprite_pixels(&graphics, &entry(PAGE_SIZE, 4, 2)), None);
+        // the first row is cut off
+        assert_eq!(
+            sprite_pixels(&graphics, &entry(2 * PAGE_SIZE - 2, 4, 1)),
+            None
+        );
+    }
+
+    #[test]
+    fn sprite_bases_of_the_wrong_size_are_errors() {
+        let mut reader = Cursor::new(vec![1; 12]);
+
+        let err = load_sprite_bases(10, &mut reader).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -1,6 +1,6 @@
 // FIXME duplicate code
 const PAGE_SIZE: usize = 256;
-const IMAGE_SIZE: usize = 64;
+pub const IMAGE_SIZE: usize = 64;
 
 #[derive(Debug)]
 pub struct Tile(pub Vec<u8>);
@@ -23,4 +23,3 @@ impl Tile {
         Tile(tile)
     }
 }
-
@@ -0,0 +1,477 @@
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use super::{tile::IMAGE_SIZE, PhysicalPalette, StyleFile, PAGE_SIZE, TILES_PER_PAGE};
+
+const TILES_PER_ROW: usize = PAGE_SIZE / IMAGE_SIZE;
+const PALETTE_SIZE: usize = 256;
+/// Palette index 0 is rendered transparent, see [`StyleFile::tile_bgra`].
+const TRANSPARENT_INDEX: u8 = 0;
+
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub enum TileSheetError {
+    /// An [IO](std::io) Error
+    #[error("Could not access file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not encode PNG: {0}")]
+    Encoding(#[from] png::EncodingError),
+    #[error("Could not decode PNG: {0}")]
+    Decoding(#[from] png::DecodingError),
+    #[error("Tile {0} can not be resolved through its palette")]
+    UnresolvedTile(usize),
+    #[error("Tile {0} does not exist in the style")]
+    UnknownTile(usize),
+    #[error("{path} is {width}x{height}, but tiles have to be {IMAGE_SIZE}x{IMAGE_SIZE}")]
+    InvalidSize {
+        path: PathBuf,
+        width: u32,
+        height: u32,
+    },
+}
+
+/// How imported tiles are mapped to palette colours.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaletteMode {
+    /// Quantise to the physical palette the tile already uses.
+    Existing,
+    /// Generate a physical palette from the imported colours and point the
+    /// palette index of the tile to it.
+    New,
+}
+
+pub fn tile_file_name(id: usize) -> String {
+    format!("tile_{id:04}.png")
+}
+
+pub fn page_file_name(page: usize) -> String {
+    format!("page_{page:03}.png")
+}
+
+/// Writes every tile of the style as a 64x64 RGBA PNG into `dir`, named by
+/// [`tile_file_name`]. Returns the paths of the written files.
+pub fn export_tiles(
+    style: &StyleFile,
+    dir: impl AsRef<Path>,
+) -> Result<Vec<PathBuf>, TileSheetError> {
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir)?;
+
+    let mut written = Vec::with_capacity(style.tiles.len());
+    for id in 0..style.tiles.len() {
+        let mut pixels = style
+            .tile_bgra(id)
+            .ok_or(TileSheetError::UnresolvedTile(id))?;
+        bgra_to_rgba(&mut pixels);
+
+        let path = dir.join(tile_file_name(id));
+        write_png(&path, IMAGE_SIZE, IMAGE_SIZE, &pixels)?;
+        written.push(path);
+    }
+
+    Ok(written)
+}
+
+/// Writes every 256x256 page of the style as an RGBA PNG into `dir`, named by
+/// [`page_file_name`]. Each tile is resolved through its own palette, so the
+/// pages look like in game.
+pub fn export_pages(
+    style: &StyleFile,
+    dir: impl AsRef<Path>,
+) -> Result<Vec<PathBuf>, TileSheetError> {
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir)?;
+
+    let pages_count = style.tiles.len().div_ceil(TILES_PER_PAGE);
+    let mut written = Vec::with_capacity(pages_count);
+
+    for page in 0..pages_count {
+        let mut pixels = vec![0; PAGE_SIZE * PAGE_SIZE * 4];
+
+        let first = page * TILES_PER_PAGE;
+        let last = (first + TILES_PER_PAGE).min(style.tiles.len());
+        for id in first..last {
+            let tile = style
+                .tile_bgra(id)
+                .ok_or(TileSheetError::UnresolvedTile(id))?;
+
+            let x_start = ((id - first) % TILES_PER_ROW) * IMAGE_SIZE;
+            let y_start = ((id - first) / TILES_PER_ROW) * IMAGE_SIZE;
+            for (y, row) in tile.chunks_exact(IMAGE_SIZE * 4).enumerate() {
+                let start = ((y_start + y) * PAGE_SIZE + x_start) * 4;
+                pixels[start..start + row.len()].copy_from_slice(row);
+            }
+        }
+        bgra_to_rgba(&mut pixels);
+
+        let path = dir.join(page_file_name(page));
+        write_png(&path, PAGE_SIZE, PAGE_SIZE, &pixels)?;
+        written.push(path);
+    }
+
+    Ok(written)
+}
+
+/// Imports every file named like [`tile_file_name`] from `dir` into the
+/// style. Returns the ids of the imported tiles.
+pub fn import_tiles(
+    style: &mut StyleFile,
+    dir: impl AsRef<Path>,
+    mode: PaletteMode,
+) -> Result<Vec<usize>, TileSheetError> {
+    let mut tiles = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let id = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix("tile_")?.strip_suffix(".png"))
+            .and_then(|id| id.parse::<usize>().ok());
+
+        if let Some(id) = id {
+            tiles.push((id, path));
+        }
+    }
+    tiles.sort();
+
+    for (id, path) in &tiles {
+        let rgba = read_png(path)?;
+        import_tile(style, *id, &rgba, mode)?;
+    }
+
+    Ok(tiles.into_iter().map(|(id, _)| id).collect())
+}
+
+/// Replaces tile `id` by 64x64 RGBA pixels. Pixels with an alpha below 50%
+/// become transparent, all others are mapped to the nearest palette colour.
+pub fn import_tile(
+    style: &mut StyleFile,
+    id: usize,
+    rgba: &[u8],
+    mode: PaletteMode,
+) -> Result<(), TileSheetError> {
+    if id >= style.tiles.len() || id >= style.palette_index.physical_index.len() {
+        return Err(TileSheetError::UnknownTile(id));
+    }
+
+    let palette_id = match mode {
+        PaletteMode::Existing => style.palette_index.physical_index[id] as usize,
+        PaletteMode::New => {
+            let palette = generate_palette(rgba);
+            let palette_id = match style
+                .physical_palette
+                .iter()
+                .position(|p| p.colors == palette.colors)
+            {
+                Some(palette_id) => palette_id,
+                None => {
+                    style.physical_palette.push(palette);
+                    style.physical_palette.len() - 1
+                }
+            };
+            style.palette_index.physical_index[id] = palette_id as u16;
+
+            palette_id
+        }
+    };
+
+    let palette = style
+        .physical_palette
+        .get(palette_id)
+        .ok_or(TileSheetError::UnresolvedTile(id))?;
+
+    style.tiles[id].0 = rgba
+        .chunks_exact(4)
+        .map(|pixel| {
+            if is_transparent(pixel) {
+                TRANSPARENT_INDEX
+            } else {
+                nearest_color(&palette.colors, palette_color(pixel))
+            }
+        })
+        .collect();
+
+    Ok(())
+}
+
+fn read_png(path: &Path) -> Result<Vec<u8>, TileSheetError> {
+    let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+
+    let mut reader = decoder.read_info()?;
+    let mut buffer = vec![0; reader.output_buffer_size().unwrap_or_default()];
+    let info = reader.next_frame(&mut buffer)?;
+
+    if info.width as usize != IMAGE_SIZE || info.height as usize != IMAGE_SIZE {
+        return Err(TileSheetError::InvalidSize {
+            path: path.to_path_buf(),
+            width: info.width,
+            height: info.height,
+        });
+    }
+
+    let pixels = &buffer[..info.buffer_size()];
+    let rgba = match info.color_type {
+        png::ColorType::Rgba => pixels.to_vec(),
+        png::ColorType::Rgb => pixels
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => pixels
+            .chunks_exact(2)
+            .flat_map(|p| [p[0], p[0], p[0], p[1]])
+            .collect(),
+        png::ColorType::Grayscale | png::ColorType::Indexed => {
+            pixels.iter().flat_map(|p| [*p, *p, *p, 255]).collect()
+        }
+    };
+
+    Ok(rgba)
+}
+
+fn is_transparent(rgba: &[u8]) -> bool {
+    rgba[3] < 128
+}
+
+/// Physical palettes store colours as BGRA, the alpha byte is unused by the
+/// game.
+fn palette_color(rgba: &[u8]) -> u32 {
+    u32::from_ne_bytes([rgba[2], rgba[1], rgba[0], 0])
+}
+
+fn color_distance(a: u32, b: u32) -> u32 {
+    let a = a.to_ne_bytes();
+    let b = b.to_ne_bytes();
+
+    (0..3)
+        .map(|i| (i32::from(a[i]) - i32::from(b[i])).pow(2) as u32)
+        .sum()
+}
+
+fn nearest_color(colors: &[u32], color: u32) -> u8 {
+    colors
+        .iter()
+        .enumerate()
+        .take(PALETTE_SIZE)
+        .filter(|(index, _)| *index != usize::from(TRANSPARENT_INDEX))
+        .min_by_key(|(_, c)| color_distance(**c, color))
+        .map(|(index, _)| index as u8)
+        .unwrap_or(TRANSPARENT_INDEX)
+}
+
+/// Builds a palette with the transparent colour at index 0 followed by the
+/// opaque colours of the tile, reduced by median cut if there are more than
+/// 255 of them.
+fn generate_palette(rgba: &[u8]) -> PhysicalPalette {
+    let pixels: Vec<u32> = rgba
+        .chunks_exact(4)
+        .filter(|pixel| !is_transparent(pixel))
+        .map(palette_color)
+        .collect();
+
+    let mut unique = pixels.clone();
+    unique.sort_unstable();
+    unique.dedup();
+
+    let opaque = if unique.len() < PALETTE_SIZE {
+        unique
+    } else {
+        median_cut(pixels, PALETTE_SIZE - 1)
+    };
+
+    let mut colors = vec![0; PALETTE_SIZE];
+    colors[1..=opaque.len()].copy_from_slice(&opaque);
+
+    PhysicalPalette { colors }
+}
+
+fn median_cut(pixels: Vec<u32>, max_colors: usize) -> Vec<u32> {
+    let channel = |color: u32, i: usize| color.to_ne_bytes()[i];
+    let range = |pixels: &[u32], i: usize| {
+        let min = pixels.iter().map(|c| channel(*c, i)).min().unwrap_or(0);
+        let max = pixels.iter().map(|c| channel(*c, i)).max().unwrap_or(0);
+        max - min
+    };
+    let widest_channel = |pixels: &[u32]| {
+        (0..3)
+            .map(|i| (i, range(pixels, i)))
+            .max_by_key(|(_, range)| *range)
+            .unwrap_or((0, 0))
+    };
+
+    let mut boxes = vec![pixels];
+    while boxes.len() < max_colors {
+        let Some((index, (channel_index, _))) = boxes
+            .iter()
+            .map(|b| widest_channel(b))
+            .enumerate()
+            .filter(|(_, (_, range))| *range > 0)
+            .max_by_key(|(_, (_, range))| *range)
+        else {
+            break;
+        };
+
+        let mut pixels = boxes.swap_remove(index);
+        pixels.sort_unstable_by_key(|c| channel(*c, channel_index));
+        let upper = pixels.split_off(pixels.len() / 2);
+        boxes.push(pixels);
+        boxes.push(upper);
+    }
+
+    boxes
+        .iter()
+        .map(|pixels| {
+            let mut sum = [0u32; 3];
+            for color in pixels {
+                for (i, sum) in sum.iter_mut().enumerate() {
+                    *sum += u32::from(channel(*color, i));
+                }
+            }
+            let len = pixels.len() as u32;
+            let [b, g, r] = sum.map(|s| (s / len) as u8);
+
+            palette_color(&[r, g, b, 255])
+        })
+        .collect()
+}
+
+pub fn bgra_to_rgba(pixels: &mut [u8]) {
+    for pixel in pixels.chunks_exact_mut(4) {
+        pixel.swap(0, 2);
+    }
+}
+
+fn write_png(path: &Path, width: usize, height: usize, rgba: &[u8]) -> Result<(), TileSheetError> {
+    let file = BufWriter::new(File::create(path)?);
+    encode_png(file, width, height, rgba)
+}
+
+/// Encodes 8 bit RGBA pixels as PNG.
+pub fn encode_png<W: Write>(
+    writer: W,
+    width: usize,
+    height: usize,
+    rgba: &[u8],
+) -> Result<(), TileSheetError> {
+    let mut encoder = png::Encoder::new(writer, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(rgba)?;
+    writer.finish()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::style::{tests::test_style_with_tiles, tile::Tile};
+
+    fn rgba(pixels: &[[u8; 4]]) -> Vec<u8> {
+        pixels.concat()
+    }
+
+    #[test]
+    fn generated_palettes_keep_index_0_transparent() {
+        let red = [255, 0, 0, 255];
+        let green = [0, 255, 0, 255];
+        let see_through = [0, 0, 255, 100];
+        let palette = generate_palette(&rgba(&[red, green, red, see_through]));
+
+        assert_eq!(palette.colors.len(), PALETTE_SIZE);
+        assert_eq!(palette.colors[0], 0);
+        assert_eq!(palette.colors[1], palette_color(&green));
+        assert_eq!(palette.colors[2], palette_color(&red));
+        assert!(palette.colors[3..].iter().all(|color| *color == 0));
+    }
+
+    #[test]
+    fn palettes_of_many_colours_are_reduced() {
+        let pixels: Vec<[u8; 4]> = (0..IMAGE_SIZE * IMAGE_SIZE)
+            .map(|i| [(i % 64 * 4) as u8, (i / 64 * 4) as u8, 128, 255])
+            .collect();
+        let palette = generate_palette(&rgba(&pixels));
+
+        assert_eq!(palette.colors[0], 0);
+        let mut used: Vec<_> = palette.colors[1..].to_vec();
+        used.sort_unstable();
+        used.dedup();
+        assert!(used.len() > 200, "{} colours", used.len());
+    }
+
+    #[test]
+    fn median_cut_averages_each_cluster() {
+        let dark = [[10, 0, 0, 255], [20, 0, 0, 255], [30, 0, 0, 255]];
+        let bright = [[200, 0, 0, 255], [220, 0, 0, 255], [240, 0, 0, 255]];
+        let pixels = dark
+            .iter()
+            .chain(&bright)
+            .map(|p| palette_color(p))
+            .collect();
+
+        let mut colors = median_cut(pixels, 2);
+        colors.sort_unstable_by_key(|color| color.to_ne_bytes()[2]);
+
+        assert_eq!(
+            colors,
+            [
+                palette_color(&[20, 0, 0, 255]),
+                palette_color(&[220, 0, 0, 255])
+            ]
+        );
+    }
+
+    #[test]
+    fn nearest_colours_skip_the_transparent_index() {
+        let black = palette_color(&[0, 0, 0, 255]);
+        let grey = palette_color(&[40, 40, 40, 255]);
+        let white = palette_color(&[255, 255, 255, 255]);
+        let colors = [black, white, grey];
+
+        // index 0 matches exactly, but would be transparent
+        assert_eq!(nearest_color(&colors, black), 2);
+        assert_eq!(
+            nearest_color(&colors, palette_color(&[250, 250, 250, 255])),
+            1
+        );
+        assert_eq!(nearest_color(&[black], black), TRANSPARENT_INDEX);
+    }
+
+    #[test]
+    fn pixels_below_half_alpha_become_transparent() {
+        let mut style = test_style_with_tiles(vec![Tile(Vec::new())]);
+        let mut pixels = vec![[255, 0, 0, 255]; IMAGE_SIZE * IMAGE_SIZE];
+        pixels[0] = [255, 0, 0, 127];
+        pixels[1] = [255, 0, 0, 128];
+
+        import_tile(&mut style, 0, &rgba(&pixels), PaletteMode::Existing).unwrap();
+
+        // the test palette has red at index 2
+        assert_eq!(style.tiles[0].0[..3], [TRANSPARENT_INDEX, 2, 2]);
+    }
+
+    #[test]
+    fn pngs_of_the_wrong_size_are_rejected() {
+        let dir = std::env::temp_dir().join(format!("arrie-tile-sheet-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(tile_file_name(0));
+        write_png(&path, 2, 3, &[255; 2 * 3 * 4]).unwrap();
+
+        let result = read_png(&path);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(matches!(
+            result,
+            Err(TileSheetError::InvalidSize {
+                width: 2,
+                height: 3,
+                ..
+            })
+        ));
+    }
+}
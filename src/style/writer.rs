@@ -0,0 +1,252 @@
+use std::io::{self, Cursor, Write};
+
+use byteorder::{NativeEndian, WriteBytesExt};
+
+use super::{
+    tile::IMAGE_SIZE, PhysicalPalette, StyleFile, PAGE_SIZE, PALETTES_PER_PAGE, TILES_PER_PAGE,
+};
+use crate::chunk::{self, HEADER_SIZE};
+
+const TILES_PER_ROW: usize = PAGE_SIZE / IMAGE_SIZE;
+
+/// Writes `style` as a STY file. The header and every chunk except TILE,
+/// PPAL and PALX are copied unchanged from `original`, the file `style` was
+/// read from, so chunks arrie does not parse survive the round trip.
+pub fn write_style<W: Write>(style: &StyleFile, original: &[u8], writer: &mut W) -> io::Result<()> {
+    let (_header, chunks) = chunk::read_layout(&mut Cursor::new(original))?;
+
+    writer.write_all(&original[..HEADER_SIZE as usize])?;
+
+    for chunk in chunks {
+        let data = match chunk.tag.as_str() {
+            "TILE" => tiles_chunk(style),
+            "PPAL" => physical_palettes_chunk(&style.physical_palette),
+            "PALX" => palette_index_chunk(&style.palette_index.physical_index),
+            _ => {
+                let start = chunk.offset as usize;
+                let end = (start + chunk.size as usize).min(original.len());
+                original[start..end].to_vec()
+            }
+        };
+
+        writer.write_all(chunk.tag.as_bytes())?;
+        writer.write_u32::<NativeEndian>(data.len() as u32)?;
+        writer.write_all(&data)?;
+    }
+
+    Ok(())
+}
+
+fn tiles_chunk(style: &StyleFile) -> Vec<u8> {
+    let pages_count = style.tiles.len().div_ceil(TILES_PER_PAGE);
+    let mut data = vec![0; pages_count * PAGE_SIZE * PAGE_SIZE];
+
+    for (id, tile) in style.tiles.iter().enumerate() {
+        let page = &mut data[(id / TILES_PER_PAGE) * PAGE_SIZE * PAGE_SIZE..];
+        let x_start = (id % TILES_PER_ROW) * IMAGE_SIZE;
+        let y_start = ((id % TILES_PER_PAGE) / TILES_PER_ROW) * IMAGE_SIZE;
+
+        for (y, row) in tile.0.chunks_exact(IMAGE_SIZE).enumerate() {
+            let start = (y_start + y) * PAGE_SIZE + x_start;
+            page[start..start + IMAGE_SIZE].copy_from_slice(row);
+        }
+    }
+
+    data
+}
+
+fn physical_palettes_chunk(palettes: &[PhysicalPalette]) -> Vec<u8> {
+    let pages_count = palettes.len().div_ceil(PALETTES_PER_PAGE);
+    let mut data = vec![0; pages_count * PAGE_SIZE * PAGE_SIZE];
+
+    for (id, palette) in palettes.iter().enumerate() {
+        let page = &mut data[(id / PALETTES_PER_PAGE) * PAGE_SIZE * PAGE_SIZE..];
+        let x_start = (id % PALETTES_PER_PAGE) * 4;
+
+        for (y, color) in palette.colors.iter().enumerate().take(PAGE_SIZE) {
+            let index = (y * PAGE_SIZE) + x_start;
+            page[index..index + 4].copy_from_slice(&color.to_ne_bytes());
+        }
+    }
+
+    data
+}
+
+fn palette_index_chunk(physical_index: &[u16]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(physical_index.len() * 2);
+    for index in physical_index {
+        data.write_u16::<NativeEndian>(*index).unwrap();
+    }
+
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        chunk::ChunkHeader,
+        tile_sheet::{import_tile, PaletteMode},
+    };
+
+    fn chunk(tag: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut bytes = tag.to_vec();
+        bytes.extend_from_slice(&(data.len() as u32).to_ne_bytes());
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    /// A minimal style with two tile pages, one palette page and a trailing
+    /// chunk arrie does not parse.
+    fn test_style_bytes() -> Vec<u8> {
+        let mut ppal = vec![0; PAGE_SIZE * PAGE_SIZE];
+        for palette in 0..PALETTES_PER_PAGE {
+            for color in 1..256 {
+                let value = (color as u32) | ((palette as u32) << 8) | ((255 - color as u32) << 16);
+                let index = color * PAGE_SIZE + palette * 4;
+                ppal[index..index + 4].copy_from_slice(&value.to_ne_bytes());
+            }
+        }
+
+        let palx: Vec<u8> = (0..2 * TILES_PER_PAGE as u16)
+            .flat_map(|id| (id % PALETTES_PER_PAGE as u16).to_ne_bytes())
+            .collect();
+
+        let mut palb = vec![0; 16];
+        palb[0..2].copy_from_slice(&(2 * TILES_PER_PAGE as u16).to_ne_bytes());
+
+        let tiles: Vec<u8> = (0..2 * PAGE_SIZE * PAGE_SIZE)
+            .map(|i| (i * 7 % 251) as u8)
+            .collect();
+
+        let mut bytes = b"GBST".to_vec();
+        bytes.extend_from_slice(&700u16.to_ne_bytes());
+        bytes.extend(chunk(b"PALX", &palx));
+        bytes.extend(chunk(b"PPAL", &ppal));
+        bytes.extend(chunk(b"PALB", &palb));
+        bytes.extend(chunk(b"TILE", &tiles));
+        bytes.extend(chunk(b"SPEC", b"unparsed"));
+        bytes
+    }
+
+    fn layout(bytes: &[u8]) -> Vec<ChunkHeader> {
+        chunk::read_layout(&mut Cursor::new(bytes)).unwrap().1
+    }
+
+    #[test]
+    fn unchanged_style_round_trips_byte_for_byte() {
+        let original = test_style_bytes();
+        let style = StyleFile::from_bytes(original.clone()).unwrap();
+
+        let mut written = Vec::new();
+        write_style(&style, &original, &mut written).unwrap();
+
+        assert_eq!(written, original);
+    }
+
+    #[test]
+    fn modified_chunks_are_read_back() {
+        let original = test_style_bytes();
+        let mut style = StyleFile::from_bytes(original.clone()).unwrap();
+
+        style.tiles[17].0 = (0..IMAGE_SIZE * IMAGE_SIZE).map(|i| i as u8).collect();
+        style.physical_palette.push(PhysicalPalette {
+            colors: (0..256).map(|c| c * 3).collect(),
+        });
+        style.palette_index.physical_index[17] = PALETTES_PER_PAGE as u16;
+
+        let mut written = Vec::new();
+        write_style(&style, &original, &mut written).unwrap();
+        let read = StyleFile::from_bytes(written.clone()).unwrap();
+
+        assert_eq!(read.tiles.len(), style.tiles.len());
+        for (read, expected) in read.tiles.iter().zip(&style.tiles) {
+            assert_eq!(read.0, expected.0);
+        }
+
+        // palettes are stored in pages of 64
+        assert_eq!(read.physical_palette.len(), 2 * PALETTES_PER_PAGE);
+        for (read, expected) in read.physical_palette.iter().zip(&style.physical_palette) {
+            assert_eq!(read.colors, expected.colors);
+        }
+
+        assert_eq!(
+            read.palette_index.physical_index,
+            style.palette_index.physical_index
+        );
+        assert_eq!(read.tile_bgra(17), style.tile_bgra(17));
+    }
+
+    #[test]
+    fn truncated_styles_are_errors() {
+        let original = test_style_bytes();
+        let tile_chunk = layout(&original)
+            .into_iter()
+            .find(|chunk| chunk.tag == "TILE")
+            .unwrap();
+
+        for len in [3, 12, tile_chunk.offset as usize + 100] {
+            assert!(
+                StyleFile::from_bytes(original[..len].to_vec()).is_err(),
+                "{len} bytes"
+            );
+        }
+    }
+
+    #[test]
+    fn other_chunks_are_copied_through() {
+        let original = test_style_bytes();
+        let style = StyleFile::from_bytes(original.clone()).unwrap();
+
+        let mut written = Vec::new();
+        write_style(&style, &original, &mut written).unwrap();
+
+        let tags: Vec<_> = layout(&written).into_iter().map(|c| c.tag).collect();
+        assert_eq!(tags, ["PALX", "PPAL", "PALB", "TILE", "SPEC"]);
+
+        let spec = layout(&written).pop().unwrap();
+        let start = spec.offset as usize;
+        assert_eq!(&written[start..start + spec.size as usize], b"unparsed");
+    }
+
+    #[test]
+    fn imported_tiles_survive_writing() {
+        let original = test_style_bytes();
+        let mut style = StyleFile::from_bytes(original.clone()).unwrap();
+
+        // a gradient with opaque black and a transparent corner
+        let mut rgba: Vec<u8> = (0..IMAGE_SIZE * IMAGE_SIZE)
+            .flat_map(|i| [(i % 16 * 16) as u8, 0, (i / 64 % 8 * 32) as u8, 255])
+            .collect();
+        rgba[4..8].copy_from_slice(&[0, 0, 0, 0]);
+
+        import_tile(&mut style, 3, &rgba, PaletteMode::New).unwrap();
+        import_tile(&mut style, 4, &rgba, PaletteMode::Existing).unwrap();
+
+        let mut written = Vec::new();
+        write_style(&style, &original, &mut written).unwrap();
+        let read = StyleFile::from_bytes(written).unwrap();
+
+        let bgra = read.tile_bgra(3).unwrap();
+        let pixel = |i: usize| {
+            [
+                bgra[i * 4 + 2],
+                bgra[i * 4 + 1],
+                bgra[i * 4],
+                bgra[i * 4 + 3],
+            ]
+        };
+        assert_eq!(pixel(0), [0, 0, 0, 255]);
+        assert_eq!(pixel(1), [0, 0, 0, 0]);
+        assert_eq!(pixel(2), [32, 0, 0, 255]);
+        assert_eq!(pixel(64 * 63 + 63), [240, 0, 224, 255]);
+        assert_eq!(
+            read.palette_index.physical_index[3],
+            PALETTES_PER_PAGE as u16
+        );
+
+        assert_eq!(read.tiles[4].0, style.tiles[4].0);
+        assert_eq!(read.palette_index.physical_index[4], 4);
+    }
+}
@@ -0,0 +1,653 @@
+//! Traffic driving along the road arrows of the map. Cars follow the green
+//! arrows from cell to cell, taking the red ones where there are none. They
+//! stop before junctions until these are clear, and before traffic light
+//! zones while the lights are red for them.
+
+use bevy::{
+    color::palettes::css::{LIME, ORANGE_RED, RED},
+    input::common_conditions::input_just_pressed,
+    prelude::*,
+    utils::HashMap,
+};
+use bevy_panorbit_camera::PanOrbitCamera;
+
+use crate::{
+    camera::{CameraMode, CameraTarget},
+    car::{Car, CarModel, Controls, MAX_DELTA},
+    loader::StyleFileAsset,
+    map::{
+        collision::{CollisionMap, MAX_CLIMB, STEP_BODY_SIZE},
+        file::{GroundType, SlopeDirection, UncompressedMap, Zone, ZoneType},
+        ground::{self, Ground},
+        Map, MapFileAsset, Style,
+    },
+    rng::Rng,
+};
+
+pub fn plugin(app: &mut App) {
+    app.init_resource::<ArrowOverlay>()
+        .add_systems(
+            Update,
+            toggle_traffic
+                .run_if(input_just_pressed(TRAFFIC_KEY))
+                .run_if(in_state(CameraMode::Game)),
+        )
+        .add_systems(
+            Update,
+            toggle_arrow_overlay.run_if(input_just_pressed(OVERLAY_KEY)),
+        )
+        .add_systems(Update, drive_traffic)
+        .add_systems(
+            Update,
+            (draw_arrows, draw_traffic_lights).run_if(overlay_enabled),
+        );
+}
+
+const TRAFFIC_KEY: KeyCode = KeyCode::KeyJ;
+const OVERLAY_KEY: KeyCode = KeyCode::KeyI;
+
+/// Cars spawned at once.
+const TRAFFIC_COUNT: usize = 20;
+/// Distance in cells around the camera target cars are spawned in.
+const SPAWN_RADIUS: i32 = 16;
+/// Distance in cells around the camera focus arrows are drawn in.
+const OVERLAY_RADIUS: i32 = 24;
+
+// speeds in blocks per second
+const CRUISE_SPEED: f32 = 5.0;
+const TURN_SPEED: f32 = 2.0;
+/// Deceleration planned for, well below what the brakes manage.
+const STOP_DECELERATION: f32 = 4.0;
+/// Distance to the centre of the cell driven to at which the car heads for
+/// the next one.
+const ARRIVE_DISTANCE: f32 = 0.5;
+const STEERING_GAIN: f32 = 2.5;
+const THROTTLE_GAIN: f32 = 1.0;
+/// Seconds to wait before entering a junction.
+const JUNCTION_WAIT: f32 = 1.0;
+/// A junction is clear when no other car is this close to it.
+const JUNCTION_CLEARANCE: f32 = 1.5;
+/// Gap kept to the car ahead.
+const FOLLOW_GAP: f32 = 0.6;
+/// Cars further to the side are not ahead.
+const LANE_WIDTH: f32 = 0.6;
+
+// traffic lights let the traffic along X and along Y through in turn, with
+// all lights red in between
+const GREEN_TIME: f32 = 8.0;
+const CLEAR_TIME: f32 = 2.0;
+
+/// Car following the road arrows.
+#[derive(Component, Debug)]
+pub struct TrafficCar {
+    /// Cell driven to and its floor.
+    target: IVec2,
+    ground: Ground,
+    /// Direction into the target cell.
+    direction: IVec2,
+    /// Direction from the target cell on and its floor, `None` at the end of
+    /// the road.
+    next: Option<(IVec2, Ground)>,
+    /// Seconds waited in front of a junction.
+    waited: f32,
+    rng: Rng,
+}
+
+/// Whether road arrows and traffic lights are drawn.
+#[derive(Resource, Debug, Default)]
+struct ArrowOverlay {
+    enabled: bool,
+}
+
+fn overlay_enabled(overlay: Res<ArrowOverlay>) -> bool {
+    overlay.enabled
+}
+
+fn toggle_arrow_overlay(mut overlay: ResMut<ArrowOverlay>) {
+    overlay.enabled = !overlay.enabled;
+}
+
+/// Step to the neighbouring world cell an arrow points to, up is north.
+fn arrow_step(direction: SlopeDirection) -> IVec2 {
+    match direction {
+        SlopeDirection::Up => IVec2::Y,
+        SlopeDirection::Down => IVec2::NEG_Y,
+        SlopeDirection::Left => IVec2::NEG_X,
+        SlopeDirection::Right => IVec2::X,
+    }
+}
+
+/// Road arrows of the block below `ground` at `cell`, the green ones first.
+fn arrows(map: &UncompressedMap, cell: IVec2, ground: &Ground) -> (Vec<IVec2>, Vec<IVec2>) {
+    let arrows = ground::block_at(map, cell, ground.level)
+        .map(|block| block.arrows)
+        .unwrap_or_default();
+
+    (
+        arrows.green().map(arrow_step).collect(),
+        arrows.red().map(arrow_step).collect(),
+    )
+}
+
+/// Whether traffic can go more than one way from `cell`.
+fn is_junction(map: &UncompressedMap, cell: IVec2, ground: &Ground) -> bool {
+    arrows(map, cell, ground).0.len() > 1
+}
+
+/// Floor of the road cell `to` a car on `ground` at `from` drives onto.
+fn drive_to(
+    map: &UncompressedMap,
+    collision: &CollisionMap,
+    from: IVec2,
+    ground: &Ground,
+    to: IVec2,
+) -> Option<Ground> {
+    collision
+        .step(map, from, ground, to, STEP_BODY_SIZE, MAX_CLIMB)
+        .filter(|next| next.ground_type == GroundType::Road)
+}
+
+/// Direction a car on `ground` at `cell` drives on to, coming from
+/// `direction`, and the floor there. Cars never turn around, they follow
+/// the green arrows, the red ones if there are none and the road straight
+/// ahead if it has no arrows.
+fn next_cell(
+    map: &UncompressedMap,
+    collision: &CollisionMap,
+    cell: IVec2,
+    ground: &Ground,
+    direction: IVec2,
+    rng: &mut Rng,
+) -> Option<(IVec2, Ground)> {
+    let drivable = |directions: Vec<IVec2>| -> Vec<(IVec2, Ground)> {
+        directions
+            .into_iter()
+            .filter(|to| *to != -direction)
+            .filter_map(|to| Some((to, drive_to(map, collision, cell, ground, cell + to)?)))
+            .collect()
+    };
+
+    let (green, red) = arrows(map, cell, ground);
+    let no_arrows = green.is_empty() && red.is_empty();
+    let mut ways = drivable(green);
+    if ways.is_empty() {
+        ways = drivable(red);
+    }
+    if ways.is_empty() && no_arrows {
+        ways = drivable(vec![direction]);
+    }
+
+    rng.pick(&ways)
+}
+
+/// Whether the lights let traffic moving along `direction` through, after
+/// `elapsed` seconds.
+fn traffic_light_green(elapsed: f32, direction: IVec2) -> bool {
+    let phase = GREEN_TIME + CLEAR_TIME;
+    let time = elapsed.rem_euclid(2.0 * phase);
+    let along_x = time < phase;
+
+    time % phase < GREEN_TIME && along_x == (direction.x != 0)
+}
+
+fn in_traffic_light(zones: &[Zone], cell: IVec2) -> bool {
+    zones
+        .iter()
+        .any(|zone| zone.zone_type == ZoneType::TrafficLight && zone.contains(cell.x, cell.y))
+}
+
+/// Other car as seen by a traffic car.
+struct Obstacle {
+    entity: Entity,
+    position: Vec2,
+    half_length: f32,
+}
+
+impl TrafficCar {
+    fn new(cell: IVec2, ground: Ground, direction: IVec2, rng: Rng) -> Self {
+        Self {
+            target: cell,
+            ground,
+            direction,
+            next: None,
+            waited: 0.0,
+            rng,
+        }
+    }
+
+    /// Plans the way on from the target cell.
+    fn plan(&mut self, map: &UncompressedMap, collision: &CollisionMap) {
+        self.next = next_cell(
+            map,
+            collision,
+            self.target,
+            &self.ground,
+            self.direction,
+            &mut self.rng,
+        );
+    }
+
+    /// Whether the car may drive on from the target cell into the next one.
+    fn may_go_on(
+        &self,
+        map: &UncompressedMap,
+        zones: &[Zone],
+        obstacles: &[Obstacle],
+        entity: Entity,
+        elapsed: f32,
+    ) -> bool {
+        let Some((direction, ground)) = self.next else {
+            return false;
+        };
+        let next = self.target + direction;
+
+        // NOTE: cars already inside a junction drive on
+        if in_traffic_light(zones, next) {
+            return in_traffic_light(zones, self.target) || traffic_light_green(elapsed, direction);
+        }
+        if is_junction(map, next, &ground) && !is_junction(map, self.target, &self.ground) {
+            let clear = obstacles.iter().all(|obstacle| {
+                obstacle.entity == entity
+                    || obstacle.position.distance(next.as_vec2()) > JUNCTION_CLEARANCE
+            });
+            return self.waited >= JUNCTION_WAIT && clear;
+        }
+
+        true
+    }
+
+    /// Controls steering to the target cell at a safe speed, moving on to
+    /// the next cell on arrival if the way is free.
+    #[allow(clippy::too_many_arguments)]
+    fn controls(
+        &mut self,
+        car: &Car,
+        position: Vec2,
+        map: &UncompressedMap,
+        collision: &CollisionMap,
+        zones: &[Zone],
+        obstacles: &[Obstacle],
+        entity: Entity,
+        elapsed: f32,
+        dt: f32,
+    ) -> Controls {
+        let go_on = self.may_go_on(map, zones, obstacles, entity, elapsed);
+        let distance = position.distance(self.target.as_vec2());
+
+        if distance < ARRIVE_DISTANCE {
+            if go_on {
+                if let Some((direction, ground)) = self.next {
+                    self.target += direction;
+                    self.ground = ground;
+                    self.direction = direction;
+                    self.waited = 0.0;
+                    self.plan(map, collision);
+                }
+            } else {
+                self.waited += dt;
+            }
+        }
+        let distance = position.distance(self.target.as_vec2());
+
+        // slow down for turns, stop where the way is not free
+        let forward = Car::forward(car.heading());
+        let to_target = self.target.as_vec2() - position;
+        let angle = forward.angle_to(to_target);
+        let mut speed = if angle.abs() > 0.3 {
+            TURN_SPEED
+        } else {
+            CRUISE_SPEED
+        };
+        let turns = self
+            .next
+            .is_some_and(|(direction, _)| direction != self.direction);
+        if turns {
+            speed = speed.min(TURN_SPEED + stopping_speed(distance));
+        }
+        if !go_on || self.next.is_none() {
+            speed = speed.min(stopping_speed(distance));
+        }
+
+        let right = Vec2::new(forward.y, -forward.x);
+        for obstacle in obstacles
+            .iter()
+            .filter(|obstacle| obstacle.entity != entity)
+        {
+            let offset = obstacle.position - position;
+            let ahead = offset.dot(forward);
+            if ahead > 0.0 && offset.dot(right).abs() < LANE_WIDTH {
+                let gap = ahead - car.half_size().y - obstacle.half_length - FOLLOW_GAP;
+                speed = speed.min(stopping_speed(gap));
+            }
+        }
+
+        Controls {
+            throttle: ((speed - car.speed()) * THROTTLE_GAIN).clamp(-1.0, 1.0),
+            steering: (angle * STEERING_GAIN).clamp(-1.0, 1.0),
+            handbrake: false,
+        }
+    }
+}
+
+/// Highest speed at which the car still stops within `distance`.
+fn stopping_speed(distance: f32) -> f32 {
+    (2.0 * STOP_DECELERATION * distance.max(0.0)).sqrt()
+}
+
+/// Removes the traffic, or spawns cars on the road cells with arrows around
+/// the followed target.
+#[allow(clippy::too_many_arguments)]
+fn toggle_traffic(
+    mut commands: Commands,
+    mut seed: Local<u32>,
+    traffic: Query<Entity, With<TrafficCar>>,
+    targets: Query<&GlobalTransform, With<CameraTarget>>,
+    map: Option<Res<Map>>,
+    map_assets: Res<Assets<MapFileAsset>>,
+    collision: Option<Res<CollisionMap>>,
+    style: Option<Res<Style>>,
+    style_assets: Res<Assets<StyleFileAsset>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    if !traffic.is_empty() {
+        for car in &traffic {
+            commands.entity(car).despawn_recursive();
+        }
+        return;
+    }
+
+    let (Some(uncompressed), Some(collision)) = (
+        map.and_then(|map| map_assets.get(&map.asset))
+            .and_then(|map| map.0.uncompressed_map.as_ref()),
+        collision,
+    ) else {
+        warn!("No map loaded to drive on");
+        return;
+    };
+    let Some(target) = targets.iter().next() else {
+        return;
+    };
+    let style = style
+        .and_then(|style| style_assets.get(&style.asset))
+        .map(|style| &style.0);
+    let car_infos = style.map_or(&[][..], |style| &style.car_infos[..]);
+
+    let center = target.translation().truncate().round().as_ivec2();
+    let mut roads: Vec<_> = (-SPAWN_RADIUS..=SPAWN_RADIUS)
+        .flat_map(|x| (-SPAWN_RADIUS..=SPAWN_RADIUS).map(move |y| center + IVec2::new(x, y)))
+        .filter_map(|cell| {
+            let ground = ground::grounds(uncompressed, cell.as_vec2())
+                .find(|ground| ground.ground_type == GroundType::Road)?;
+            let direction = *arrows(uncompressed, cell, &ground).0.first()?;
+            Some((cell, ground, direction))
+        })
+        .collect();
+    if roads.is_empty() {
+        warn!("No road with arrows around {center}");
+        return;
+    }
+
+    let mut rng = Rng::new(*seed);
+    *seed = seed.wrapping_add(1);
+    let mut models = HashMap::new();
+    for _ in 0..TRAFFIC_COUNT.min(roads.len()) {
+        let (cell, ground, direction) = roads.swap_remove(rng.below(roads.len()));
+        let model = rng.below(car_infos.len());
+        let model = models.entry(model).or_insert_with(|| {
+            CarModel::new(
+                style,
+                car_infos.get(model),
+                &mut meshes,
+                &mut materials,
+                &mut images,
+            )
+        });
+
+        let mut traffic_car = TrafficCar::new(cell, ground, direction, Rng::new(rng.next()));
+        traffic_car.plan(uncompressed, &collision);
+        let heading = Vec2::Y.angle_to(direction.as_vec2());
+        model
+            .spawn(&mut commands, cell.as_vec2().extend(ground.height), heading)
+            .insert(traffic_car);
+    }
+}
+
+fn drive_traffic(
+    time: Res<Time>,
+    map: Option<Res<Map>>,
+    map_assets: Res<Assets<MapFileAsset>>,
+    collision: Option<Res<CollisionMap>>,
+    mut cars: Query<(Entity, &mut Car, &mut Transform, Option<&mut TrafficCar>)>,
+) {
+    let (Some(map_file), Some(collision)) =
+        (map.and_then(|map| map_assets.get(&map.asset)), collision)
+    else {
+        return;
+    };
+    let Some(uncompressed) = map_file.0.uncompressed_map.as_ref() else {
+        return;
+    };
+    let dt = time.delta_secs().min(MAX_DELTA);
+    if dt <= 0.0 {
+        return;
+    }
+
+    // NOTE: the player car is in the way of traffic as well
+    let obstacles: Vec<_> = cars
+        .iter()
+        .map(|(entity, car, transform, _)| Obstacle {
+            entity,
+            position: transform.translation.truncate(),
+            half_length: car.half_size().y,
+        })
+        .collect();
+
+    for (entity, mut car, mut transform, traffic_car) in &mut cars {
+        let Some(mut traffic_car) = traffic_car else {
+            continue;
+        };
+
+        let controls = traffic_car.controls(
+            &car,
+            transform.translation.truncate(),
+            uncompressed,
+            &collision,
+            &map_file.0.zones,
+            &obstacles,
+            entity,
+            time.elapsed_secs(),
+            dt,
+        );
+        car.step(&mut transform, &collision, controls, dt);
+    }
+}
+
+/// Draws the road arrows around the camera focus, green and red, on the
+/// floor of their block.
+fn draw_arrows(
+    map: Option<Res<Map>>,
+    map_assets: Res<Assets<MapFileAsset>>,
+    cameras: Query<&PanOrbitCamera>,
+    mut gizmos: Gizmos,
+) {
+    let Some(uncompressed) = map
+        .and_then(|map| map_assets.get(&map.asset))
+        .and_then(|map| map.0.uncompressed_map.as_ref())
+    else {
+        return;
+    };
+    let Some(camera) = cameras.iter().next() else {
+        return;
+    };
+
+    let center = camera.focus.truncate().round().as_ivec2();
+    for x in -OVERLAY_RADIUS..=OVERLAY_RADIUS {
+        for y in -OVERLAY_RADIUS..=OVERLAY_RADIUS {
+            let cell = center + IVec2::new(x, y);
+            for ground in ground::grounds(uncompressed, cell.as_vec2()) {
+                let (green, red) = arrows(uncompressed, cell, &ground);
+                let base = cell.as_vec2().extend(ground.height + 0.05);
+
+                for direction in green {
+                    let direction = direction.as_vec2().extend(0.0);
+                    gizmos.arrow(base - direction * 0.35, base + direction * 0.35, LIME);
+                }
+                // red arrows a bit higher and shorter, to see both
+                for direction in red {
+                    let direction = direction.as_vec2().extend(0.0);
+                    let base = base + Vec3::Z * 0.05;
+                    gizmos.arrow(base - direction * 0.25, base + direction * 0.25, RED);
+                }
+            }
+        }
+    }
+}
+
+/// Outlines the traffic light zones, the sides where traffic along X and
+/// along Y enters in the colour of their lights.
+fn draw_traffic_lights(
+    time: Res<Time>,
+    map: Option<Res<Map>>,
+    map_assets: Res<Assets<MapFileAsset>>,
+    mut gizmos: Gizmos,
+) {
+    let Some(map_file) = map.and_then(|map| map_assets.get(&map.asset)) else {
+        return;
+    };
+    let Some(uncompressed) = map_file.0.uncompressed_map.as_ref() else {
+        return;
+    };
+
+    let color = |direction: IVec2| {
+        if traffic_light_green(time.elapsed_secs(), direction) {
+            LIME
+        } else {
+            ORANGE_RED
+        }
+    };
+
+    for zone in map_file
+        .0
+        .zones
+        .iter()
+        .filter(|zone| zone.zone_type == ZoneType::TrafficLight)
+    {
+        // NOTE: zones count rows from the top, world Y from the bottom
+        let bottom_row = i32::from(zone.y) + i32::from(zone.height) - 1;
+        let min = Vec2::new(
+            f32::from(zone.x) - 0.5,
+            UncompressedMap::world_y(bottom_row) as f32 - 0.5,
+        );
+        let max = min + Vec2::new(f32::from(zone.width), f32::from(zone.height));
+        let height =
+            ground::ground_below(uncompressed, (min + max) / 2.0, UncompressedMap::Z as f32)
+                .map_or(0.0, |ground| ground.height)
+                + 0.1;
+
+        let corner = |x: f32, y: f32| Vec3::new(x, y, height);
+        gizmos.line(corner(min.x, min.y), corner(min.x, max.y), color(IVec2::X));
+        gizmos.line(corner(max.x, min.y), corner(max.x, max.y), color(IVec2::X));
+        gizmos.line(corner(min.x, min.y), corner(max.x, min.y), color(IVec2::Y));
+        gizmos.line(corner(min.x, max.y), corner(max.x, max.y), color(IVec2::Y));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::map::file::{tests::test_map_with_blocks, Arrows, BlockInfo};
+
+    const RIGHT: u8 = 0b0010;
+    const UP: u8 = 0b0100;
+    const DOWN: u8 = 0b1000;
+
+    /// Map with level 0 road blocks at `(x, y, arrows)`.
+    fn map(roads: &[(i32, i32, u8)]) -> (UncompressedMap, CollisionMap) {
+        let map = test_map_with_blocks(roads.iter().map(|&(x, y, arrows)| {
+            let block = BlockInfo {
+                ground_type: GroundType::Road,
+                arrows: Arrows(arrows),
+                ..default()
+            };
+            (IVec3::new(x, y, 0), block)
+        }));
+
+        let collision = CollisionMap::from(&map);
+        (map, collision)
+    }
+
+    fn next(
+        (map, collision): &(UncompressedMap, CollisionMap),
+        cell: IVec2,
+        direction: IVec2,
+        seed: u32,
+    ) -> Option<IVec2> {
+        let ground = ground::ground_below(map, cell.as_vec2(), 10.0).unwrap();
+        next_cell(
+            map,
+            collision,
+            cell,
+            &ground,
+            direction,
+            &mut Rng::new(seed),
+        )
+        .map(|(direction, _)| direction)
+    }
+
+    #[test]
+    fn cars_follow_green_arrows() {
+        let map = map(&[(100, 100, RIGHT | UP), (101, 100, RIGHT), (100, 101, UP)]);
+
+        let ways: Vec<_> = (0..20)
+            .filter_map(|seed| next(&map, IVec2::new(100, 100), IVec2::X, seed))
+            .collect();
+        assert_eq!(ways.len(), 20);
+        assert!(ways.contains(&IVec2::X));
+        assert!(ways.contains(&IVec2::Y));
+    }
+
+    #[test]
+    fn cars_take_red_arrows_without_green_ones() {
+        let map = map(&[(100, 100, UP << 4), (100, 101, 0), (101, 100, 0)]);
+
+        assert_eq!(
+            next(&map, IVec2::new(100, 100), IVec2::X, 1),
+            Some(IVec2::Y)
+        );
+    }
+
+    #[test]
+    fn cars_do_not_turn_around_or_drive_against_arrows() {
+        let map = map(&[(100, 100, DOWN), (100, 101, UP), (101, 100, 0)]);
+
+        // the only arrow points back
+        assert_eq!(next(&map, IVec2::new(100, 101), IVec2::NEG_Y, 1), None);
+        // arrows pointing off the road lead nowhere
+        assert_eq!(next(&map, IVec2::new(100, 100), IVec2::X, 1), None);
+    }
+
+    #[test]
+    fn roads_without_arrows_are_driven_straight() {
+        let map = map(&[(100, 100, 0), (101, 100, 0), (100, 101, 0)]);
+
+        assert_eq!(
+            next(&map, IVec2::new(100, 100), IVec2::X, 1),
+            Some(IVec2::X)
+        );
+    }
+
+    #[test]
+    fn traffic_lights_alternate() {
+        assert!(traffic_light_green(1.0, IVec2::X));
+        assert!(!traffic_light_green(1.0, IVec2::NEG_Y));
+        // all red while the junction clears
+        assert!(!traffic_light_green(GREEN_TIME + 1.0, IVec2::X));
+        assert!(!traffic_light_green(GREEN_TIME + 1.0, IVec2::Y));
+        let y_phase = GREEN_TIME + CLEAR_TIME + 1.0;
+        assert!(traffic_light_green(y_phase, IVec2::NEG_Y));
+        assert!(!traffic_light_green(y_phase, IVec2::X));
+    }
+}